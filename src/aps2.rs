@@ -0,0 +1,418 @@
+//! Read and write Android packed relocations, the `APS2` format used in the
+//! `.rela.dyn`/`.rel.dyn` sections of Android shared libraries linked with
+//! `--pack-dyn-relocs=android`.
+//!
+//! After the `APS2` magic, the section is a stream of signed LEB128 numbers:
+//! the relocation count and initial offset, then groups of relocations. Each
+//! group starts with its size and flags, and may share an offset delta, an
+//! info word or an addend between all its members, which makes long runs of
+//! similar relocations (such as `R_*_RELATIVE`) very compact. Offsets and
+//! addends are stored as deltas from the previous relocation.
+//!
+//! ```
+//! use leb128::aps2::{self, Relocation};
+//!
+//! let relocs: Vec<Relocation> = (0..100)
+//!     .map(|i| Relocation { offset: 0x1000 + 8 * i, info: 0x403, addend: 0x40 })
+//!     .collect();
+//!
+//! let mut buf = vec![];
+//! aps2::write(&mut buf, &relocs).unwrap();
+//! assert_eq!(buf.len(), 16);
+//! assert_eq!(aps2::read(&mut &buf[..], 1000).unwrap(), relocs);
+//! ```
+
+use super::{read, write};
+use std::fmt;
+use std::io;
+
+/// The magic number at the start of a packed relocation section.
+pub const MAGIC: &[u8; 4] = b"APS2";
+
+/// All relocations in the group share one offset delta.
+pub const RELOCATION_GROUPED_BY_OFFSET_DELTA_FLAG: i64 = 1;
+/// All relocations in the group share one info word.
+pub const RELOCATION_GROUPED_BY_INFO_FLAG: i64 = 2;
+/// All relocations in the group share one addend.
+pub const RELOCATION_GROUPED_BY_ADDEND_FLAG: i64 = 4;
+/// The relocations in the group have addends; otherwise they are zero.
+pub const RELOCATION_GROUP_HAS_ADDEND_FLAG: i64 = 8;
+
+/// A relocation, with the fields of an `Elf64_Rela`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Relocation {
+    /// The address the relocation applies to.
+    pub offset: u64,
+    /// The relocation type and symbol index.
+    pub info: u64,
+    /// The constant addend, which is zero for `REL` sections.
+    pub addend: i64,
+}
+
+/// An error that occurred while reading packed relocations.
+#[derive(Debug)]
+pub enum Error {
+    /// A number could not be read, or there were more relocations than the
+    /// maximum allowed (`read::Error::TooLong`).
+    Read(read::Error),
+    /// The section did not start with `MAGIC`.
+    BadMagic,
+    /// A group had a size of zero or less.
+    InvalidGroupSize(i64),
+}
+
+impl From<read::Error> for Error {
+    fn from(e: read::Error) -> Self {
+        Error::Read(e)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Read(read::Error::IoError(e))
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match *self {
+            Error::Read(ref e) => write!(f, "leb128::aps2::Error: {}", e),
+            Error::BadMagic => write!(f, "leb128::aps2::Error: Missing APS2 magic number"),
+            Error::InvalidGroupSize(size) => {
+                write!(f, "leb128::aps2::Error: Invalid group size {}", size)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match *self {
+            Error::Read(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// Read a packed relocation section, including its magic number, from the
+/// given `std::io::Read`able. Sections with more than `max_count` relocations
+/// are rejected before any are decoded.
+pub fn read<R>(r: &mut R, max_count: usize) -> Result<Vec<Relocation>, Error>
+where
+    R: io::Read,
+{
+    let mut magic = [0; 4];
+    r.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(Error::BadMagic);
+    }
+
+    let count = read::signed(r)? as u64;
+    if count > max_count as u64 {
+        return Err(read::Error::TooLong.into());
+    }
+
+    let mut reloc = Relocation {
+        offset: read::signed(r)? as u64,
+        ..Relocation::default()
+    };
+    let mut relocs = Vec::with_capacity(count as usize);
+
+    while relocs.len() < count as usize {
+        let group_size = read::signed(r)?;
+        if group_size <= 0 {
+            return Err(Error::InvalidGroupSize(group_size));
+        }
+        let flags = read::signed(r)?;
+        let grouped_by_offset_delta = flags & RELOCATION_GROUPED_BY_OFFSET_DELTA_FLAG != 0;
+        let grouped_by_info = flags & RELOCATION_GROUPED_BY_INFO_FLAG != 0;
+        let grouped_by_addend = flags & RELOCATION_GROUPED_BY_ADDEND_FLAG != 0;
+        let has_addend = flags & RELOCATION_GROUP_HAS_ADDEND_FLAG != 0;
+
+        let offset_delta = if grouped_by_offset_delta {
+            read::signed(r)?
+        } else {
+            0
+        };
+        if grouped_by_info {
+            reloc.info = read::signed(r)? as u64;
+        }
+        if has_addend && grouped_by_addend {
+            reloc.addend = reloc.addend.wrapping_add(read::signed(r)?);
+        } else if !has_addend {
+            reloc.addend = 0;
+        }
+
+        // Like the Android linker, stop at the relocation count even in the
+        // middle of a group.
+        let group_size = (group_size as u64).min((count as usize - relocs.len()) as u64);
+        for _ in 0..group_size {
+            let delta = if grouped_by_offset_delta {
+                offset_delta
+            } else {
+                read::signed(r)?
+            };
+            reloc.offset = reloc.offset.wrapping_add(delta as u64);
+            if !grouped_by_info {
+                reloc.info = read::signed(r)? as u64;
+            }
+            if has_addend && !grouped_by_addend {
+                reloc.addend = reloc.addend.wrapping_add(read::signed(r)?);
+            }
+            relocs.push(reloc);
+        }
+    }
+
+    Ok(relocs)
+}
+
+/// The difference between the offset of `relocs[i]` and the previous
+/// relocation's, or the initial offset for the first.
+fn offset_delta(relocs: &[Relocation], initial: u64, i: usize) -> i64 {
+    let prev = if i == 0 {
+        initial
+    } else {
+        relocs[i - 1].offset
+    };
+    relocs[i].offset.wrapping_sub(prev) as i64
+}
+
+/// Whether `relocs[i]` continues a run with a shared offset delta and info.
+fn continues_run(relocs: &[Relocation], initial: u64, i: usize) -> bool {
+    i > 0
+        && relocs[i].info == relocs[i - 1].info
+        && offset_delta(relocs, initial, i) == offset_delta(relocs, initial, i - 1)
+}
+
+/// Encode the relocations `relocs[start..end]` as one group, where `addend`
+/// is the addend of the previous relocation.
+fn write_group(
+    buf: &mut Vec<u8>,
+    relocs: &[Relocation],
+    initial: u64,
+    start: usize,
+    end: usize,
+    addend: &mut i64,
+) -> Result<(), write::Error> {
+    let group = &relocs[start..end];
+    let run = group.len() > 1 && (start + 1..end).all(|i| continues_run(relocs, initial, i));
+
+    let mut flags = 0;
+    if run {
+        flags |= RELOCATION_GROUPED_BY_OFFSET_DELTA_FLAG | RELOCATION_GROUPED_BY_INFO_FLAG;
+    }
+    if group.iter().any(|reloc| reloc.addend != 0) {
+        flags |= RELOCATION_GROUP_HAS_ADDEND_FLAG;
+        if group.iter().all(|reloc| reloc.addend == group[0].addend) {
+            flags |= RELOCATION_GROUPED_BY_ADDEND_FLAG;
+        }
+    }
+
+    write::signed(buf, group.len() as i64)?;
+    write::signed(buf, flags)?;
+    if run {
+        write::signed(buf, offset_delta(relocs, initial, start))?;
+        write::signed(buf, group[0].info as i64)?;
+    }
+    if flags & RELOCATION_GROUPED_BY_ADDEND_FLAG != 0 {
+        write::signed(buf, group[0].addend.wrapping_sub(*addend))?;
+    }
+
+    for i in start..end {
+        if !run {
+            write::signed(buf, offset_delta(relocs, initial, i))?;
+            write::signed(buf, relocs[i].info as i64)?;
+        }
+        if flags & RELOCATION_GROUP_HAS_ADDEND_FLAG != 0
+            && flags & RELOCATION_GROUPED_BY_ADDEND_FLAG == 0
+        {
+            write::signed(buf, relocs[i].addend.wrapping_sub(*addend))?;
+            *addend = relocs[i].addend;
+        }
+    }
+
+    *addend = if flags & RELOCATION_GROUP_HAS_ADDEND_FLAG != 0 {
+        group[group.len() - 1].addend
+    } else {
+        0
+    };
+    Ok(())
+}
+
+/// Write the given relocations as a packed relocation section, including its
+/// magic number, to the given `std::io::Write`able. The whole section is
+/// encoded up front and handed to `w` in one go. Returns the number of bytes
+/// written to `w`, or an error if writing failed.
+///
+/// Relocations are written in the order given. Runs of relocations with the
+/// same info word at a constant stride are packed into a single group.
+pub fn write<W>(w: &mut W, relocs: &[Relocation]) -> Result<usize, write::Error>
+where
+    W: ?Sized + io::Write,
+{
+    let mut buf = MAGIC.to_vec();
+    write::signed(&mut buf, relocs.len() as i64)?;
+
+    // Start one stride before the first relocation, so that it can join a run
+    // with the ones after it.
+    let initial = match relocs {
+        [first, second, ..] => first
+            .offset
+            .wrapping_sub(second.offset.wrapping_sub(first.offset)),
+        _ => 0,
+    };
+    write::signed(&mut buf, initial as i64)?;
+
+    let mut addend = 0;
+    let mut start = 0;
+    while start < relocs.len() {
+        let mut end = start + 1;
+        if end < relocs.len() && continues_run(relocs, initial, end) {
+            while end < relocs.len() && continues_run(relocs, initial, end) {
+                end += 1;
+            }
+        } else {
+            // Gather relocations up to the start of the next run.
+            while end < relocs.len()
+                && !(end + 1 < relocs.len() && continues_run(relocs, initial, end + 1))
+            {
+                end += 1;
+            }
+        }
+        write_group(&mut buf, relocs, initial, start, end, &mut addend)?;
+        start = end;
+    }
+
+    write::write_encoded(w, &buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const R_AARCH64_GLOB_DAT: u64 = 1025;
+    const R_AARCH64_JUMP_SLOT: u64 = 1026;
+    const R_AARCH64_RELATIVE: u64 = 1027;
+
+    fn round_trip(relocs: &[Relocation]) -> usize {
+        let mut buf = vec![];
+        let len = write(&mut buf, relocs).unwrap();
+        assert_eq!(len, buf.len());
+        assert_eq!(read(&mut &buf[..], relocs.len()).unwrap(), relocs);
+        len
+    }
+
+    #[test]
+    fn test_read_hand_encoded() {
+        #[rustfmt::skip]
+        let buf = [
+            b'A', b'P', b'S', b'2', 0x05, 0x80, 0x20,
+            // A group of three relative relocations, eight bytes apart, with
+            // an addend of 0x20.
+            0x03, 0x0f, 0x08, 0x83, 0x08, 0x20,
+            // A group of two relocations with individual fields.
+            0x02, 0x08, 0x10, 0x81, 0x08, 0x60, 0x58, 0x82, 0x08, 0x05,
+        ];
+        let relative = |offset| Relocation {
+            offset,
+            info: R_AARCH64_RELATIVE,
+            addend: 0x20,
+        };
+        assert_eq!(
+            read(&mut &buf[..], 5).unwrap(),
+            vec![
+                relative(0x1008),
+                relative(0x1010),
+                relative(0x1018),
+                Relocation {
+                    offset: 0x1028,
+                    info: R_AARCH64_GLOB_DAT,
+                    addend: 0,
+                },
+                Relocation {
+                    offset: 0x1000,
+                    info: R_AARCH64_JUMP_SLOT,
+                    addend: 5,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_round_trip_synthetic_tables() {
+        assert_eq!(round_trip(&[]), 6);
+
+        // A typical table: a long run of relative relocations with varying
+        // addends, then symbol relocations for a GOT and a PLT.
+        let mut relocs: Vec<Relocation> = (0..1000)
+            .map(|i| Relocation {
+                offset: 0x10_0000 + 8 * i,
+                info: R_AARCH64_RELATIVE,
+                addend: 0x4000 + (i as i64 * 37) % 0x1000,
+            })
+            .collect();
+        relocs.extend((1..50).map(|sym| Relocation {
+            offset: 0x20_0000 + 8 * sym,
+            info: sym << 32 | R_AARCH64_GLOB_DAT,
+            addend: 0,
+        }));
+        relocs.extend((1..50).map(|sym| Relocation {
+            offset: 0x30_0000 + 8 * sym,
+            info: sym << 32 | R_AARCH64_JUMP_SLOT,
+            addend: 0,
+        }));
+        let len = round_trip(&relocs);
+        assert!(len < relocs.len() * 4, "{} bytes", len);
+
+        // Irregular relocations, out of order and with negative addends.
+        round_trip(&[
+            Relocation {
+                offset: 0x30,
+                info: R_AARCH64_RELATIVE,
+                addend: -1,
+            },
+            Relocation {
+                offset: 0x10,
+                info: R_AARCH64_RELATIVE,
+                addend: i64::MIN,
+            },
+            Relocation {
+                offset: 0x18,
+                info: u64::MAX,
+                addend: i64::MAX,
+            },
+            Relocation {
+                offset: u64::MAX,
+                info: 0,
+                addend: 0,
+            },
+            Relocation {
+                offset: 0,
+                info: 0,
+                addend: 0,
+            },
+        ]);
+    }
+
+    #[test]
+    fn test_read_errors() {
+        match read(&mut &b"APS1\x00\x00"[..], 10) {
+            Err(Error::BadMagic) => {}
+            otherwise => panic!("Unexpected: {:?}", otherwise),
+        }
+        match read(&mut &b"APS2\x7f\x00"[..], 10) {
+            Err(Error::Read(read::Error::TooLong)) => {}
+            otherwise => panic!("Unexpected: {:?}", otherwise),
+        }
+        match read(&mut &b"APS2\x01\x00\x00\x00"[..], 10) {
+            Err(Error::InvalidGroupSize(0)) => {}
+            otherwise => panic!("Unexpected: {:?}", otherwise),
+        }
+        match read(&mut &b"APS2\x02\x00\x02\x00\x08"[..], 10) {
+            Err(Error::Read(read::Error::IoError(e))) => {
+                assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof)
+            }
+            otherwise => panic!("Unexpected: {:?}", otherwise),
+        }
+    }
+}
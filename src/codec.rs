@@ -0,0 +1,432 @@
+//! A configurable base 128 codec, for the many dialects of LEB128-like
+//! variable length integers found in the wild.
+//!
+//! A `Codec` describes the order of the seven-bit groups, whether one is added
+//! at each continuation so that every number has exactly one encoding, which
+//! value of the high bit marks the last byte, and how many bytes a number may
+//! take. Presets are provided for common dialects:
+//!
+//! ```
+//! use leb128::codec::Codec;
+//!
+//! let mut buf = vec![];
+//! Codec::LEB128.write(&mut buf, 624485).unwrap();
+//! assert_eq!(buf, [0xe5, 0x8e, 0x26]);
+//!
+//! // The encoding of patch file offsets in the UPS and BPS formats.
+//! let mut buf = vec![];
+//! Codec::UPS.write(&mut buf, 128).unwrap();
+//! assert_eq!(buf, [0x00, 0x80]);
+//! assert_eq!(Codec::UPS.decode(&buf).unwrap(), (128, 2));
+//! ```
+//!
+//! The dedicated functions in `read`, `write`, `vlq` and `git` are faster
+//! than the equivalent presets, and should be preferred where they apply.
+
+use super::{low_bits_of_u64, read, write, CONTINUATION_BIT};
+use std::io;
+
+/// The longest encoding any dialect produces for a 64-bit number.
+const MAX_ENCODED_LEN: usize = 10;
+
+/// The order in which a number's seven-bit groups are written.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum GroupOrder {
+    /// Least significant group first, as in LEB128.
+    LittleEndian,
+    /// Most significant group first, as in VLQ.
+    BigEndian,
+}
+
+/// A description of a base 128 variable length integer dialect.
+///
+/// Build one by starting from `Codec::new()` (which is plain unsigned LEB128
+/// without a length limit) or from a preset, and adjusting it:
+///
+/// ```
+/// use leb128::codec::{Codec, GroupOrder};
+///
+/// let codec = Codec::new()
+///     .group_order(GroupOrder::BigEndian)
+///     .bias(true);
+/// assert_eq!(codec, Codec::GIT_OFFSET);
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Codec {
+    group_order: GroupOrder,
+    bias: bool,
+    inverted_terminator: bool,
+    max_len: usize,
+}
+
+impl Default for Codec {
+    fn default() -> Codec {
+        Codec::new()
+    }
+}
+
+impl Codec {
+    /// Unsigned LEB128, exactly as read by `read::unsigned` and written by
+    /// `write::unsigned`.
+    pub const LEB128: Codec = Codec::new().max_len(10);
+
+    /// Protocol Buffers varints, which are unsigned LEB128 limited to ten
+    /// bytes. See also the `protobuf` module.
+    pub const PROTOBUF: Codec = Codec::LEB128;
+
+    /// Big-endian base 128, exactly as read by `vlq::read` and written by
+    /// `vlq::write`.
+    pub const VLQ: Codec = Codec::new().group_order(GroupOrder::BigEndian);
+
+    /// The offsets of git's `OBJ_OFS_DELTA` objects, exactly as read by
+    /// `git::read_offset` and written by `git::write_offset`.
+    pub const GIT_OFFSET: Codec = Codec::VLQ.bias(true);
+
+    /// The numbers in UPS and BPS patch files: little-endian with one added at
+    /// each continuation, and the high bit set on the last byte rather than
+    /// the others.
+    pub const UPS: Codec = Codec::new().bias(true).inverted_terminator(true);
+
+    /// Plain unsigned LEB128 without a length limit. Little-endian numbers
+    /// that continue past the last group a `u64` can hold are still rejected
+    /// with `read::Error::Overflow`.
+    pub const fn new() -> Codec {
+        Codec {
+            group_order: GroupOrder::LittleEndian,
+            bias: false,
+            inverted_terminator: false,
+            max_len: usize::MAX,
+        }
+    }
+
+    /// Set the order in which seven-bit groups are written.
+    pub const fn group_order(self, group_order: GroupOrder) -> Codec {
+        Codec {
+            group_order,
+            ..self
+        }
+    }
+
+    /// Set whether one is added at each continuation. With a bias, every
+    /// number has exactly one encoding.
+    pub const fn bias(self, bias: bool) -> Codec {
+        Codec { bias, ..self }
+    }
+
+    /// Set whether the high bit is set on the last byte, rather than on every
+    /// byte but the last.
+    pub const fn inverted_terminator(self, inverted_terminator: bool) -> Codec {
+        Codec {
+            inverted_terminator,
+            ..self
+        }
+    }
+
+    /// Set the maximum number of bytes a number may take. Longer encodings
+    /// are rejected with `read::Error::Overflow` when reading, and numbers
+    /// that need more bytes are rejected with an `InvalidInput` error when
+    /// writing.
+    pub const fn max_len(self, max_len: usize) -> Codec {
+        Codec { max_len, ..self }
+    }
+
+    fn is_last(&self, byte: u8) -> bool {
+        (byte & CONTINUATION_BIT == 0) != self.inverted_terminator
+    }
+
+    /// Decode a number from the bytes produced by `next_byte`.
+    fn decode_with<F>(&self, mut next_byte: F) -> Result<u64, read::Error>
+    where
+        F: FnMut() -> Result<u8, read::Error>,
+    {
+        let mut result: u64 = 0;
+        // The weight of the next little-endian group, or `None` once it no
+        // longer fits in a `u64`.
+        let mut weight = Some(1u64);
+
+        for _ in 0..self.max_len {
+            let byte = next_byte()?;
+            let group = u64::from(byte & !CONTINUATION_BIT);
+
+            result = match self.group_order {
+                GroupOrder::LittleEndian => match (group, weight) {
+                    (0, _) => Some(result),
+                    (_, Some(weight)) => group
+                        .checked_mul(weight)
+                        .and_then(|val| result.checked_add(val)),
+                    (_, None) => None,
+                },
+                GroupOrder::BigEndian => result.checked_mul(1 << 7).map(|val| val | group),
+            }
+            .ok_or(read::Error::Overflow)?;
+
+            if self.is_last(byte) {
+                return Ok(result);
+            }
+
+            // Zero groups past the last weight that fits would otherwise be
+            // accepted forever.
+            if self.group_order == GroupOrder::LittleEndian && weight.is_none() {
+                return Err(read::Error::Overflow);
+            }
+            weight = weight.and_then(|w| w.checked_mul(1 << 7));
+            if self.bias {
+                result = match self.group_order {
+                    GroupOrder::LittleEndian => weight.and_then(|w| result.checked_add(w)),
+                    GroupOrder::BigEndian => result.checked_add(1),
+                }
+                .ok_or(read::Error::Overflow)?;
+            }
+        }
+
+        Err(read::Error::Overflow)
+    }
+
+    /// Decode a number from the start of `buf` without consuming it. Returns
+    /// the number along with the length of its encoding.
+    pub fn decode(&self, buf: &[u8]) -> Result<(u64, usize), read::Error> {
+        let mut len = 0;
+        let val = self.decode_with(read::slice_bytes(buf, &mut len))?;
+        Ok((val, len))
+    }
+
+    /// Read a number from the given `std::io::Read`able and return it or an
+    /// error if reading failed.
+    pub fn read<R>(&self, r: &mut R) -> Result<u64, read::Error>
+    where
+        R: ?Sized + io::Read,
+    {
+        self.decode_with(read::io_bytes(r))
+    }
+
+    /// Encode `val` into `buf`, returning the length of the encoding.
+    fn encode(&self, buf: &mut [u8; MAX_ENCODED_LEN], mut val: u64) -> usize {
+        // Produce the groups least significant first, then put them in order.
+        let mut len = 0;
+        loop {
+            buf[len] = low_bits_of_u64(val);
+            len += 1;
+            val >>= 7;
+            if val == 0 {
+                break;
+            }
+            if self.bias {
+                val -= 1;
+            }
+        }
+        if self.group_order == GroupOrder::BigEndian {
+            buf[..len].reverse();
+        }
+
+        if self.inverted_terminator {
+            buf[len - 1] |= CONTINUATION_BIT;
+        } else {
+            for byte in &mut buf[..len - 1] {
+                *byte |= CONTINUATION_BIT;
+            }
+        }
+        len
+    }
+
+    /// The number of bytes `write` uses to encode `val`, regardless of the
+    /// maximum length.
+    pub fn encoded_len(&self, val: u64) -> usize {
+        self.encode(&mut [0; MAX_ENCODED_LEN], val)
+    }
+
+    /// Write the given number to the given `std::io::Write`able. Returns the
+    /// number of bytes written to `w`, or an error if writing failed.
+    pub fn write<W>(&self, w: &mut W, val: u64) -> Result<usize, write::Error>
+    where
+        W: ?Sized + io::Write,
+    {
+        let mut buf = [0; MAX_ENCODED_LEN];
+        let len = self.encode(&mut buf, val);
+        if len > self.max_len {
+            return Err(write::invalid_input(
+                "number is too large for the codec's maximum length",
+            ));
+        }
+        write::write_encoded(w, &buf[..len])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use {git, vlq};
+
+    const SAMPLES: &[u64] = &[
+        0,
+        1,
+        0x7f,
+        0x80,
+        0x3fff,
+        0x4000,
+        0x407f,
+        0x4080,
+        0x204080,
+        1 << 56,
+        u64::MAX >> 1,
+        u64::MAX - 1,
+        u64::MAX,
+    ];
+
+    fn round_trip(codec: Codec) {
+        for &val in SAMPLES {
+            let mut buf = vec![];
+            let len = codec.write(&mut buf, val).unwrap();
+            assert_eq!(len, buf.len());
+            assert_eq!(codec.encoded_len(val), len);
+            assert_eq!(
+                codec.decode(&buf).unwrap(),
+                (val, len),
+                "{:?} {:#x}",
+                codec,
+                val
+            );
+            assert_eq!(codec.read(&mut &buf[..]).unwrap(), val);
+        }
+    }
+
+    #[test]
+    fn test_round_trip_all_dialects() {
+        for &group_order in &[GroupOrder::LittleEndian, GroupOrder::BigEndian] {
+            for &bias in &[false, true] {
+                for &inverted_terminator in &[false, true] {
+                    round_trip(
+                        Codec::new()
+                            .group_order(group_order)
+                            .bias(bias)
+                            .inverted_terminator(inverted_terminator),
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_leb128_preset() {
+        for &val in SAMPLES {
+            let mut expected = vec![];
+            write::unsigned(&mut expected, val).unwrap();
+            let mut buf = vec![];
+            Codec::LEB128.write(&mut buf, val).unwrap();
+            assert_eq!(buf, expected);
+        }
+
+        // Padding is accepted up to ten bytes, as with `read::unsigned`.
+        let padded = [0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x00];
+        assert_eq!(Codec::LEB128.decode(&padded).unwrap(), (0, 10));
+        let too_long = [
+            0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x00,
+        ];
+        match Codec::LEB128.decode(&too_long) {
+            Err(read::Error::Overflow) => {}
+            otherwise => panic!("Unexpected: {:?}", otherwise),
+        }
+        let excess = [0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x02];
+        assert!(Codec::LEB128.decode(&excess).is_err());
+        assert!(read::peek_unsigned(&excess).is_err());
+    }
+
+    #[test]
+    fn test_endless_padding_without_max_len() {
+        for &codec in &[Codec::new(), Codec::new().bias(true)] {
+            match codec.read(&mut io::repeat(0x80)) {
+                Err(read::Error::Overflow) => {}
+                otherwise => panic!("Unexpected: {:?}", otherwise),
+            }
+        }
+        match Codec::UPS.read(&mut io::repeat(0x00)) {
+            Err(read::Error::Overflow) => {}
+            otherwise => panic!("Unexpected: {:?}", otherwise),
+        }
+    }
+
+    #[test]
+    fn test_vlq_and_git_presets() {
+        for &val in SAMPLES {
+            let mut expected = vec![];
+            vlq::write(&mut expected, val).unwrap();
+            let mut buf = vec![];
+            Codec::VLQ.write(&mut buf, val).unwrap();
+            assert_eq!(buf, expected);
+
+            let mut expected = vec![];
+            git::write_offset(&mut expected, val).unwrap();
+            let mut buf = vec![];
+            Codec::GIT_OFFSET.write(&mut buf, val).unwrap();
+            assert_eq!(buf, expected);
+        }
+    }
+
+    #[test]
+    fn test_ups_preset() {
+        // Values taken from the reference UPS encoder.
+        for &(val, encoded) in &[
+            (0u64, &[0x80][..]),
+            (0x7f, &[0xff]),
+            (0x80, &[0x00, 0x80]),
+            (0x407f, &[0x7f, 0xff]),
+            (0x4080, &[0x00, 0x00, 0x80]),
+        ] {
+            let mut buf = vec![];
+            Codec::UPS.write(&mut buf, val).unwrap();
+            assert_eq!(buf, encoded, "{:#x}", val);
+            assert_eq!(Codec::UPS.decode(encoded).unwrap(), (val, encoded.len()));
+        }
+    }
+
+    #[test]
+    fn test_max_len() {
+        let codec = Codec::VLQ.max_len(4);
+        let mut buf = vec![];
+        assert_eq!(codec.write(&mut buf, 0x0fff_ffff).unwrap(), 4);
+        match codec.write(&mut buf, 0x1000_0000) {
+            Err(e) => {
+                assert_eq!(e.io_error().kind(), io::ErrorKind::InvalidInput);
+                assert_eq!(e.bytes_written(), 0);
+            }
+            otherwise => panic!("Unexpected: {:?}", otherwise),
+        }
+        match codec.decode(&[0x81, 0x80, 0x80, 0x80, 0x00]) {
+            Err(read::Error::Overflow) => {}
+            otherwise => panic!("Unexpected: {:?}", otherwise),
+        }
+    }
+
+    #[test]
+    fn test_overflow() {
+        for codec in &[Codec::new(), Codec::VLQ, Codec::GIT_OFFSET, Codec::UPS] {
+            let mut buf = vec![];
+            codec.write(&mut buf, u64::MAX).unwrap();
+            // Make the number one group longer.
+            let extra = if codec.inverted_terminator {
+                0x00
+            } else {
+                0x80
+            };
+            match codec.group_order {
+                GroupOrder::LittleEndian => {
+                    let last = buf.len() - 1;
+                    buf[last] ^= CONTINUATION_BIT;
+                    buf.push((extra ^ CONTINUATION_BIT) | 0x01);
+                }
+                GroupOrder::BigEndian => buf.insert(0, extra | 0x01),
+            }
+            match codec.decode(&buf) {
+                Err(read::Error::Overflow) => {}
+                otherwise => panic!("Unexpected for {:?}: {:?}", codec, otherwise),
+            }
+        }
+    }
+
+    #[test]
+    fn test_not_enough_data() {
+        match Codec::UPS.decode(&[0x00, 0x00]) {
+            Err(read::Error::IoError(e)) => assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof),
+            otherwise => panic!("Unexpected: {:?}", otherwise),
+        }
+    }
+}
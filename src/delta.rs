@@ -0,0 +1,202 @@
+//! Read and write lists of ascending addresses stored as unsigned LEB128
+//! deltas, as in Mach-O `LC_FUNCTION_STARTS` (see `macho::function_starts`)
+//! and several other tables.
+//!
+//! Each delta is added to the previous address, starting from a base address.
+//! The list ends either at a reserved delta, usually zero, or at the end of
+//! the input.
+//!
+//! ```
+//! use leb128::delta::{self, Terminator};
+//!
+//! let mut buf = vec![];
+//! delta::write(&mut buf, 0x1000, &[0x1010, 0x1090], Terminator::Delta(0)).unwrap();
+//! assert_eq!(buf, [0x10, 0x80, 0x01, 0x00]);
+//!
+//! let addresses: Vec<u64> = delta::addresses(&buf[..], 0x1000, Terminator::Delta(0))
+//!     .collect::<Result<_, _>>()
+//!     .unwrap();
+//! assert_eq!(addresses, [0x1010, 0x1090]);
+//! ```
+
+use super::{read, write};
+use std::io;
+
+/// How a delta list ends.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Terminator {
+    /// The list ends at the first delta equal to this value, which is not
+    /// itself applied.
+    Delta(u64),
+    /// The list ends with its input.
+    EndOfInput,
+}
+
+/// An iterator over the absolute addresses in a delta list.
+///
+/// Iteration stops at the terminator, or after the first error. An address
+/// that does not fit in 64 bits is reported as `read::Error::Overflow`.
+#[derive(Debug)]
+pub struct Addresses<R> {
+    reader: R,
+    address: u64,
+    terminator: Terminator,
+    done: bool,
+}
+
+/// Iterate over the addresses of the delta list read from `reader`, with the
+/// first delta relative to `base`.
+pub fn addresses<R>(reader: R, base: u64, terminator: Terminator) -> Addresses<R>
+where
+    R: io::BufRead,
+{
+    Addresses {
+        reader,
+        address: base,
+        terminator,
+        done: false,
+    }
+}
+
+impl<R> Addresses<R>
+where
+    R: io::BufRead,
+{
+    /// Consume this iterator and return the underlying reader, positioned
+    /// just after the last delta read.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    fn next_address(&mut self) -> Result<Option<u64>, read::Error> {
+        if self.terminator == Terminator::EndOfInput && self.reader.fill_buf()?.is_empty() {
+            return Ok(None);
+        }
+
+        let delta = read::unsigned(&mut self.reader)?;
+        if self.terminator == Terminator::Delta(delta) {
+            return Ok(None);
+        }
+        self.address = self
+            .address
+            .checked_add(delta)
+            .ok_or(read::Error::Overflow)?;
+        Ok(Some(self.address))
+    }
+}
+
+impl<R> Iterator for Addresses<R>
+where
+    R: io::BufRead,
+{
+    type Item = Result<u64, read::Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let result = self.next_address();
+        self.done = !matches!(result, Ok(Some(_)));
+        result.transpose()
+    }
+}
+
+/// Write `addresses` as a delta list with the first delta relative to `base`,
+/// followed by the terminator unless it is `Terminator::EndOfInput`. The whole
+/// list is encoded up front and handed to `w` in one go. Returns the number of
+/// bytes written to `w`, or an error if writing failed.
+///
+/// Addresses that are not in ascending order, or that would need the
+/// terminating delta, are rejected with an `InvalidInput` error before
+/// anything is written.
+pub fn write<W>(
+    w: &mut W,
+    base: u64,
+    addresses: &[u64],
+    terminator: Terminator,
+) -> Result<usize, write::Error>
+where
+    W: ?Sized + io::Write,
+{
+    let mut buf = Vec::with_capacity(addresses.len() + 1);
+    let mut prev = base;
+    for &address in addresses {
+        let delta = match address.checked_sub(prev) {
+            Some(delta) if terminator != Terminator::Delta(delta) => delta,
+            Some(_) => return Err(write::invalid_input("address needs the terminating delta")),
+            None => return Err(write::invalid_input("addresses are not in ascending order")),
+        };
+        write::unsigned(&mut buf, delta)?;
+        prev = address;
+    }
+    if let Terminator::Delta(delta) = terminator {
+        write::unsigned(&mut buf, delta)?;
+    }
+    write::write_encoded(w, &buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let list = [0x10, 0x11, 0x1000, u64::MAX];
+        for &terminator in &[Terminator::Delta(0), Terminator::EndOfInput] {
+            let mut buf = vec![];
+            let len = write(&mut buf, 0x08, &list, terminator).unwrap();
+            assert_eq!(len, buf.len());
+            let decoded: Vec<u64> = addresses(&buf[..], 0x08, terminator)
+                .collect::<Result<_, _>>()
+                .unwrap();
+            assert_eq!(decoded, list);
+        }
+    }
+
+    #[test]
+    fn test_stops_at_terminator() {
+        let buf = [0x01, 0x7f, 0x02, 0x7f, 0x00];
+        let mut iter = addresses(&buf[..], 0, Terminator::Delta(0x7f));
+        assert_eq!(iter.next().unwrap().unwrap(), 1);
+        assert!(iter.next().is_none());
+        assert!(iter.next().is_none());
+        assert_eq!(iter.into_inner(), [0x02, 0x7f, 0x00]);
+    }
+
+    #[test]
+    fn test_read_errors() {
+        // Running out of input before the terminator.
+        let mut iter = addresses(&[0x01, 0x80][..], 0, Terminator::Delta(0));
+        assert_eq!(iter.next().unwrap().unwrap(), 1);
+        match iter.next() {
+            Some(Err(read::Error::IoError(e))) => {
+                assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof)
+            }
+            otherwise => panic!("Unexpected: {:?}", otherwise),
+        }
+        assert!(iter.next().is_none());
+
+        // Passing the end of the address space.
+        let mut iter = addresses(&[0x02][..], u64::MAX - 1, Terminator::EndOfInput);
+        match iter.next() {
+            Some(Err(read::Error::Overflow)) => {}
+            otherwise => panic!("Unexpected: {:?}", otherwise),
+        }
+    }
+
+    #[test]
+    fn test_write_errors() {
+        let mut buf = vec![];
+        for &(base, list) in &[(0x10, &[0x0f][..]), (0, &[0x10, 0x08]), (0, &[0x10, 0x10])] {
+            match write(&mut buf, base, list, Terminator::Delta(0)) {
+                Err(e) => assert_eq!(e.io_error().kind(), io::ErrorKind::InvalidInput),
+                otherwise => panic!("Unexpected: {:?}", otherwise),
+            }
+        }
+        assert!(buf.is_empty());
+
+        // Without a terminating delta, repeated addresses are fine.
+        write(&mut buf, 0, &[0x10, 0x10], Terminator::EndOfInput).unwrap();
+        assert_eq!(buf, [0x10, 0x00]);
+    }
+}
@@ -0,0 +1,436 @@
+//! Read and write the LEB128 variants used in Android DEX files, and decode
+//! the `class_data_item` structures built from them.
+//!
+//! DEX files use ordinary `uleb128` and `sleb128`, but every value must fit in
+//! 32 bits, so an encoding is at most five bytes long. They also use
+//! `uleb128p1`, which stores a value plus one so that `-1` (typically meaning
+//! "no index") encodes as a single zero byte.
+//!
+//! ```
+//! use leb128::dex;
+//!
+//! let mut buf = vec![];
+//! dex::write_uleb128p1(&mut buf, -1).unwrap();
+//! dex::write_sleb128(&mut buf, -128).unwrap();
+//! assert_eq!(buf, [0x00, 0x80, 0x7f]);
+//!
+//! let mut readable = &buf[..];
+//! assert_eq!(dex::read_uleb128p1(&mut readable).unwrap(), -1);
+//! assert_eq!(dex::read_sleb128(&mut readable).unwrap(), -128);
+//! ```
+
+use super::{low_bits_of_byte, read, write, CONTINUATION_BIT, SIGN_BIT};
+use std::io;
+
+/// The maximum number of bytes a DEX `uleb128`, `sleb128` or `uleb128p1` can
+/// occupy.
+pub const MAX_ENCODED_LEN: usize = 5;
+
+/// Decode a 32-bit LEB128 number from the bytes produced by `next_byte`,
+/// sign extending it when `signed` is set.
+///
+/// The fifth byte may only hold the top four bits of the number (and, when
+/// `signed`, copies of the top bit); anything else is `Overflow`.
+fn decode<F>(mut next_byte: F, signed: bool) -> Result<u32, read::Error>
+where
+    F: FnMut() -> Result<u8, read::Error>,
+{
+    let mut result = 0;
+    let mut shift = 0;
+
+    loop {
+        let byte = next_byte()?;
+        let low_bits = low_bits_of_byte(byte);
+
+        if shift == 7 * (MAX_ENCODED_LEN as u32 - 1) {
+            let valid = match low_bits {
+                0x00..=0x07 => true,
+                0x08..=0x0f => !signed,
+                0x78..=0x7f => signed,
+                _ => false,
+            };
+            if !valid || byte & CONTINUATION_BIT != 0 {
+                return Err(read::Error::Overflow);
+            }
+        }
+
+        result |= u32::from(low_bits) << shift;
+        shift += 7;
+
+        if byte & CONTINUATION_BIT == 0 {
+            if signed && shift < 32 && byte & SIGN_BIT != 0 {
+                result |= !0 << shift;
+            }
+            return Ok(result);
+        }
+    }
+}
+
+/// Read a DEX `uleb128` from the given `std::io::Read`able and return it or
+/// an error if reading failed. Numbers that do not fit in 32 bits are rejected
+/// with `read::Error::Overflow`.
+pub fn read_uleb128<R>(r: &mut R) -> Result<u32, read::Error>
+where
+    R: io::Read,
+{
+    decode(read::io_bytes(r), false)
+}
+
+/// Read a DEX `sleb128` from the given `std::io::Read`able and return it or
+/// an error if reading failed. Numbers that do not fit in 32 bits are rejected
+/// with `read::Error::Overflow`.
+pub fn read_sleb128<R>(r: &mut R) -> Result<i32, read::Error>
+where
+    R: io::Read,
+{
+    decode(read::io_bytes(r), true).map(|val| val as i32)
+}
+
+/// Read a DEX `uleb128p1` from the given `std::io::Read`able and return it
+/// or an error if reading failed.
+pub fn read_uleb128p1<R>(r: &mut R) -> Result<i32, read::Error>
+where
+    R: io::Read,
+{
+    read_uleb128(r).map(|val| val.wrapping_sub(1) as i32)
+}
+
+/// Decode a DEX `uleb128` from the start of `buf` without consuming it.
+/// Returns the number along with the length of its encoding.
+pub fn peek_uleb128(buf: &[u8]) -> Result<(u32, usize), read::Error> {
+    let mut len = 0;
+    let val = decode(read::slice_bytes(buf, &mut len), false)?;
+    Ok((val, len))
+}
+
+/// Decode a DEX `sleb128` from the start of `buf` without consuming it.
+/// Returns the number along with the length of its encoding.
+pub fn peek_sleb128(buf: &[u8]) -> Result<(i32, usize), read::Error> {
+    let mut len = 0;
+    let val = decode(read::slice_bytes(buf, &mut len), true)?;
+    Ok((val as i32, len))
+}
+
+/// Decode a DEX `uleb128p1` from the start of `buf` without consuming it.
+/// Returns the number along with the length of its encoding.
+pub fn peek_uleb128p1(buf: &[u8]) -> Result<(i32, usize), read::Error> {
+    let (val, len) = peek_uleb128(buf)?;
+    Ok((val.wrapping_sub(1) as i32, len))
+}
+
+/// Write a DEX `uleb128` to the given `std::io::Write`able. Returns the
+/// number of bytes written to `w`, or an error if writing failed.
+pub fn write_uleb128<W>(w: &mut W, val: u32) -> Result<usize, write::Error>
+where
+    W: ?Sized + io::Write,
+{
+    write::unsigned(w, u64::from(val))
+}
+
+/// Write a DEX `sleb128` to the given `std::io::Write`able. Returns the
+/// number of bytes written to `w`, or an error if writing failed.
+pub fn write_sleb128<W>(w: &mut W, val: i32) -> Result<usize, write::Error>
+where
+    W: ?Sized + io::Write,
+{
+    write::signed(w, i64::from(val))
+}
+
+/// Write a DEX `uleb128p1` to the given `std::io::Write`able. Returns the
+/// number of bytes written to `w`, or an error if writing failed.
+pub fn write_uleb128p1<W>(w: &mut W, val: i32) -> Result<usize, write::Error>
+where
+    W: ?Sized + io::Write,
+{
+    write_uleb128(w, (val as u32).wrapping_add(1))
+}
+
+/// A field in a `class_data_item`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EncodedField {
+    /// The index of the field in the `field_ids` list.
+    pub field_idx: u32,
+    /// The field's access flags, such as `ACC_STATIC`.
+    pub access_flags: u32,
+}
+
+/// A method in a `class_data_item`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EncodedMethod {
+    /// The index of the method in the `method_ids` list.
+    pub method_idx: u32,
+    /// The method's access flags, such as `ACC_PUBLIC`.
+    pub access_flags: u32,
+    /// The file offset of the method's `code_item`, or zero if the method is
+    /// abstract or native.
+    pub code_off: u32,
+}
+
+/// A decoded `class_data_item`, with the delta-encoded field and method
+/// indices resolved to absolute indices.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ClassData {
+    /// The class's static fields.
+    pub static_fields: Vec<EncodedField>,
+    /// The class's instance fields.
+    pub instance_fields: Vec<EncodedField>,
+    /// The class's static, private and constructor methods.
+    pub direct_methods: Vec<EncodedMethod>,
+    /// The class's other methods.
+    pub virtual_methods: Vec<EncodedMethod>,
+}
+
+/// Add a delta to the previous index in a list, or to zero for the first.
+fn next_index(prev: Option<u32>, diff: u32) -> Result<u32, read::Error> {
+    match prev {
+        None => Ok(diff),
+        Some(prev) => prev.checked_add(diff).ok_or(read::Error::Overflow),
+    }
+}
+
+/// Decode `count` fields from the bytes produced by `next_byte`.
+fn decode_fields<F>(next_byte: &mut F, count: u32) -> Result<Vec<EncodedField>, read::Error>
+where
+    F: FnMut() -> Result<u8, read::Error>,
+{
+    // The count is untrusted, so let the vector grow as elements arrive.
+    let mut fields: Vec<EncodedField> = Vec::new();
+    for _ in 0..count {
+        let diff = decode(&mut *next_byte, false)?;
+        let field_idx = next_index(fields.last().map(|f| f.field_idx), diff)?;
+        let access_flags = decode(&mut *next_byte, false)?;
+        fields.push(EncodedField {
+            field_idx,
+            access_flags,
+        });
+    }
+    Ok(fields)
+}
+
+/// Decode `count` methods from the bytes produced by `next_byte`.
+fn decode_methods<F>(next_byte: &mut F, count: u32) -> Result<Vec<EncodedMethod>, read::Error>
+where
+    F: FnMut() -> Result<u8, read::Error>,
+{
+    let mut methods: Vec<EncodedMethod> = Vec::new();
+    for _ in 0..count {
+        let diff = decode(&mut *next_byte, false)?;
+        let method_idx = next_index(methods.last().map(|m| m.method_idx), diff)?;
+        let access_flags = decode(&mut *next_byte, false)?;
+        let code_off = decode(&mut *next_byte, false)?;
+        methods.push(EncodedMethod {
+            method_idx,
+            access_flags,
+            code_off,
+        });
+    }
+    Ok(methods)
+}
+
+/// Decode a `class_data_item` from the bytes produced by `next_byte`.
+fn decode_class_data<F>(mut next_byte: F) -> Result<ClassData, read::Error>
+where
+    F: FnMut() -> Result<u8, read::Error>,
+{
+    let static_fields_size = decode(&mut next_byte, false)?;
+    let instance_fields_size = decode(&mut next_byte, false)?;
+    let direct_methods_size = decode(&mut next_byte, false)?;
+    let virtual_methods_size = decode(&mut next_byte, false)?;
+
+    Ok(ClassData {
+        static_fields: decode_fields(&mut next_byte, static_fields_size)?,
+        instance_fields: decode_fields(&mut next_byte, instance_fields_size)?,
+        direct_methods: decode_methods(&mut next_byte, direct_methods_size)?,
+        virtual_methods: decode_methods(&mut next_byte, virtual_methods_size)?,
+    })
+}
+
+/// Read a `class_data_item` from the given `std::io::Read`able and return it
+/// or an error if reading failed. A field or method index that does not fit
+/// in 32 bits is rejected with `read::Error::Overflow`.
+pub fn read_class_data<R>(r: &mut R) -> Result<ClassData, read::Error>
+where
+    R: io::Read,
+{
+    decode_class_data(read::io_bytes(r))
+}
+
+/// Decode a `class_data_item` from the start of `buf` without consuming it.
+/// Returns the item along with the length of its encoding.
+pub fn peek_class_data(buf: &[u8]) -> Result<(ClassData, usize), read::Error> {
+    let mut len = 0;
+    let class_data = decode_class_data(read::slice_bytes(buf, &mut len))?;
+    Ok((class_data, len))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Examples from the DEX format documentation.
+    #[test]
+    fn test_spec_examples() {
+        for &(encoded, sleb, uleb, ulebp1) in &[
+            (&[0x00][..], 0, 0, -1),
+            (&[0x01], 1, 1, 0),
+            (&[0x7f], -1, 127, 126),
+            (&[0x80, 0x7f], -128, 16256, 16255),
+        ] {
+            assert_eq!(peek_sleb128(encoded).unwrap(), (sleb, encoded.len()));
+            assert_eq!(peek_uleb128(encoded).unwrap(), (uleb, encoded.len()));
+            assert_eq!(peek_uleb128p1(encoded).unwrap(), (ulebp1, encoded.len()));
+
+            let mut buf = vec![];
+            write_uleb128(&mut buf, uleb).unwrap();
+            assert_eq!(buf, encoded);
+            let mut buf = vec![];
+            write_uleb128p1(&mut buf, ulebp1).unwrap();
+            assert_eq!(buf, encoded);
+            assert_eq!(read_sleb128(&mut &encoded[..]).unwrap(), sleb);
+        }
+    }
+
+    #[test]
+    fn test_32_bit_limits() {
+        for &val in &[0, 1, 0x7f, 0x80, 0x0fff_ffff, 0x1000_0000, u32::MAX] {
+            let mut buf = vec![];
+            let len = write_uleb128(&mut buf, val).unwrap();
+            assert!(len <= MAX_ENCODED_LEN);
+            assert_eq!(read_uleb128(&mut &buf[..]).unwrap(), val);
+        }
+        for &val in &[
+            i32::MIN,
+            -0x0800_0001,
+            -0x0800_0000,
+            -1,
+            0,
+            0x07ff_ffff,
+            0x0800_0000,
+            i32::MAX,
+        ] {
+            let mut buf = vec![];
+            let len = write_sleb128(&mut buf, val).unwrap();
+            assert!(len <= MAX_ENCODED_LEN);
+            assert_eq!(peek_sleb128(&buf).unwrap(), (val, len));
+        }
+        for &val in &[-1, 0, i32::MAX, i32::MIN] {
+            let mut buf = vec![];
+            write_uleb128p1(&mut buf, val).unwrap();
+            assert_eq!(read_uleb128p1(&mut &buf[..]).unwrap(), val);
+        }
+    }
+
+    #[test]
+    fn test_overflow() {
+        for buf in &[
+            // More than 32 bits.
+            &[0xff, 0xff, 0xff, 0xff, 0x1f][..],
+            // More than five bytes.
+            &[0x80, 0x80, 0x80, 0x80, 0x80, 0x00],
+        ] {
+            match peek_uleb128(buf) {
+                Err(read::Error::Overflow) => {}
+                otherwise => panic!("Unexpected: {:?}", otherwise),
+            }
+        }
+
+        // The fifth byte of a signed number must be a sign extension of bit 31.
+        assert_eq!(
+            peek_sleb128(&[0x80, 0x80, 0x80, 0x80, 0x78]).unwrap(),
+            (i32::MIN, 5)
+        );
+        match peek_sleb128(&[0x80, 0x80, 0x80, 0x80, 0x08]) {
+            Err(read::Error::Overflow) => {}
+            otherwise => panic!("Unexpected: {:?}", otherwise),
+        }
+    }
+
+    #[test]
+    fn test_class_data() {
+        let mut buf = vec![];
+        // 1 static field, 2 instance fields, 1 direct method, 2 virtual methods.
+        for &n in &[1, 2, 1, 2] {
+            write_uleb128(&mut buf, n).unwrap();
+        }
+        // Static field 3.
+        for &n in &[3, 0x0008] {
+            write_uleb128(&mut buf, n).unwrap();
+        }
+        // Instance fields 1 and 5; the index list starts again from zero.
+        for &n in &[1, 0x0002, 4, 0x0012] {
+            write_uleb128(&mut buf, n).unwrap();
+        }
+        // Direct method 7, a constructor.
+        for &n in &[7, 0x1_0001, 0x1234] {
+            write_uleb128(&mut buf, n).unwrap();
+        }
+        // Virtual methods 2 and 300, the second abstract.
+        for &n in &[2, 0x0001, 0x2000, 298, 0x0401, 0] {
+            write_uleb128(&mut buf, n).unwrap();
+        }
+        buf.push(0xff);
+
+        let (class_data, len) = peek_class_data(&buf).unwrap();
+        assert_eq!(len, buf.len() - 1);
+        assert_eq!(
+            class_data,
+            ClassData {
+                static_fields: vec![EncodedField {
+                    field_idx: 3,
+                    access_flags: 0x0008,
+                }],
+                instance_fields: vec![
+                    EncodedField {
+                        field_idx: 1,
+                        access_flags: 0x0002,
+                    },
+                    EncodedField {
+                        field_idx: 5,
+                        access_flags: 0x0012,
+                    },
+                ],
+                direct_methods: vec![EncodedMethod {
+                    method_idx: 7,
+                    access_flags: 0x1_0001,
+                    code_off: 0x1234,
+                }],
+                virtual_methods: vec![
+                    EncodedMethod {
+                        method_idx: 2,
+                        access_flags: 0x0001,
+                        code_off: 0x2000,
+                    },
+                    EncodedMethod {
+                        method_idx: 300,
+                        access_flags: 0x0401,
+                        code_off: 0,
+                    },
+                ],
+            }
+        );
+        assert_eq!(read_class_data(&mut &buf[..]).unwrap(), class_data);
+    }
+
+    #[test]
+    fn test_class_data_errors() {
+        // Index deltas that add up to more than 32 bits.
+        let mut buf = vec![];
+        for &n in &[2, 0, 0, 0, u32::MAX, 0, 1, 0] {
+            write_uleb128(&mut buf, n).unwrap();
+        }
+        match peek_class_data(&buf) {
+            Err(read::Error::Overflow) => {}
+            otherwise => panic!("Unexpected: {:?}", otherwise),
+        }
+
+        // A huge count with nothing after it.
+        let mut buf = vec![];
+        for &n in &[u32::MAX, 0, 0, 0] {
+            write_uleb128(&mut buf, n).unwrap();
+        }
+        match peek_class_data(&buf) {
+            Err(read::Error::IoError(e)) => assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof),
+            otherwise => panic!("Unexpected: {:?}", otherwise),
+        }
+    }
+}
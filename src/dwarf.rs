@@ -0,0 +1,8 @@
+//! Decode the LEB128 based structures of DWARF debugging information.
+//!
+//! Each submodule handles one kind of data, reading from a byte slice of the
+//! relevant section and reporting errors with offsets into that section.
+
+pub mod abbrev;
+pub mod expr;
+pub mod line;
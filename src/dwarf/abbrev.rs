@@ -0,0 +1,330 @@
+//! Parse the abbreviation tables of a `.debug_abbrev` section.
+//!
+//! Each entry of a table is a ULEB128 code, a ULEB128 tag, a one-byte
+//! children flag, and a list of ULEB128 attribute name and form pairs ended
+//! by two zeros. Attributes with the DWARF 5 `DW_FORM_implicit_const` form
+//! also carry an SLEB128 value. A table ends with a zero code.
+//!
+//! ```
+//! use leb128::dwarf::abbrev;
+//!
+//! // Code 1: DW_TAG_compile_unit, with children,
+//! //         DW_AT_name DW_FORM_string, DW_AT_language DW_FORM_implicit_const 0x1d.
+//! let section = [0x01, 0x11, 0x01, 0x03, 0x08, 0x13, 0x21, 0x1d, 0x00, 0x00, 0x00];
+//! let table = abbrev::parse(&section, 0).unwrap();
+//!
+//! let unit = table.get(1).unwrap();
+//! assert_eq!(unit.tag, 0x11);
+//! assert!(unit.has_children);
+//! assert_eq!(unit.attributes[1].implicit_const, Some(0x1d));
+//! ```
+
+use super::super::reader::{self, Leb128Reader};
+use std::collections::HashMap;
+use std::fmt;
+
+/// The children flag of an entry without children.
+pub const DW_CHILDREN_NO: u8 = 0x00;
+/// The children flag of an entry with children.
+pub const DW_CHILDREN_YES: u8 = 0x01;
+/// The form of attributes whose value is stored in the abbreviation.
+pub const DW_FORM_IMPLICIT_CONST: u64 = 0x21;
+
+/// An error that occurred while parsing an abbreviation table.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// A code, tag, attribute or form could not be read, or the table ended
+    /// without a zero code.
+    Read(reader::Error),
+    /// An entry had a tag of zero. Carries the offset of the tag.
+    ZeroTag(usize),
+    /// A children flag was neither `DW_CHILDREN_NO` nor `DW_CHILDREN_YES`.
+    /// Carries the offset and the flag.
+    InvalidChildren(usize, u8),
+    /// An attribute had a name or a form of zero, but not both. Carries the
+    /// offset of the pair.
+    InvalidAttribute(usize),
+    /// Two entries of the table had the same code. Carries the offset of the
+    /// second and the code.
+    DuplicateCode(usize, u64),
+}
+
+impl From<reader::Error> for Error {
+    fn from(e: reader::Error) -> Self {
+        Error::Read(e)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match *self {
+            Error::Read(ref e) => write!(f, "leb128::dwarf::abbrev::Error: {}", e),
+            Error::ZeroTag(offset) => write!(
+                f,
+                "leb128::dwarf::abbrev::Error: Zero tag at offset {:#x}",
+                offset
+            ),
+            Error::InvalidChildren(offset, flag) => write!(
+                f,
+                "leb128::dwarf::abbrev::Error: Invalid children flag {:#04x} at offset {:#x}",
+                flag, offset
+            ),
+            Error::InvalidAttribute(offset) => write!(
+                f,
+                "leb128::dwarf::abbrev::Error: Invalid attribute specification at offset {:#x}",
+                offset
+            ),
+            Error::DuplicateCode(offset, code) => write!(
+                f,
+                "leb128::dwarf::abbrev::Error: Duplicate code {} at offset {:#x}",
+                code, offset
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match *self {
+            Error::Read(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// An attribute of an abbreviation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AttributeSpec {
+    /// The attribute name, such as `DW_AT_name` (0x03).
+    pub name: u64,
+    /// The attribute form, such as `DW_FORM_string` (0x08).
+    pub form: u64,
+    /// The value of a `DW_FORM_IMPLICIT_CONST` attribute, or `None` for other
+    /// forms.
+    pub implicit_const: Option<i64>,
+}
+
+/// An entry of an abbreviation table.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Abbreviation {
+    /// The offset of the entry within the section.
+    pub offset: usize,
+    /// The code that debugging information entries refer to this entry by.
+    pub code: u64,
+    /// The tag, such as `DW_TAG_compile_unit` (0x11).
+    pub tag: u64,
+    /// Whether debugging information entries using this entry have children.
+    pub has_children: bool,
+    /// The attributes, in order.
+    pub attributes: Vec<AttributeSpec>,
+}
+
+/// An abbreviation table, keyed by code.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Abbreviations {
+    entries: HashMap<u64, Abbreviation>,
+}
+
+impl Abbreviations {
+    /// The entry with the given code, if there is one.
+    pub fn get(&self, code: u64) -> Option<&Abbreviation> {
+        self.entries.get(&code)
+    }
+
+    /// The number of entries.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Whether the table has no entries.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Iterate over the entries, in no particular order.
+    pub fn iter(&self) -> impl Iterator<Item = &Abbreviation> {
+        self.entries.values()
+    }
+}
+
+fn parse_attributes(reader: &mut Leb128Reader) -> Result<Vec<AttributeSpec>, Error> {
+    let mut attributes = vec![];
+    loop {
+        let offset = reader.offset();
+        let name = reader.read_uleb128()?;
+        let form = reader.read_uleb128()?;
+        match (name, form) {
+            (0, 0) => return Ok(attributes),
+            (0, _) | (_, 0) => return Err(Error::InvalidAttribute(offset)),
+            _ => {}
+        }
+        let implicit_const = if form == DW_FORM_IMPLICIT_CONST {
+            Some(reader.read_sleb128()?)
+        } else {
+            None
+        };
+        attributes.push(AttributeSpec {
+            name,
+            form,
+            implicit_const,
+        });
+    }
+}
+
+/// Parse the abbreviation table starting at `offset` within `debug_abbrev`,
+/// the contents of a `.debug_abbrev` section. The offset usually comes from
+/// a unit header's `debug_abbrev_offset`.
+pub fn parse(debug_abbrev: &[u8], offset: usize) -> Result<Abbreviations, Error> {
+    let mut reader = Leb128Reader::new(debug_abbrev);
+    reader.seek(offset)?;
+    let mut table = Abbreviations::default();
+
+    loop {
+        let entry_offset = reader.offset();
+        let code = reader.read_uleb128()?;
+        if code == 0 {
+            return Ok(table);
+        }
+        if table.entries.contains_key(&code) {
+            return Err(Error::DuplicateCode(entry_offset, code));
+        }
+
+        let tag_offset = reader.offset();
+        let tag = reader.read_uleb128()?;
+        if tag == 0 {
+            return Err(Error::ZeroTag(tag_offset));
+        }
+        let children_offset = reader.offset();
+        let has_children = match reader.read_u8()? {
+            DW_CHILDREN_NO => false,
+            DW_CHILDREN_YES => true,
+            flag => return Err(Error::InvalidChildren(children_offset, flag)),
+        };
+        let attributes = parse_attributes(&mut reader)?;
+
+        table.entries.insert(
+            code,
+            Abbreviation {
+                offset: entry_offset,
+                code,
+                tag,
+                has_children,
+                attributes,
+            },
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::super::reader::ErrorKind;
+    use super::*;
+
+    #[rustfmt::skip]
+    const SECTION: [u8; 31] = [
+        // An empty table.
+        0x00,
+        // 1: DW_TAG_compile_unit, children, DW_AT_producer DW_FORM_strp.
+        0x01, 0x11, 0x01, 0x25, 0x0e, 0x00, 0x00,
+        // 300: DW_TAG_variable, no children, DW_AT_name DW_FORM_string,
+        //      DW_AT_decl_line DW_FORM_implicit_const -2.
+        0xac, 0x02, 0x34, 0x00, 0x03, 0x08, 0x3b, 0x21, 0x7e, 0x00, 0x00,
+        // 2: DW_TAG_base_type, no children, no attributes.
+        0x02, 0x24, 0x00, 0x00, 0x00,
+        0x00,
+        // A second table with just DW_TAG_subprogram.
+        0x01, 0x2e, 0x00, 0x00, 0x00,
+        0x00,
+    ];
+
+    #[test]
+    fn test_parse() {
+        assert!(parse(&SECTION, 0).unwrap().is_empty());
+
+        let table = parse(&SECTION, 1).unwrap();
+        assert_eq!(table.len(), 3);
+        assert_eq!(
+            table.get(1).unwrap(),
+            &Abbreviation {
+                offset: 1,
+                code: 1,
+                tag: 0x11,
+                has_children: true,
+                attributes: vec![AttributeSpec {
+                    name: 0x25,
+                    form: 0x0e,
+                    implicit_const: None,
+                }],
+            }
+        );
+        assert_eq!(
+            table.get(300).unwrap(),
+            &Abbreviation {
+                offset: 8,
+                code: 300,
+                tag: 0x34,
+                has_children: false,
+                attributes: vec![
+                    AttributeSpec {
+                        name: 0x03,
+                        form: 0x08,
+                        implicit_const: None,
+                    },
+                    AttributeSpec {
+                        name: 0x3b,
+                        form: DW_FORM_IMPLICIT_CONST,
+                        implicit_const: Some(-2),
+                    },
+                ],
+            }
+        );
+        assert!(table.get(2).unwrap().attributes.is_empty());
+        assert!(table.get(3).is_none());
+
+        let table = parse(&SECTION, 25).unwrap();
+        assert_eq!(table.len(), 1);
+        assert_eq!(table.get(1).unwrap().tag, 0x2e);
+        assert_eq!(table.iter().count(), 1);
+    }
+
+    #[test]
+    fn test_errors() {
+        for &(section, ref error) in &[
+            // No zero code at the end of the table.
+            (
+                &[0x01, 0x11, 0x00, 0x00, 0x00][..],
+                Error::Read(reader::Error::new(5, ErrorKind::UnexpectedEof)),
+            ),
+            (&[0x01, 0x00, 0x00, 0x00, 0x00, 0x00], Error::ZeroTag(1)),
+            (
+                &[0x01, 0x11, 0x02, 0x00, 0x00, 0x00],
+                Error::InvalidChildren(2, 0x02),
+            ),
+            (
+                &[0x01, 0x11, 0x00, 0x03, 0x08, 0x03, 0x00, 0x00],
+                Error::InvalidAttribute(5),
+            ),
+            (
+                &[
+                    0x01, 0x11, 0x00, 0x00, 0x00, 0x01, 0x24, 0x00, 0x00, 0x00, 0x00,
+                ],
+                Error::DuplicateCode(5, 1),
+            ),
+            // An implicit constant that runs off the end.
+            (
+                &[0x01, 0x11, 0x00, 0x03, 0x21, 0x80],
+                Error::Read(reader::Error::new(5, ErrorKind::UnexpectedEof)),
+            ),
+        ] {
+            assert_eq!(parse(section, 0), Err(error.clone()));
+        }
+
+        assert_eq!(
+            parse(&SECTION, 31),
+            Err(Error::Read(reader::Error::new(
+                31,
+                ErrorKind::UnexpectedEof
+            )))
+        );
+    }
+}
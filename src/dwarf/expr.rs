@@ -0,0 +1,900 @@
+//! Disassemble and evaluate DWARF expressions, the stack machine bytecode
+//! used for location descriptions and other computed attribute values.
+//!
+//! Each operation is a one-byte `DW_OP_*` opcode followed by its operands,
+//! which are mostly ULEB128 or SLEB128 numbers. `Operations` disassembles an
+//! expression, and `evaluate` runs the simple ones: those that only need the
+//! stack, registers and memory.
+//!
+//! ```
+//! use leb128::dwarf::expr::{self, Encoding, Location, Operation, Operations};
+//!
+//! let encoding = Encoding { address_size: 8, offset_size: 4 };
+//!
+//! // DW_OP_breg6 -16; DW_OP_deref
+//! let expression = [0x76, 0x70, 0x06];
+//! let ops: Vec<_> = Operations::new(&expression, encoding)
+//!     .collect::<Result<_, _>>()
+//!     .unwrap();
+//! assert_eq!(ops, [(0, Operation::Breg(6, -16)), (2, Operation::Deref)]);
+//!
+//! let location = expr::evaluate(
+//!     &expression,
+//!     encoding,
+//!     |register| if register == 6 { Some(0x7ff0) } else { None },
+//!     |address, _size| if address == 0x7fe0 { Some(0x1234) } else { None },
+//! );
+//! assert_eq!(location, Ok(Location::Memory(0x1234)));
+//! ```
+
+use super::super::reader::{self, Leb128Reader};
+use std::fmt;
+
+/// The maximum number of operations `evaluate` executes before giving up,
+/// which stops expressions that branch backwards from looping forever.
+pub const MAX_STEPS: usize = 1 << 16;
+
+/// An error that occurred while disassembling or evaluating an expression.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// An opcode or operand could not be read.
+    Read(reader::Error),
+    /// An opcode was not recognized. Carries the offset and opcode.
+    UnknownOpcode(usize, u8),
+    /// An address or offset operand was wider than eight bytes. Carries the
+    /// offset of the operation and the width.
+    InvalidSize(usize, u8),
+    /// An operation is not supported by `evaluate`. Carries the offset.
+    Unsupported(usize),
+    /// An operation needed more values than were on the stack, or the
+    /// expression left no value on the stack. Carries the offset.
+    StackUnderflow(usize),
+    /// A division or modulus by zero. Carries the offset.
+    DivisionByZero(usize),
+    /// A branch did not land on an operation. Carries the offset of the
+    /// branch.
+    InvalidBranch(usize),
+    /// A register, implicit value or stack value location description was
+    /// not the last operation. Carries the offset.
+    MisplacedLocation(usize),
+    /// The register callback had no value for a register. Carries the offset
+    /// and register.
+    RegisterUnavailable(usize, u64),
+    /// The memory callback had no value for an address. Carries the offset
+    /// and address.
+    MemoryUnavailable(usize, u64),
+    /// Evaluation ran for more than `MAX_STEPS` operations.
+    StepLimit,
+}
+
+impl From<reader::Error> for Error {
+    fn from(e: reader::Error) -> Self {
+        Error::Read(e)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match *self {
+            Error::Read(ref e) => write!(f, "leb128::dwarf::expr::Error: {}", e),
+            Error::UnknownOpcode(offset, opcode) => write!(
+                f,
+                "leb128::dwarf::expr::Error: Unknown opcode {:#04x} at offset {:#x}",
+                opcode, offset
+            ),
+            Error::InvalidSize(offset, size) => write!(
+                f,
+                "leb128::dwarf::expr::Error: Invalid operand size {} at offset {:#x}",
+                size, offset
+            ),
+            Error::Unsupported(offset) => write!(
+                f,
+                "leb128::dwarf::expr::Error: Unsupported operation at offset {:#x}",
+                offset
+            ),
+            Error::StackUnderflow(offset) => write!(
+                f,
+                "leb128::dwarf::expr::Error: Stack underflow at offset {:#x}",
+                offset
+            ),
+            Error::DivisionByZero(offset) => write!(
+                f,
+                "leb128::dwarf::expr::Error: Division by zero at offset {:#x}",
+                offset
+            ),
+            Error::InvalidBranch(offset) => write!(
+                f,
+                "leb128::dwarf::expr::Error: Invalid branch target at offset {:#x}",
+                offset
+            ),
+            Error::MisplacedLocation(offset) => write!(
+                f,
+                "leb128::dwarf::expr::Error: Location description is not last at offset {:#x}",
+                offset
+            ),
+            Error::RegisterUnavailable(offset, register) => write!(
+                f,
+                "leb128::dwarf::expr::Error: Register {} unavailable at offset {:#x}",
+                register, offset
+            ),
+            Error::MemoryUnavailable(offset, address) => write!(
+                f,
+                "leb128::dwarf::expr::Error: Memory at {:#x} unavailable at offset {:#x}",
+                address, offset
+            ),
+            Error::StepLimit => write!(
+                f,
+                "leb128::dwarf::expr::Error: Evaluation exceeded {} steps",
+                MAX_STEPS
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match *self {
+            Error::Read(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// The sizes of the fixed-width operands of an expression, which come from
+/// the unit the expression belongs to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Encoding {
+    /// The size of a target address in bytes, used by `DW_OP_addr` and as
+    /// the width of stack values.
+    pub address_size: u8,
+    /// The size of a section offset in bytes: 4 for 32-bit DWARF and 8 for
+    /// 64-bit DWARF.
+    pub offset_size: u8,
+}
+
+/// A disassembled operation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Operation<'a> {
+    /// `DW_OP_addr`: push an address.
+    Addr(u64),
+    /// `DW_OP_deref`: replace the top of the stack with the address-sized value it points to.
+    Deref,
+    /// `DW_OP_const1u`: push a constant.
+    Const1u(u8),
+    /// `DW_OP_const1s`: push a constant.
+    Const1s(i8),
+    /// `DW_OP_const2u`: push a constant.
+    Const2u(u16),
+    /// `DW_OP_const2s`: push a constant.
+    Const2s(i16),
+    /// `DW_OP_const4u`: push a constant.
+    Const4u(u32),
+    /// `DW_OP_const4s`: push a constant.
+    Const4s(i32),
+    /// `DW_OP_const8u`: push a constant.
+    Const8u(u64),
+    /// `DW_OP_const8s`: push a constant.
+    Const8s(i64),
+    /// `DW_OP_constu`: push a ULEB128 constant.
+    Constu(u64),
+    /// `DW_OP_consts`: push an SLEB128 constant.
+    Consts(i64),
+    /// `DW_OP_dup`.
+    Dup,
+    /// `DW_OP_drop`.
+    Drop,
+    /// `DW_OP_over`.
+    Over,
+    /// `DW_OP_pick`: push a copy of the entry at this index from the top.
+    Pick(u8),
+    /// `DW_OP_swap`.
+    Swap,
+    /// `DW_OP_rot`.
+    Rot,
+    /// `DW_OP_xderef`.
+    Xderef,
+    /// `DW_OP_abs`.
+    Abs,
+    /// `DW_OP_and`.
+    And,
+    /// `DW_OP_div`.
+    Div,
+    /// `DW_OP_minus`.
+    Minus,
+    /// `DW_OP_mod`.
+    Mod,
+    /// `DW_OP_mul`.
+    Mul,
+    /// `DW_OP_neg`.
+    Neg,
+    /// `DW_OP_not`.
+    Not,
+    /// `DW_OP_or`.
+    Or,
+    /// `DW_OP_plus`.
+    Plus,
+    /// `DW_OP_plus_uconst`: add a ULEB128 constant to the top of the stack.
+    PlusUconst(u64),
+    /// `DW_OP_shl`.
+    Shl,
+    /// `DW_OP_shr`.
+    Shr,
+    /// `DW_OP_shra`.
+    Shra,
+    /// `DW_OP_xor`.
+    Xor,
+    /// `DW_OP_bra`: branch by this many bytes if the popped value is not zero.
+    Bra(i16),
+    /// `DW_OP_eq`.
+    Eq,
+    /// `DW_OP_ge`.
+    Ge,
+    /// `DW_OP_gt`.
+    Gt,
+    /// `DW_OP_le`.
+    Le,
+    /// `DW_OP_lt`.
+    Lt,
+    /// `DW_OP_ne`.
+    Ne,
+    /// `DW_OP_skip`: branch by this many bytes.
+    Skip(i16),
+    /// `DW_OP_lit0` to `DW_OP_lit31`: push the literal.
+    Lit(u8),
+    /// `DW_OP_reg0` to `DW_OP_reg31`: the value is in the register.
+    Reg(u8),
+    /// `DW_OP_breg0` to `DW_OP_breg31`: push the register plus an SLEB128 offset.
+    Breg(u8, i64),
+    /// `DW_OP_regx`: the value is in the register.
+    Regx(u64),
+    /// `DW_OP_fbreg`: push the frame base plus an SLEB128 offset.
+    Fbreg(i64),
+    /// `DW_OP_bregx`: push the register plus an SLEB128 offset.
+    Bregx(u64, i64),
+    /// `DW_OP_piece`: the previous location holds this many bytes of the value.
+    Piece(u64),
+    /// `DW_OP_deref_size`: like `Deref`, reading this many bytes.
+    DerefSize(u8),
+    /// `DW_OP_xderef_size`.
+    XderefSize(u8),
+    /// `DW_OP_nop`.
+    Nop,
+    /// `DW_OP_push_object_address`.
+    PushObjectAddress,
+    /// `DW_OP_call2`: call the expression of the entry at this unit offset.
+    Call2(u16),
+    /// `DW_OP_call4`: call the expression of the entry at this unit offset.
+    Call4(u32),
+    /// `DW_OP_call_ref`: call the expression of the entry at this section offset.
+    CallRef(u64),
+    /// `DW_OP_form_tls_address`.
+    FormTlsAddress,
+    /// `DW_OP_call_frame_cfa`.
+    CallFrameCfa,
+    /// `DW_OP_bit_piece`.
+    BitPiece {
+        /// The size of the piece in bits.
+        size: u64,
+        /// The offset of the piece in bits.
+        offset: u64,
+    },
+    /// `DW_OP_implicit_value`: the value is these bytes.
+    ImplicitValue(&'a [u8]),
+    /// `DW_OP_stack_value`: the value is the top of the stack.
+    StackValue,
+    /// `DW_OP_implicit_pointer`.
+    ImplicitPointer {
+        /// The section offset of the entry describing the pointed-to value.
+        die_offset: u64,
+        /// The offset into that value.
+        byte_offset: i64,
+    },
+    /// `DW_OP_addrx`: push the address at this index of `.debug_addr`.
+    Addrx(u64),
+    /// `DW_OP_constx`: push the constant at this index of `.debug_addr`.
+    Constx(u64),
+    /// `DW_OP_entry_value`: push the value this expression had on entry.
+    EntryValue(&'a [u8]),
+    /// `DW_OP_const_type`.
+    ConstType {
+        /// The unit offset of the base type entry.
+        type_offset: u64,
+        /// The bytes of the constant.
+        value: &'a [u8],
+    },
+    /// `DW_OP_regval_type`.
+    RegvalType {
+        /// The register.
+        register: u64,
+        /// The unit offset of the base type entry.
+        type_offset: u64,
+    },
+    /// `DW_OP_deref_type`.
+    DerefType {
+        /// The number of bytes to read.
+        size: u8,
+        /// The unit offset of the base type entry.
+        type_offset: u64,
+    },
+    /// `DW_OP_xderef_type`.
+    XderefType {
+        /// The number of bytes to read.
+        size: u8,
+        /// The unit offset of the base type entry.
+        type_offset: u64,
+    },
+    /// `DW_OP_convert`: convert to the base type at this unit offset.
+    Convert(u64),
+    /// `DW_OP_reinterpret`: reinterpret as the base type at this unit offset.
+    Reinterpret(u64),
+}
+
+/// An iterator over the operations of an expression, yielding each with its
+/// offset.
+///
+/// Iteration stops after the first error.
+#[derive(Clone, Debug)]
+pub struct Operations<'a> {
+    reader: Leb128Reader<'a>,
+    encoding: Encoding,
+    failed: bool,
+}
+
+impl<'a> Operations<'a> {
+    /// Iterate over the operations in `expr`.
+    pub fn new(expr: &'a [u8], encoding: Encoding) -> Operations<'a> {
+        Operations {
+            reader: Leb128Reader::new(expr),
+            encoding,
+            failed: false,
+        }
+    }
+
+    /// Read a little-endian operand of `size` bytes for the operation at
+    /// `offset`.
+    fn read_fixed(&mut self, offset: usize, size: u8) -> Result<u64, Error> {
+        if size > 8 {
+            return Err(Error::InvalidSize(offset, size));
+        }
+        let bytes = self.reader.read_bytes(size as usize)?;
+        Ok(bytes
+            .iter()
+            .rev()
+            .fold(0, |val, &byte| (val << 8) | u64::from(byte)))
+    }
+
+    fn read_block(&mut self) -> Result<&'a [u8], Error> {
+        let len_offset = self.reader.offset();
+        let len = self.reader.read_uleb128()?;
+        if len > self.reader.remaining() as u64 {
+            return Err(reader::Error::new(len_offset, reader::ErrorKind::UnexpectedEof).into());
+        }
+        Ok(self.reader.read_bytes(len as usize)?)
+    }
+
+    fn read_operation(&mut self) -> Result<Operation<'a>, Error> {
+        let offset = self.reader.offset();
+        let opcode = self.reader.read_u8()?;
+
+        Ok(match opcode {
+            0x03 => Operation::Addr(self.read_fixed(offset, self.encoding.address_size)?),
+            0x06 => Operation::Deref,
+            0x08 => Operation::Const1u(self.read_fixed(offset, 1)? as u8),
+            0x09 => Operation::Const1s(self.read_fixed(offset, 1)? as i8),
+            0x0a => Operation::Const2u(self.read_fixed(offset, 2)? as u16),
+            0x0b => Operation::Const2s(self.read_fixed(offset, 2)? as i16),
+            0x0c => Operation::Const4u(self.read_fixed(offset, 4)? as u32),
+            0x0d => Operation::Const4s(self.read_fixed(offset, 4)? as i32),
+            0x0e => Operation::Const8u(self.read_fixed(offset, 8)?),
+            0x0f => Operation::Const8s(self.read_fixed(offset, 8)? as i64),
+            0x10 => Operation::Constu(self.reader.read_uleb128()?),
+            0x11 => Operation::Consts(self.reader.read_sleb128()?),
+            0x12 => Operation::Dup,
+            0x13 => Operation::Drop,
+            0x14 => Operation::Over,
+            0x15 => Operation::Pick(self.reader.read_u8()?),
+            0x16 => Operation::Swap,
+            0x17 => Operation::Rot,
+            0x18 => Operation::Xderef,
+            0x19 => Operation::Abs,
+            0x1a => Operation::And,
+            0x1b => Operation::Div,
+            0x1c => Operation::Minus,
+            0x1d => Operation::Mod,
+            0x1e => Operation::Mul,
+            0x1f => Operation::Neg,
+            0x20 => Operation::Not,
+            0x21 => Operation::Or,
+            0x22 => Operation::Plus,
+            0x23 => Operation::PlusUconst(self.reader.read_uleb128()?),
+            0x24 => Operation::Shl,
+            0x25 => Operation::Shr,
+            0x26 => Operation::Shra,
+            0x27 => Operation::Xor,
+            0x28 => Operation::Bra(self.read_fixed(offset, 2)? as i16),
+            0x29 => Operation::Eq,
+            0x2a => Operation::Ge,
+            0x2b => Operation::Gt,
+            0x2c => Operation::Le,
+            0x2d => Operation::Lt,
+            0x2e => Operation::Ne,
+            0x2f => Operation::Skip(self.read_fixed(offset, 2)? as i16),
+            0x30..=0x4f => Operation::Lit(opcode - 0x30),
+            0x50..=0x6f => Operation::Reg(opcode - 0x50),
+            0x70..=0x8f => Operation::Breg(opcode - 0x70, self.reader.read_sleb128()?),
+            0x90 => Operation::Regx(self.reader.read_uleb128()?),
+            0x91 => Operation::Fbreg(self.reader.read_sleb128()?),
+            0x92 => Operation::Bregx(self.reader.read_uleb128()?, self.reader.read_sleb128()?),
+            0x93 => Operation::Piece(self.reader.read_uleb128()?),
+            0x94 => Operation::DerefSize(self.reader.read_u8()?),
+            0x95 => Operation::XderefSize(self.reader.read_u8()?),
+            0x96 => Operation::Nop,
+            0x97 => Operation::PushObjectAddress,
+            0x98 => Operation::Call2(self.read_fixed(offset, 2)? as u16),
+            0x99 => Operation::Call4(self.read_fixed(offset, 4)? as u32),
+            0x9a => Operation::CallRef(self.read_fixed(offset, self.encoding.offset_size)?),
+            0x9b => Operation::FormTlsAddress,
+            0x9c => Operation::CallFrameCfa,
+            0x9d => Operation::BitPiece {
+                size: self.reader.read_uleb128()?,
+                offset: self.reader.read_uleb128()?,
+            },
+            0x9e => Operation::ImplicitValue(self.read_block()?),
+            0x9f => Operation::StackValue,
+            0xa0 => Operation::ImplicitPointer {
+                die_offset: self.read_fixed(offset, self.encoding.offset_size)?,
+                byte_offset: self.reader.read_sleb128()?,
+            },
+            0xa1 => Operation::Addrx(self.reader.read_uleb128()?),
+            0xa2 => Operation::Constx(self.reader.read_uleb128()?),
+            0xa3 => Operation::EntryValue(self.read_block()?),
+            0xa4 => {
+                let type_offset = self.reader.read_uleb128()?;
+                let len = self.reader.read_u8()?;
+                Operation::ConstType {
+                    type_offset,
+                    value: self.reader.read_bytes(len as usize)?,
+                }
+            }
+            0xa5 => Operation::RegvalType {
+                register: self.reader.read_uleb128()?,
+                type_offset: self.reader.read_uleb128()?,
+            },
+            0xa6 => Operation::DerefType {
+                size: self.reader.read_u8()?,
+                type_offset: self.reader.read_uleb128()?,
+            },
+            0xa7 => Operation::XderefType {
+                size: self.reader.read_u8()?,
+                type_offset: self.reader.read_uleb128()?,
+            },
+            0xa8 => Operation::Convert(self.reader.read_uleb128()?),
+            0xa9 => Operation::Reinterpret(self.reader.read_uleb128()?),
+            _ => return Err(Error::UnknownOpcode(offset, opcode)),
+        })
+    }
+}
+
+impl<'a> Iterator for Operations<'a> {
+    type Item = Result<(usize, Operation<'a>), Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed || self.reader.is_empty() {
+            return None;
+        }
+        let offset = self.reader.offset();
+        let result = self.read_operation().map(|op| (offset, op));
+        self.failed = result.is_err();
+        Some(result)
+    }
+}
+
+/// The result of evaluating an expression.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Location<'a> {
+    /// The expression was empty, so the value is unavailable, for example
+    /// because it was optimized out.
+    Empty,
+    /// The value is in memory at this address.
+    Memory(u64),
+    /// The value is in this register.
+    Register(u64),
+    /// The value is this number, from `DW_OP_stack_value`.
+    Value(u64),
+    /// The value is these bytes, from `DW_OP_implicit_value`.
+    Implicit(&'a [u8]),
+}
+
+fn pop(stack: &mut Vec<u64>, offset: usize) -> Result<u64, Error> {
+    stack.pop().ok_or(Error::StackUnderflow(offset))
+}
+
+/// Evaluate `expr` on an initially empty stack. `register` returns the value
+/// of a DWARF register number, and `memory` reads a little-endian value of
+/// the given size in bytes from an address; both return `None` when the
+/// value is unavailable.
+///
+/// Stack values are `encoding.address_size` bytes wide, and arithmetic wraps
+/// at that width. Operations that need more context than registers and
+/// memory, such as `DW_OP_fbreg`, `DW_OP_call*`, `DW_OP_piece` and the typed
+/// operations, are rejected with `Error::Unsupported`.
+pub fn evaluate<'a, R, M>(
+    expr: &'a [u8],
+    encoding: Encoding,
+    mut register: R,
+    mut memory: M,
+) -> Result<Location<'a>, Error>
+where
+    R: FnMut(u64) -> Option<u64>,
+    M: FnMut(u64, u8) -> Option<u64>,
+{
+    let ops = Operations::new(expr, encoding).collect::<Result<Vec<_>, _>>()?;
+    let bits = 8 * u32::from(encoding.address_size.clamp(1, 8));
+    let mask = u64::MAX >> (64 - bits);
+    let signed = |val: u64| ((val << (64 - bits)) as i64) >> (64 - bits);
+
+    let mut stack: Vec<u64> = vec![];
+    let mut pc = 0;
+    let mut steps = 0;
+    while pc < ops.len() {
+        steps += 1;
+        if steps > MAX_STEPS {
+            return Err(Error::StepLimit);
+        }
+        let (offset, op) = ops[pc];
+        let next_offset = ops.get(pc + 1).map_or(expr.len(), |&(offset, _)| offset);
+        pc += 1;
+        let is_last = pc == ops.len();
+
+        let val = match op {
+            Operation::Addr(address) => address,
+            Operation::Deref => {
+                let address = pop(&mut stack, offset)?;
+                memory(address, encoding.address_size)
+                    .ok_or(Error::MemoryUnavailable(offset, address))?
+            }
+            Operation::DerefSize(size) => {
+                let address = pop(&mut stack, offset)?;
+                memory(address, size).ok_or(Error::MemoryUnavailable(offset, address))?
+            }
+            Operation::Const1u(val) => u64::from(val),
+            Operation::Const1s(val) => val as u64,
+            Operation::Const2u(val) => u64::from(val),
+            Operation::Const2s(val) => val as u64,
+            Operation::Const4u(val) => u64::from(val),
+            Operation::Const4s(val) => val as u64,
+            Operation::Const8u(val) | Operation::Constu(val) => val,
+            Operation::Const8s(val) | Operation::Consts(val) => val as u64,
+            Operation::Lit(val) => u64::from(val),
+            Operation::Dup | Operation::Over | Operation::Pick(_) => {
+                let depth = match op {
+                    Operation::Dup => 0,
+                    Operation::Over => 1,
+                    Operation::Pick(index) => index as usize,
+                    _ => unreachable!(),
+                };
+                *stack
+                    .iter()
+                    .rev()
+                    .nth(depth)
+                    .ok_or(Error::StackUnderflow(offset))?
+            }
+            Operation::Drop => {
+                pop(&mut stack, offset)?;
+                continue;
+            }
+            Operation::Swap | Operation::Rot => {
+                let n = if op == Operation::Swap { 2 } else { 3 };
+                let len = stack.len();
+                if len < n {
+                    return Err(Error::StackUnderflow(offset));
+                }
+                stack[len - n..].rotate_right(1);
+                continue;
+            }
+            Operation::Abs => signed(pop(&mut stack, offset)?).wrapping_abs() as u64,
+            Operation::Neg => signed(pop(&mut stack, offset)?).wrapping_neg() as u64,
+            Operation::Not => !pop(&mut stack, offset)?,
+            Operation::PlusUconst(addend) => pop(&mut stack, offset)?.wrapping_add(addend),
+            Operation::And
+            | Operation::Div
+            | Operation::Minus
+            | Operation::Mod
+            | Operation::Mul
+            | Operation::Or
+            | Operation::Plus
+            | Operation::Shl
+            | Operation::Shr
+            | Operation::Shra
+            | Operation::Xor
+            | Operation::Eq
+            | Operation::Ge
+            | Operation::Gt
+            | Operation::Le
+            | Operation::Lt
+            | Operation::Ne => {
+                let b = pop(&mut stack, offset)?;
+                let a = pop(&mut stack, offset)?;
+                match op {
+                    Operation::And => a & b,
+                    Operation::Div if b == 0 => return Err(Error::DivisionByZero(offset)),
+                    Operation::Div => signed(a).wrapping_div(signed(b)) as u64,
+                    Operation::Minus => a.wrapping_sub(b),
+                    Operation::Mod if b == 0 => return Err(Error::DivisionByZero(offset)),
+                    Operation::Mod => a % b,
+                    Operation::Mul => a.wrapping_mul(b),
+                    Operation::Or => a | b,
+                    Operation::Plus => a.wrapping_add(b),
+                    Operation::Shl => a.checked_shl(b.min(64) as u32).unwrap_or(0),
+                    Operation::Shr => a.checked_shr(b.min(64) as u32).unwrap_or(0),
+                    Operation::Shra => (signed(a) >> b.min(63)) as u64,
+                    Operation::Xor => a ^ b,
+                    Operation::Eq => u64::from(signed(a) == signed(b)),
+                    Operation::Ge => u64::from(signed(a) >= signed(b)),
+                    Operation::Gt => u64::from(signed(a) > signed(b)),
+                    Operation::Le => u64::from(signed(a) <= signed(b)),
+                    Operation::Lt => u64::from(signed(a) < signed(b)),
+                    Operation::Ne => u64::from(signed(a) != signed(b)),
+                    _ => unreachable!(),
+                }
+            }
+            Operation::Bra(_) | Operation::Skip(_) => {
+                let delta = match op {
+                    Operation::Bra(delta) if pop(&mut stack, offset)? != 0 => delta,
+                    Operation::Skip(delta) => delta,
+                    _ => continue,
+                };
+                let target = next_offset as i64 + i64::from(delta);
+                pc = if target == expr.len() as i64 {
+                    ops.len()
+                } else {
+                    ops.binary_search_by_key(&target, |&(offset, _)| offset as i64)
+                        .map_err(|_| Error::InvalidBranch(offset))?
+                };
+                continue;
+            }
+            Operation::Breg(reg, displacement) => {
+                let reg = u64::from(reg);
+                let val = register(reg).ok_or(Error::RegisterUnavailable(offset, reg))?;
+                val.wrapping_add(displacement as u64)
+            }
+            Operation::Bregx(reg, displacement) => {
+                let val = register(reg).ok_or(Error::RegisterUnavailable(offset, reg))?;
+                val.wrapping_add(displacement as u64)
+            }
+            Operation::Nop => continue,
+            Operation::Reg(_)
+            | Operation::Regx(_)
+            | Operation::StackValue
+            | Operation::ImplicitValue(_)
+                if !is_last =>
+            {
+                return Err(Error::MisplacedLocation(offset))
+            }
+            Operation::Reg(reg) => return Ok(Location::Register(u64::from(reg))),
+            Operation::Regx(reg) => return Ok(Location::Register(reg)),
+            Operation::StackValue => {
+                return Ok(Location::Value(pop(&mut stack, offset)?));
+            }
+            Operation::ImplicitValue(bytes) => return Ok(Location::Implicit(bytes)),
+            _ => return Err(Error::Unsupported(offset)),
+        };
+        stack.push(val & mask);
+    }
+
+    if ops.is_empty() {
+        return Ok(Location::Empty);
+    }
+    pop(&mut stack, expr.len()).map(Location::Memory)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ENCODING: Encoding = Encoding {
+        address_size: 8,
+        offset_size: 4,
+    };
+
+    fn disassemble(expr: &[u8], encoding: Encoding) -> Vec<(usize, Operation<'_>)> {
+        Operations::new(expr, encoding)
+            .collect::<Result<_, _>>()
+            .unwrap()
+    }
+
+    fn eval(expr: &[u8]) -> Result<Location<'_>, Error> {
+        evaluate(expr, ENCODING, |reg| Some(reg * 0x100), |_, _| None)
+    }
+
+    #[test]
+    fn test_disassemble() {
+        #[rustfmt::skip]
+        let expr = [
+            0x03, 0x78, 0x56, 0x34, 0x12,   // DW_OP_addr 0x12345678
+            0x09, 0xff,                     // DW_OP_const1s -1
+            0x10, 0xe5, 0x8e, 0x26,         // DW_OP_constu 624485
+            0x11, 0x80, 0x7f,               // DW_OP_consts -128
+            0x23, 0x80, 0x01,               // DW_OP_plus_uconst 128
+            0x2f, 0xfd, 0xff,               // DW_OP_skip -3
+            0x35, 0x55,                     // DW_OP_lit5 DW_OP_reg5
+            0x92, 0x21, 0x7c,               // DW_OP_bregx 33 -4
+            0x93, 0x04,                     // DW_OP_piece 4
+            0x9d, 0x03, 0x05,               // DW_OP_bit_piece 3 5
+            0x9e, 0x02, 0xaa, 0xbb,         // DW_OP_implicit_value
+            0xa0, 0x10, 0x00, 0x00, 0x00, 0x08, // DW_OP_implicit_pointer
+            0xa4, 0x2a, 0x01, 0x07,         // DW_OP_const_type
+            0x9f,                           // DW_OP_stack_value
+        ];
+        let encoding = Encoding {
+            address_size: 4,
+            offset_size: 4,
+        };
+        assert_eq!(
+            disassemble(&expr, encoding),
+            vec![
+                (0, Operation::Addr(0x1234_5678)),
+                (5, Operation::Const1s(-1)),
+                (7, Operation::Constu(624_485)),
+                (11, Operation::Consts(-128)),
+                (14, Operation::PlusUconst(128)),
+                (17, Operation::Skip(-3)),
+                (20, Operation::Lit(5)),
+                (21, Operation::Reg(5)),
+                (22, Operation::Bregx(33, -4)),
+                (25, Operation::Piece(4)),
+                (27, Operation::BitPiece { size: 3, offset: 5 }),
+                (30, Operation::ImplicitValue(&[0xaa, 0xbb])),
+                (
+                    34,
+                    Operation::ImplicitPointer {
+                        die_offset: 0x10,
+                        byte_offset: 8,
+                    }
+                ),
+                (
+                    40,
+                    Operation::ConstType {
+                        type_offset: 0x2a,
+                        value: &[0x07],
+                    }
+                ),
+                (44, Operation::StackValue),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_disassemble_errors() {
+        let mut ops = Operations::new(&[0x96, 0xff], ENCODING);
+        assert_eq!(ops.next(), Some(Ok((0, Operation::Nop))));
+        assert_eq!(ops.next(), Some(Err(Error::UnknownOpcode(1, 0xff))));
+        assert_eq!(ops.next(), None);
+
+        assert_eq!(
+            Operations::new(&[0x9e, 0x03, 0x00], ENCODING).next(),
+            Some(Err(Error::Read(reader::Error::new(
+                1,
+                reader::ErrorKind::UnexpectedEof
+            ))))
+        );
+        let wide = Encoding {
+            address_size: 16,
+            offset_size: 4,
+        };
+        assert_eq!(
+            Operations::new(&[0x03], wide).next(),
+            Some(Err(Error::InvalidSize(0, 16)))
+        );
+    }
+
+    #[test]
+    fn test_evaluate_arithmetic() {
+        // 5 - 3
+        assert_eq!(eval(&[0x35, 0x33, 0x1c, 0x9f]), Ok(Location::Value(2)));
+        // -7 / 2, -7 mod 2 (unsigned), -7 >> 1 (arithmetic)
+        assert_eq!(
+            eval(&[0x11, 0x79, 0x32, 0x1b, 0x9f]),
+            Ok(Location::Value(-3i64 as u64))
+        );
+        assert_eq!(
+            eval(&[0x11, 0x79, 0x32, 0x1d, 0x9f]),
+            Ok(Location::Value(1))
+        );
+        assert_eq!(
+            eval(&[0x11, 0x79, 0x31, 0x26, 0x9f]),
+            Ok(Location::Value(-4i64 as u64))
+        );
+        // -1 < 0 is signed.
+        assert_eq!(
+            eval(&[0x11, 0x7f, 0x30, 0x2d, 0x9f]),
+            Ok(Location::Value(1))
+        );
+        // lit1 lit2 lit3 rot: the top moves to third.
+        assert_eq!(
+            eval(&[0x31, 0x32, 0x33, 0x17, 0x9f]),
+            Ok(Location::Value(2))
+        );
+        assert_eq!(
+            eval(&[0x31, 0x32, 0x33, 0x17, 0x13, 0x13, 0x9f]),
+            Ok(Location::Value(3))
+        );
+        // pick 2
+        assert_eq!(
+            eval(&[0x31, 0x32, 0x33, 0x15, 0x02, 0x9f]),
+            Ok(Location::Value(1))
+        );
+
+        // Arithmetic wraps at the address size.
+        let encoding = Encoding {
+            address_size: 4,
+            offset_size: 4,
+        };
+        assert_eq!(
+            evaluate(&[0x30, 0x31, 0x1c, 0x9f], encoding, |_| None, |_, _| None),
+            Ok(Location::Value(0xffff_ffff))
+        );
+    }
+
+    #[test]
+    fn test_evaluate_locations() {
+        assert_eq!(eval(&[]), Ok(Location::Empty));
+        assert_eq!(eval(&[0x55]), Ok(Location::Register(5)));
+        assert_eq!(eval(&[0x90, 0x80, 0x01]), Ok(Location::Register(128)));
+        assert_eq!(eval(&[0x9e, 0x01, 0x2a]), Ok(Location::Implicit(&[0x2a])));
+        // DW_OP_breg2 8
+        assert_eq!(eval(&[0x72, 0x08]), Ok(Location::Memory(0x208)));
+        // DW_OP_bregx 3 -1; DW_OP_deref_size 2
+        let location = evaluate(
+            &[0x92, 0x03, 0x7f, 0x94, 0x02],
+            ENCODING,
+            |reg| Some(reg * 0x100),
+            |address, size| {
+                assert_eq!((address, size), (0x2ff, 2));
+                Some(0x1000)
+            },
+        );
+        assert_eq!(location, Ok(Location::Memory(0x1000)));
+    }
+
+    #[test]
+    fn test_evaluate_branches() {
+        #[rustfmt::skip]
+        let countdown = [
+            0x33,             // 0: lit3
+            0x31,             // 1: lit1
+            0x1c,             // 2: minus
+            0x12,             // 3: dup
+            0x28, 0xfa, 0xff, // 4: bra -6 (to 1)
+            0x9f,             // 7: stack_value
+        ];
+        assert_eq!(eval(&countdown), Ok(Location::Value(0)));
+
+        // Skipping to the end of the expression.
+        assert_eq!(
+            eval(&[0x31, 0x2f, 0x01, 0x00, 0x9f]),
+            Ok(Location::Memory(1))
+        );
+
+        assert_eq!(eval(&[0x2f, 0xfd, 0xff]), Err(Error::StepLimit));
+        assert_eq!(
+            eval(&[0x2f, 0x02, 0x00, 0x96]),
+            Err(Error::InvalidBranch(0))
+        );
+    }
+
+    #[test]
+    fn test_evaluate_errors() {
+        assert_eq!(eval(&[0x31, 0x22]), Err(Error::StackUnderflow(1)));
+        assert_eq!(eval(&[0x96]), Err(Error::StackUnderflow(1)));
+        assert_eq!(eval(&[0x31, 0x30, 0x1b]), Err(Error::DivisionByZero(2)));
+        assert_eq!(eval(&[0x55, 0x96]), Err(Error::MisplacedLocation(0)));
+        assert_eq!(eval(&[0x91, 0x08]), Err(Error::Unsupported(0)));
+        assert_eq!(eval(&[0x31, 0x06]), Err(Error::MemoryUnavailable(1, 1)));
+        assert_eq!(
+            evaluate(&[0x75, 0x00], ENCODING, |_| None, |_, _| None),
+            Err(Error::RegisterUnavailable(0, 5))
+        );
+        assert_eq!(eval(&[0x31, 0xff]), Err(Error::UnknownOpcode(1, 0xff)));
+    }
+}
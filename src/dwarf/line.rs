@@ -0,0 +1,980 @@
+//! Parse `.debug_line` line number programs and run them to produce the line
+//! table.
+//!
+//! A line program is a header, describing the program's parameters and the
+//! directories and files it refers to, followed by a bytecode that drives a
+//! state machine. Most opcodes take ULEB128 or SLEB128 operands. Versions 2
+//! to 5 of the format are supported.
+//!
+//! ```
+//! use leb128::dwarf::line;
+//!
+//! #[rustfmt::skip]
+//! let section = [
+//!     0x23, 0x00, 0x00, 0x00,             // unit_length
+//!     0x02, 0x00,                         // version
+//!     0x1a, 0x00, 0x00, 0x00,             // header_length
+//!     0x01, 0x01, 0xfb, 0x0e, 0x0d,       // parameters, with line_base -5
+//!     0x00, 0x01, 0x01, 0x01, 0x01, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x01,
+//!     0x00,                               // no include directories
+//!     b'a', b'.', b'c', 0x00, 0x00, 0x00, 0x00,
+//!     0x00,                               // end of file names
+//!     // DW_LNS_advance_line 9; DW_LNS_copy
+//!     0x03, 0x09, 0x01,
+//! ];
+//! let header = line::parse_header(&section, 0).unwrap();
+//! assert_eq!(header.version, 2);
+//! assert_eq!(header.file_names[0].path, line::Path::String(b"a.c"));
+//!
+//! let rows: Vec<_> = header.rows().collect::<Result<_, _>>().unwrap();
+//! assert_eq!(rows[0].line, 10);
+//! ```
+
+use super::super::reader::{self, ErrorKind, Leb128Reader};
+use std::fmt;
+
+/// Append a row to the table.
+pub const DW_LNS_COPY: u8 = 0x01;
+/// Advance the address by a ULEB128 number of operations.
+pub const DW_LNS_ADVANCE_PC: u8 = 0x02;
+/// Advance the line by an SLEB128 number.
+pub const DW_LNS_ADVANCE_LINE: u8 = 0x03;
+/// Set the file to a ULEB128 index.
+pub const DW_LNS_SET_FILE: u8 = 0x04;
+/// Set the column to a ULEB128 number.
+pub const DW_LNS_SET_COLUMN: u8 = 0x05;
+/// Toggle `is_stmt`.
+pub const DW_LNS_NEGATE_STMT: u8 = 0x06;
+/// Set `basic_block`.
+pub const DW_LNS_SET_BASIC_BLOCK: u8 = 0x07;
+/// Advance the address as special opcode 255 would, without adding a row.
+pub const DW_LNS_CONST_ADD_PC: u8 = 0x08;
+/// Advance the address by a `u16` operand.
+pub const DW_LNS_FIXED_ADVANCE_PC: u8 = 0x09;
+/// Set `prologue_end`.
+pub const DW_LNS_SET_PROLOGUE_END: u8 = 0x0a;
+/// Set `epilogue_begin`.
+pub const DW_LNS_SET_EPILOGUE_BEGIN: u8 = 0x0b;
+/// Set the ISA to a ULEB128 number.
+pub const DW_LNS_SET_ISA: u8 = 0x0c;
+
+/// Append a row ending the sequence, and reset the state machine.
+pub const DW_LNE_END_SEQUENCE: u8 = 0x01;
+/// Set the address to the operand, whose size is given by the opcode length.
+pub const DW_LNE_SET_ADDRESS: u8 = 0x02;
+/// Define an extra file (DWARF 2 to 4), which the state machine skips.
+pub const DW_LNE_DEFINE_FILE: u8 = 0x03;
+/// Set the discriminator to a ULEB128 number.
+pub const DW_LNE_SET_DISCRIMINATOR: u8 = 0x04;
+
+const DW_LNCT_PATH: u64 = 0x1;
+const DW_LNCT_DIRECTORY_INDEX: u64 = 0x2;
+const DW_LNCT_TIMESTAMP: u64 = 0x3;
+const DW_LNCT_SIZE: u64 = 0x4;
+const DW_LNCT_MD5: u64 = 0x5;
+
+const DW_FORM_BLOCK: u64 = 0x09;
+const DW_FORM_DATA1: u64 = 0x0b;
+const DW_FORM_DATA2: u64 = 0x05;
+const DW_FORM_DATA4: u64 = 0x06;
+const DW_FORM_DATA8: u64 = 0x07;
+const DW_FORM_DATA16: u64 = 0x1e;
+const DW_FORM_LINE_STRP: u64 = 0x1f;
+const DW_FORM_STRING: u64 = 0x08;
+const DW_FORM_STRP: u64 = 0x0e;
+const DW_FORM_UDATA: u64 = 0x0f;
+
+/// An error that occurred while parsing or running a line program.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// A header field or opcode could not be read.
+    Read(reader::Error),
+    /// The unit length was one of the reserved values. Carries the offset
+    /// and length.
+    InvalidUnitLength(usize, u32),
+    /// The version was not 2 to 5. Carries the offset and version.
+    UnsupportedVersion(usize, u16),
+    /// The header's `line_range` was zero. Carries the offset of the field,
+    /// or of the opcode that needed it when running a program whose header
+    /// was built by hand.
+    InvalidLineRange(usize),
+    /// A DWARF 5 directory or file entry used a form this parser does not
+    /// understand, or a path that is not a string. Carries the offset and
+    /// form.
+    UnsupportedForm(usize, u64),
+    /// A `DW_LNE_set_address` operand was empty or wider than eight bytes.
+    /// Carries the offset of the opcode and the width.
+    InvalidAddressSize(usize, usize),
+    /// An unknown standard opcode had no entry in the header's
+    /// `standard_opcode_lengths`. Carries the offset and the opcode.
+    MissingOpcodeLength(usize, u8),
+}
+
+impl From<reader::Error> for Error {
+    fn from(e: reader::Error) -> Self {
+        Error::Read(e)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match *self {
+            Error::Read(ref e) => write!(f, "leb128::dwarf::line::Error: {}", e),
+            Error::InvalidUnitLength(offset, len) => write!(
+                f,
+                "leb128::dwarf::line::Error: Invalid unit length {:#x} at offset {:#x}",
+                len, offset
+            ),
+            Error::UnsupportedVersion(offset, version) => write!(
+                f,
+                "leb128::dwarf::line::Error: Unsupported version {} at offset {:#x}",
+                version, offset
+            ),
+            Error::InvalidLineRange(offset) => write!(
+                f,
+                "leb128::dwarf::line::Error: Zero line range at offset {:#x}",
+                offset
+            ),
+            Error::UnsupportedForm(offset, form) => write!(
+                f,
+                "leb128::dwarf::line::Error: Unsupported form {:#x} at offset {:#x}",
+                form, offset
+            ),
+            Error::InvalidAddressSize(offset, size) => write!(
+                f,
+                "leb128::dwarf::line::Error: Invalid address size {} at offset {:#x}",
+                size, offset
+            ),
+            Error::MissingOpcodeLength(offset, opcode) => write!(
+                f,
+                "leb128::dwarf::line::Error: No operand count for opcode {:#04x} at offset {:#x}",
+                opcode, offset
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match *self {
+            Error::Read(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// Read a little-endian number of `size` bytes, at most eight.
+fn read_fixed(reader: &mut Leb128Reader, size: usize) -> Result<u64, reader::Error> {
+    let bytes = reader.read_bytes(size)?;
+    Ok(bytes
+        .iter()
+        .rev()
+        .fold(0, |val, &byte| (val << 8) | u64::from(byte)))
+}
+
+/// A directory or file path.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Path<'a> {
+    /// The path is stored inline.
+    String(&'a [u8]),
+    /// The path is at this offset in `.debug_line_str`.
+    LineStrp(u64),
+    /// The path is at this offset in `.debug_str`.
+    Strp(u64),
+}
+
+/// An entry of a header's file name table.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FileEntry<'a> {
+    /// The file's path.
+    pub path: Path<'a>,
+    /// The index of the file's directory in `include_directories`. Before
+    /// DWARF 5, zero means the compilation directory and other indices are
+    /// one-based.
+    pub directory_index: u64,
+    /// The modification time, or zero if unknown.
+    pub timestamp: u64,
+    /// The size in bytes, or zero if unknown.
+    pub size: u64,
+    /// The MD5 digest of the contents, if present.
+    pub md5: Option<[u8; 16]>,
+}
+
+/// The header of a line number program.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LineProgramHeader<'a> {
+    /// The offset of the header within the section.
+    pub offset: usize,
+    /// The size of section offsets: 4 for 32-bit DWARF and 8 for 64-bit
+    /// DWARF.
+    pub offset_size: u8,
+    /// The format version.
+    pub version: u16,
+    /// The size of a target address, from DWARF 5 headers.
+    pub address_size: Option<u8>,
+    /// The size of the smallest target instruction.
+    pub minimum_instruction_length: u8,
+    /// The number of operations in a VLIW instruction, or 1.
+    pub maximum_operations_per_instruction: u8,
+    /// The initial value of `is_stmt`.
+    pub default_is_stmt: bool,
+    /// The smallest line advance of a special opcode.
+    pub line_base: i8,
+    /// The number of line advances special opcodes can encode.
+    pub line_range: u8,
+    /// The number of the first special opcode.
+    pub opcode_base: u8,
+    /// The number of ULEB128 operands of each standard opcode, starting with
+    /// opcode 1.
+    pub standard_opcode_lengths: &'a [u8],
+    /// The include directories.
+    pub include_directories: Vec<Path<'a>>,
+    /// The file names.
+    pub file_names: Vec<FileEntry<'a>>,
+    /// The offset of the program within the section.
+    pub program_offset: usize,
+    /// The program's opcodes.
+    pub program: &'a [u8],
+}
+
+/// A value of a DWARF 5 directory or file entry field.
+enum Value<'a> {
+    Bytes(&'a [u8]),
+    Number(u64),
+    Path(Path<'a>),
+}
+
+fn read_value<'a>(
+    reader: &mut Leb128Reader<'a>,
+    form: u64,
+    offset_size: u8,
+) -> Result<Value<'a>, Error> {
+    let offset = reader.offset();
+    Ok(match form {
+        DW_FORM_STRING => Value::Path(Path::String(reader.read_cstr_bytes()?)),
+        DW_FORM_LINE_STRP => Value::Path(Path::LineStrp(read_fixed(reader, offset_size as usize)?)),
+        DW_FORM_STRP => Value::Path(Path::Strp(read_fixed(reader, offset_size as usize)?)),
+        DW_FORM_UDATA => Value::Number(reader.read_uleb128()?),
+        DW_FORM_DATA1 => Value::Number(read_fixed(reader, 1)?),
+        DW_FORM_DATA2 => Value::Number(read_fixed(reader, 2)?),
+        DW_FORM_DATA4 => Value::Number(read_fixed(reader, 4)?),
+        DW_FORM_DATA8 => Value::Number(read_fixed(reader, 8)?),
+        DW_FORM_DATA16 => Value::Bytes(reader.read_bytes(16)?),
+        DW_FORM_BLOCK => {
+            let len = reader.read_uleb128()?;
+            if len > reader.remaining() as u64 {
+                return Err(reader::Error::new(offset, ErrorKind::UnexpectedEof).into());
+            }
+            Value::Bytes(reader.read_bytes(len as usize)?)
+        }
+        _ => return Err(Error::UnsupportedForm(offset, form)),
+    })
+}
+
+/// Read a DWARF 5 entry format description and the entries that follow it.
+fn read_entries<'a>(
+    reader: &mut Leb128Reader<'a>,
+    offset_size: u8,
+) -> Result<Vec<FileEntry<'a>>, Error> {
+    let format_count = reader.read_u8()?;
+    let mut format = Vec::with_capacity(format_count as usize);
+    for _ in 0..format_count {
+        format.push((reader.read_uleb128()?, reader.read_uleb128()?));
+    }
+
+    let count_offset = reader.offset();
+    let count = reader.read_uleb128()?;
+    if count > reader.remaining() as u64 {
+        return Err(reader::Error::new(count_offset, ErrorKind::UnexpectedEof).into());
+    }
+    let mut entries = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        let mut entry = FileEntry {
+            path: Path::String(&[]),
+            directory_index: 0,
+            timestamp: 0,
+            size: 0,
+            md5: None,
+        };
+        for &(content, form) in &format {
+            let offset = reader.offset();
+            match (content, read_value(reader, form, offset_size)?) {
+                (DW_LNCT_PATH, Value::Path(path)) => entry.path = path,
+                (DW_LNCT_PATH, _) => return Err(Error::UnsupportedForm(offset, form)),
+                (DW_LNCT_DIRECTORY_INDEX, Value::Number(index)) => entry.directory_index = index,
+                (DW_LNCT_TIMESTAMP, Value::Number(timestamp)) => entry.timestamp = timestamp,
+                (DW_LNCT_SIZE, Value::Number(size)) => entry.size = size,
+                (DW_LNCT_MD5, Value::Bytes(bytes)) if bytes.len() == 16 => {
+                    let mut md5 = [0; 16];
+                    md5.copy_from_slice(bytes);
+                    entry.md5 = Some(md5);
+                }
+                // Unknown content types, and known ones in unexpected forms,
+                // are skipped.
+                _ => {}
+            }
+        }
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
+/// Parse the header of the line number program at `offset` within
+/// `debug_line`, the contents of a `.debug_line` section. The offset usually
+/// comes from a compilation unit's `DW_AT_stmt_list` attribute.
+pub fn parse_header(debug_line: &[u8], offset: usize) -> Result<LineProgramHeader<'_>, Error> {
+    let mut reader = Leb128Reader::new(debug_line);
+    reader.seek(offset)?;
+
+    let (unit_length, offset_size) = match read_fixed(&mut reader, 4)? as u32 {
+        0xffff_ffff => (read_fixed(&mut reader, 8)?, 8),
+        len if len >= 0xffff_fff0 => return Err(Error::InvalidUnitLength(offset, len)),
+        len => (u64::from(len), 4),
+    };
+    if unit_length > reader.remaining() as u64 {
+        return Err(reader::Error::new(offset, ErrorKind::UnexpectedEof).into());
+    }
+    let mut unit = reader.sub_reader(unit_length as usize)?;
+
+    let version_offset = unit.offset();
+    let version = read_fixed(&mut unit, 2)? as u16;
+    if !(2..=5).contains(&version) {
+        return Err(Error::UnsupportedVersion(version_offset, version));
+    }
+    let address_size = if version >= 5 {
+        let address_size = unit.read_u8()?;
+        let _segment_selector_size = unit.read_u8()?;
+        Some(address_size)
+    } else {
+        None
+    };
+
+    let header_length_offset = unit.offset();
+    let header_length = read_fixed(&mut unit, offset_size as usize)?;
+    if header_length > unit.remaining() as u64 {
+        return Err(reader::Error::new(header_length_offset, ErrorKind::UnexpectedEof).into());
+    }
+    let mut header = unit.sub_reader(header_length as usize)?;
+
+    let minimum_instruction_length = header.read_u8()?;
+    let maximum_operations_per_instruction = if version >= 4 { header.read_u8()? } else { 1 };
+    let default_is_stmt = header.read_u8()? != 0;
+    let line_base = header.read_u8()? as i8;
+    let line_range_offset = header.offset();
+    let line_range = header.read_u8()?;
+    if line_range == 0 {
+        return Err(Error::InvalidLineRange(line_range_offset));
+    }
+    let opcode_base = header.read_u8()?;
+    let standard_opcode_lengths = header.read_bytes(opcode_base.saturating_sub(1) as usize)?;
+
+    let (include_directories, file_names) = if version >= 5 {
+        let directories = read_entries(&mut header, offset_size)?
+            .into_iter()
+            .map(|entry| entry.path)
+            .collect();
+        (directories, read_entries(&mut header, offset_size)?)
+    } else {
+        let mut directories = vec![];
+        loop {
+            let directory = header.read_cstr_bytes()?;
+            if directory.is_empty() {
+                break;
+            }
+            directories.push(Path::String(directory));
+        }
+        let mut files = vec![];
+        loop {
+            let path = header.read_cstr_bytes()?;
+            if path.is_empty() {
+                break;
+            }
+            files.push(FileEntry {
+                path: Path::String(path),
+                directory_index: header.read_uleb128()?,
+                timestamp: header.read_uleb128()?,
+                size: header.read_uleb128()?,
+                md5: None,
+            });
+        }
+        (directories, files)
+    };
+
+    Ok(LineProgramHeader {
+        offset,
+        offset_size,
+        version,
+        address_size,
+        minimum_instruction_length,
+        maximum_operations_per_instruction,
+        default_is_stmt,
+        line_base,
+        line_range,
+        opcode_base,
+        standard_opcode_lengths,
+        include_directories,
+        file_names,
+        program_offset: unit.offset(),
+        program: unit.rest(),
+    })
+}
+
+/// A row of the line table: the state machine's registers when a row was
+/// appended.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LineRow {
+    /// The address of the instruction.
+    pub address: u64,
+    /// The index of the operation within a VLIW instruction.
+    pub op_index: u64,
+    /// The index of the source file in the header's file names. Before
+    /// DWARF 5, indices are one-based.
+    pub file: u64,
+    /// The source line, starting at 1, or 0 if unknown.
+    pub line: u64,
+    /// The source column, starting at 1, or 0 for the whole line.
+    pub column: u64,
+    /// Whether the instruction is a recommended breakpoint location.
+    pub is_stmt: bool,
+    /// Whether the instruction starts a basic block.
+    pub basic_block: bool,
+    /// Whether this row is the first address past the end of a sequence.
+    pub end_sequence: bool,
+    /// Whether the instruction is where a function's prologue ends.
+    pub prologue_end: bool,
+    /// Whether the instruction is where a function's epilogue begins.
+    pub epilogue_begin: bool,
+    /// The instruction set architecture of the instruction.
+    pub isa: u64,
+    /// The block the instruction belongs to.
+    pub discriminator: u64,
+}
+
+impl LineRow {
+    fn new(default_is_stmt: bool) -> LineRow {
+        LineRow {
+            address: 0,
+            op_index: 0,
+            file: 1,
+            line: 1,
+            column: 0,
+            is_stmt: default_is_stmt,
+            basic_block: false,
+            end_sequence: false,
+            prologue_end: false,
+            epilogue_begin: false,
+            isa: 0,
+            discriminator: 0,
+        }
+    }
+}
+
+impl<'a> LineProgramHeader<'a> {
+    /// Run the program, iterating over the rows it appends to the line
+    /// table. A header edited to have a zero `line_range`, or fewer
+    /// `standard_opcode_lengths` than its `opcode_base` calls for, makes the
+    /// opcodes that need them fail rather than panic.
+    pub fn rows(&self) -> Rows<'a> {
+        Rows {
+            reader: Leb128Reader::with_offset(self.program, self.program_offset),
+            minimum_instruction_length: u64::from(self.minimum_instruction_length),
+            maximum_operations_per_instruction: u64::from(
+                self.maximum_operations_per_instruction.max(1),
+            ),
+            default_is_stmt: self.default_is_stmt,
+            line_base: self.line_base,
+            line_range: self.line_range,
+            opcode_base: self.opcode_base,
+            standard_opcode_lengths: self.standard_opcode_lengths,
+            state: LineRow::new(self.default_is_stmt),
+            failed: false,
+        }
+    }
+}
+
+/// An iterator over the rows produced by a line number program.
+///
+/// Iteration stops after the first error.
+#[derive(Clone, Debug)]
+pub struct Rows<'a> {
+    reader: Leb128Reader<'a>,
+    minimum_instruction_length: u64,
+    maximum_operations_per_instruction: u64,
+    default_is_stmt: bool,
+    line_base: i8,
+    line_range: u8,
+    opcode_base: u8,
+    standard_opcode_lengths: &'a [u8],
+    state: LineRow,
+    failed: bool,
+}
+
+impl<'a> Rows<'a> {
+    /// The header's `line_range`, which must not be zero.
+    fn line_range(&self, offset: usize) -> Result<u8, Error> {
+        match self.line_range {
+            0 => Err(Error::InvalidLineRange(offset)),
+            line_range => Ok(line_range),
+        }
+    }
+
+    /// Advance the address and op index by `operation_advance` operations.
+    fn advance(&mut self, operation_advance: u64) {
+        let max_ops = self.maximum_operations_per_instruction;
+        let ops = self.state.op_index.wrapping_add(operation_advance);
+        self.state.address = self
+            .state
+            .address
+            .wrapping_add(self.minimum_instruction_length.wrapping_mul(ops / max_ops));
+        self.state.op_index = ops % max_ops;
+    }
+
+    /// Append a row, and reset the registers that only apply to one row.
+    fn emit(&mut self) -> LineRow {
+        let row = self.state;
+        if row.end_sequence {
+            self.state = LineRow::new(self.default_is_stmt);
+        } else {
+            self.state.basic_block = false;
+            self.state.prologue_end = false;
+            self.state.epilogue_begin = false;
+            self.state.discriminator = 0;
+        }
+        row
+    }
+
+    fn execute_extended(&mut self, offset: usize) -> Result<Option<LineRow>, Error> {
+        let len_offset = self.reader.offset();
+        let len = self.reader.read_uleb128()?;
+        if len > self.reader.remaining() as u64 {
+            return Err(reader::Error::new(len_offset, ErrorKind::UnexpectedEof).into());
+        }
+        let mut operands = self.reader.sub_reader(len as usize)?;
+
+        match operands.read_u8()? {
+            DW_LNE_END_SEQUENCE => {
+                self.state.end_sequence = true;
+                return Ok(Some(self.emit()));
+            }
+            DW_LNE_SET_ADDRESS => {
+                let size = operands.remaining();
+                if !(1..=8).contains(&size) {
+                    return Err(Error::InvalidAddressSize(offset, size));
+                }
+                self.state.address = read_fixed(&mut operands, size)?;
+                self.state.op_index = 0;
+            }
+            DW_LNE_SET_DISCRIMINATOR => self.state.discriminator = operands.read_uleb128()?,
+            // `DW_LNE_define_file` and unknown extended opcodes are skipped.
+            _ => {}
+        }
+        Ok(None)
+    }
+
+    fn next_row(&mut self) -> Result<Option<LineRow>, Error> {
+        while !self.reader.is_empty() {
+            let offset = self.reader.offset();
+            let opcode = self.reader.read_u8()?;
+
+            if opcode >= self.opcode_base {
+                let line_range = self.line_range(offset)?;
+                let adjusted = opcode - self.opcode_base;
+                self.advance(u64::from(adjusted / line_range));
+                let line_advance = i64::from(self.line_base) + i64::from(adjusted % line_range);
+                self.state.line = self.state.line.wrapping_add(line_advance as u64);
+                return Ok(Some(self.emit()));
+            }
+
+            match opcode {
+                0 => {
+                    if let Some(row) = self.execute_extended(offset)? {
+                        return Ok(Some(row));
+                    }
+                }
+                DW_LNS_COPY => return Ok(Some(self.emit())),
+                DW_LNS_ADVANCE_PC => {
+                    let operation_advance = self.reader.read_uleb128()?;
+                    self.advance(operation_advance);
+                }
+                DW_LNS_ADVANCE_LINE => {
+                    let line_advance = self.reader.read_sleb128()?;
+                    self.state.line = self.state.line.wrapping_add(line_advance as u64);
+                }
+                DW_LNS_SET_FILE => self.state.file = self.reader.read_uleb128()?,
+                DW_LNS_SET_COLUMN => self.state.column = self.reader.read_uleb128()?,
+                DW_LNS_NEGATE_STMT => self.state.is_stmt = !self.state.is_stmt,
+                DW_LNS_SET_BASIC_BLOCK => self.state.basic_block = true,
+                DW_LNS_CONST_ADD_PC => {
+                    let line_range = self.line_range(offset)?;
+                    let adjusted = 255 - self.opcode_base;
+                    self.advance(u64::from(adjusted / line_range));
+                }
+                DW_LNS_FIXED_ADVANCE_PC => {
+                    let delta = read_fixed(&mut self.reader, 2)?;
+                    self.state.address = self.state.address.wrapping_add(delta);
+                    self.state.op_index = 0;
+                }
+                DW_LNS_SET_PROLOGUE_END => self.state.prologue_end = true,
+                DW_LNS_SET_EPILOGUE_BEGIN => self.state.epilogue_begin = true,
+                DW_LNS_SET_ISA => self.state.isa = self.reader.read_uleb128()?,
+                _ => {
+                    // Skip the ULEB128 operands of unknown standard opcodes.
+                    let operands = match self.standard_opcode_lengths.get(opcode as usize - 1) {
+                        Some(&operands) => operands,
+                        None => return Err(Error::MissingOpcodeLength(offset, opcode)),
+                    };
+                    for _ in 0..operands {
+                        self.reader.read_uleb128()?;
+                    }
+                }
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl<'a> Iterator for Rows<'a> {
+    type Item = Result<LineRow, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        let result = self.next_row();
+        self.failed = !matches!(result, Ok(Some(_)));
+        result.transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Build a 32-bit line program unit from the header fields after
+    /// `header_length` and the program.
+    fn unit(version: u16, prefix: &[u8], header: &[u8], program: &[u8]) -> Vec<u8> {
+        let mut unit = version.to_le_bytes().to_vec();
+        unit.extend_from_slice(prefix);
+        unit.extend_from_slice(&(header.len() as u32).to_le_bytes());
+        unit.extend_from_slice(header);
+        unit.extend_from_slice(program);
+        let mut section = (unit.len() as u32).to_le_bytes().to_vec();
+        section.extend_from_slice(&unit);
+        section
+    }
+
+    #[rustfmt::skip]
+    const STANDARD_OPCODE_LENGTHS: [u8; 12] = [0, 1, 1, 1, 1, 0, 0, 0, 1, 0, 0, 1];
+
+    /// The parameters of a version 3 header: minimum_instruction_length 1,
+    /// default_is_stmt, line_base -5, line_range 14, opcode_base 13.
+    fn v3_header(dirs_and_files: &[u8]) -> Vec<u8> {
+        let mut header = vec![0x01, 0x01, 0xfb, 0x0e, 0x0d];
+        header.extend_from_slice(&STANDARD_OPCODE_LENGTHS);
+        header.extend_from_slice(dirs_and_files);
+        header
+    }
+
+    fn row(address: u64, file: u64, line: u64, column: u64) -> LineRow {
+        LineRow {
+            address,
+            file,
+            line,
+            column,
+            ..LineRow::new(true)
+        }
+    }
+
+    #[test]
+    fn test_parse_v3_header() {
+        #[rustfmt::skip]
+        let header = v3_header(&[
+            b'/', b'i', b'n', b'c', 0x00, 0x00,
+            b'a', b'.', b'c', 0x00, 0x00, 0x00, 0x00,
+            b'b', b'.', b'h', 0x00, 0x01, 0xe8, 0x07, 0x80, 0x01,
+            0x00,
+        ]);
+        let section = unit(3, &[], &header, &[0x01]);
+        let header = parse_header(&section, 0).unwrap();
+        assert_eq!(header.offset_size, 4);
+        assert_eq!(header.address_size, None);
+        assert_eq!(header.maximum_operations_per_instruction, 1);
+        assert_eq!(header.line_base, -5);
+        assert_eq!(header.standard_opcode_lengths, STANDARD_OPCODE_LENGTHS);
+        assert_eq!(header.include_directories, [Path::String(b"/inc")]);
+        assert_eq!(
+            header.file_names[1],
+            FileEntry {
+                path: Path::String(b"b.h"),
+                directory_index: 1,
+                timestamp: 1000,
+                size: 128,
+                md5: None,
+            }
+        );
+        assert_eq!(header.program, [0x01]);
+        assert_eq!(header.program_offset, section.len() - 1);
+    }
+
+    #[test]
+    fn test_parse_v5_header() {
+        let mut header = vec![0x04, 0x01, 0x00, 0xfb, 0x0e, 0x0d];
+        header.extend_from_slice(&STANDARD_OPCODE_LENGTHS);
+        #[rustfmt::skip]
+        header.extend_from_slice(&[
+            // Directories: DW_LNCT_path DW_FORM_line_strp.
+            0x01, 0x01, 0x1f,
+            0x02, 0x00, 0x00, 0x00, 0x00, 0x10, 0x00, 0x00, 0x00,
+            // Files: DW_LNCT_path DW_FORM_string, DW_LNCT_directory_index
+            // DW_FORM_udata, DW_LNCT_MD5 DW_FORM_data16, and an unknown
+            // content type with DW_FORM_data1.
+            0x04, 0x01, 0x08, 0x02, 0x0f, 0x05, 0x1e, 0x80, 0x40, 0x0b,
+            0x01,
+            b'm', b'a', b'i', b'n', b'.', b'c', 0x00, 0x01,
+            0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07,
+            0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d, 0x0e, 0x0f,
+            0xff,
+        ]);
+        let section = unit(5, &[0x08, 0x00], &header, &[]);
+        let header = parse_header(&section, 0).unwrap();
+        assert_eq!(header.version, 5);
+        assert_eq!(header.address_size, Some(8));
+        assert_eq!(header.minimum_instruction_length, 4);
+        assert_eq!(
+            header.include_directories,
+            [Path::LineStrp(0), Path::LineStrp(0x10)]
+        );
+        let md5 = [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15];
+        assert_eq!(
+            header.file_names,
+            [FileEntry {
+                path: Path::String(b"main.c"),
+                directory_index: 1,
+                timestamp: 0,
+                size: 0,
+                md5: Some(md5),
+            }]
+        );
+        assert!(header.rows().next().is_none());
+    }
+
+    #[test]
+    fn test_rows() {
+        #[rustfmt::skip]
+        let program = [
+            // DW_LNE_set_address 0x1000
+            0x00, 0x05, 0x02, 0x00, 0x10, 0x00, 0x00,
+            // DW_LNS_set_column 4; DW_LNS_set_prologue_end; DW_LNS_copy
+            0x05, 0x04, 0x0a, 0x01,
+            // Special opcode: address += 2, line += 1
+            0x0d + 1 + 5 + 2 * 14,
+            // DW_LNS_advance_line -2; DW_LNS_const_add_pc; DW_LNS_negate_stmt
+            0x03, 0x7e, 0x08, 0x06,
+            // DW_LNE_set_discriminator 3; DW_LNS_set_file 2; DW_LNS_copy
+            0x00, 0x02, 0x04, 0x03, 0x04, 0x02, 0x01,
+            // DW_LNS_fixed_advance_pc 0x100; DW_LNS_advance_pc 2
+            0x09, 0x00, 0x01, 0x02, 0x02,
+            // DW_LNE_end_sequence
+            0x00, 0x01, 0x01,
+            // A second sequence: special opcode with line += 3
+            0x0d + 3 + 5,
+        ];
+        let section = unit(3, &[], &v3_header(&[0x00, 0x00]), &program);
+        let rows: Vec<_> = parse_header(&section, 0)
+            .unwrap()
+            .rows()
+            .collect::<Result<_, _>>()
+            .unwrap();
+
+        let mut expected = vec![
+            LineRow {
+                prologue_end: true,
+                ..row(0x1000, 1, 1, 4)
+            },
+            row(0x1002, 1, 2, 4),
+            LineRow {
+                is_stmt: false,
+                discriminator: 3,
+                ..row(0x1013, 2, 0, 4)
+            },
+            LineRow {
+                is_stmt: false,
+                end_sequence: true,
+                ..row(0x1115, 2, 0, 4)
+            },
+            row(0, 1, 4, 0),
+        ];
+        assert_eq!(rows, expected);
+
+        // With two operations per instruction, the address only advances
+        // every other operation.
+        let mut header = v3_header(&[0x00, 0x00]);
+        header.insert(1, 0x02);
+        let section = unit(4, &[], &header, &[0x02, 0x03, 0x01]);
+        let rows: Vec<_> = parse_header(&section, 0)
+            .unwrap()
+            .rows()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        expected = vec![LineRow {
+            op_index: 1,
+            ..row(1, 1, 1, 0)
+        }];
+        assert_eq!(rows, expected);
+    }
+
+    #[test]
+    fn test_unknown_opcodes_are_skipped() {
+        // An opcode_base of 14 makes opcode 13 a standard opcode with two
+        // operands.
+        let mut header = vec![0x01, 0x01, 0xfb, 0x0e, 0x0e];
+        header.extend_from_slice(&STANDARD_OPCODE_LENGTHS);
+        header.extend_from_slice(&[0x02, 0x00, 0x00]);
+        #[rustfmt::skip]
+        let program = [
+            0x0d, 0x80, 0x01, 0x7f,
+            // DW_LNE_define_file and an unknown extended opcode.
+            0x00, 0x06, 0x03, b'x', 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x02, 0x80, 0xaa,
+            0x01,
+        ];
+        let section = unit(2, &[], &header, &program);
+        let rows: Vec<_> = parse_header(&section, 0)
+            .unwrap()
+            .rows()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(rows, [row(0, 1, 1, 0)]);
+    }
+
+    #[test]
+    fn test_64_bit_unit() {
+        let header = v3_header(&[0x00, 0x00]);
+        let mut unit = 3u16.to_le_bytes().to_vec();
+        unit.extend_from_slice(&(header.len() as u64).to_le_bytes());
+        unit.extend_from_slice(&header);
+        unit.push(0x01);
+        let mut section = vec![0xff, 0xff, 0xff, 0xff];
+        section.extend_from_slice(&(unit.len() as u64).to_le_bytes());
+        section.extend_from_slice(&unit);
+
+        let header = parse_header(&section, 0).unwrap();
+        assert_eq!(header.offset_size, 8);
+        assert_eq!(header.rows().count(), 1);
+    }
+
+    #[test]
+    fn test_header_errors() {
+        let header = v3_header(&[0x00, 0x00]);
+        assert_eq!(
+            parse_header(&unit(6, &[], &header, &[]), 0),
+            Err(Error::UnsupportedVersion(4, 6))
+        );
+        assert_eq!(
+            parse_header(&[0xf0, 0xff, 0xff, 0xff], 0),
+            Err(Error::InvalidUnitLength(0, 0xffff_fff0))
+        );
+        assert_eq!(
+            parse_header(&[0x10, 0x00, 0x00, 0x00, 0x02, 0x00], 0),
+            Err(Error::Read(reader::Error::new(0, ErrorKind::UnexpectedEof)))
+        );
+
+        let mut zero_range = header.clone();
+        zero_range[3] = 0;
+        assert_eq!(
+            parse_header(&unit(3, &[], &zero_range, &[]), 0),
+            Err(Error::InvalidLineRange(13))
+        );
+
+        // A file name table that runs past header_length.
+        let section = unit(3, &[], &header[..header.len() - 1], &[0x00]);
+        assert_eq!(
+            parse_header(&section, 0),
+            Err(Error::Read(reader::Error::new(
+                section.len() - 1,
+                ErrorKind::UnexpectedEof
+            )))
+        );
+
+        // A DWARF 5 directory format with DW_FORM_strx1.
+        let mut v5 = vec![0x01, 0x01, 0x01, 0xfb, 0x0e, 0x0d];
+        v5.extend_from_slice(&STANDARD_OPCODE_LENGTHS);
+        v5.extend_from_slice(&[0x01, 0x01, 0x25, 0x01, 0x00]);
+        let section = unit(5, &[0x08, 0x00], &v5, &[]);
+        assert_eq!(
+            parse_header(&section, 0),
+            Err(Error::UnsupportedForm(section.len() - 1, 0x25))
+        );
+    }
+
+    #[test]
+    fn test_program_errors() {
+        let header = v3_header(&[0x00, 0x00]);
+        for (program, error) in &[
+            (
+                vec![0x01, 0x00, 0x0a, 0x02],
+                Error::Read(reader::Error::new(2, ErrorKind::UnexpectedEof)),
+            ),
+            (
+                vec![0x01, 0x00, 0x01, 0x02],
+                Error::InvalidAddressSize(1, 0),
+            ),
+            (
+                vec![0x01, 0x00, 0x0a, 0x02, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+                Error::InvalidAddressSize(1, 9),
+            ),
+            (
+                vec![0x01, 0x02, 0x80],
+                Error::Read(reader::Error::new(2, ErrorKind::UnexpectedEof)),
+            ),
+        ] {
+            let section = unit(3, &[], &header, program);
+            let header = parse_header(&section, 0).unwrap();
+            let base = header.program_offset;
+            let error = match *error {
+                Error::Read(e) => Error::Read(reader::Error::new(base + e.offset(), e.kind())),
+                Error::InvalidAddressSize(offset, size) => {
+                    Error::InvalidAddressSize(base + offset, size)
+                }
+                ref other => other.clone(),
+            };
+            let mut rows = header.rows();
+            assert!(rows.next().unwrap().is_ok());
+            assert_eq!(rows.next(), Some(Err(error)));
+            assert_eq!(rows.next(), None);
+        }
+    }
+
+    #[test]
+    fn test_edited_header_errors() {
+        // DW_LNS_copy, a special opcode and DW_LNS_const_add_pc.
+        let section = unit(3, &[], &v3_header(&[0x00, 0x00]), &[0x01, 0x0d, 0x08]);
+        let header = parse_header(&section, 0).unwrap();
+        let base = header.program_offset;
+
+        let mut zero_range = header.clone();
+        zero_range.line_range = 0;
+        let mut rows = zero_range.rows();
+        assert!(rows.next().unwrap().is_ok());
+        assert_eq!(rows.next(), Some(Err(Error::InvalidLineRange(base + 1))));
+        assert_eq!(rows.next(), None);
+
+        zero_range.program = &zero_range.program[2..];
+        zero_range.program_offset += 2;
+        assert_eq!(
+            zero_range.rows().next(),
+            Some(Err(Error::InvalidLineRange(base + 2)))
+        );
+
+        // Opcode 13 becomes a standard opcode without an operand count.
+        let mut short_lengths = header.clone();
+        short_lengths.opcode_base = 14;
+        let mut rows = short_lengths.rows();
+        assert!(rows.next().unwrap().is_ok());
+        assert_eq!(
+            rows.next(),
+            Some(Err(Error::MissingOpcodeLength(base + 1, 0x0d)))
+        );
+        assert_eq!(rows.next(), None);
+    }
+}
@@ -0,0 +1,363 @@
+//! Read and write the two base 128 variants used in git packfiles.
+//!
+//! The negative offsets of `OBJ_OFS_DELTA` objects are big-endian base 128
+//! where one is added at each continuation, so that every number has exactly
+//! one encoding and no bytes are wasted.
+//!
+//! Every object in a pack starts with a header holding the object's type and
+//! uncompressed size. The first byte has a continuation bit, a 3-bit type and
+//! the low four bits of the size; any further bytes hold the rest of the size
+//! seven bits at a time, least significant first.
+//!
+//! ```
+//! use leb128::git::{self, ObjectHeader, ObjectType};
+//!
+//! let mut buf = vec![];
+//! git::write_object_header(&mut buf, ObjectHeader { kind: ObjectType::Blob, size: 100 }).unwrap();
+//! git::write_offset(&mut buf, 200).unwrap();
+//! assert_eq!(buf, [0xb4, 0x06, 0x80, 0x48]);
+//!
+//! let mut readable = &buf[..];
+//! let header = git::read_object_header(&mut readable).unwrap();
+//! assert_eq!(header.kind, ObjectType::Blob);
+//! assert_eq!(header.size, 100);
+//! assert_eq!(git::read_offset(&mut readable).unwrap(), 200);
+//! ```
+
+use super::{low_bits_of_byte, low_bits_of_u64, read, write, CONTINUATION_BIT};
+use std::fmt;
+use std::io;
+
+/// The maximum number of bytes an object header or offset with a 64-bit size
+/// can occupy.
+pub const MAX_ENCODED_LEN: usize = 10;
+
+/// Decode an offset from the bytes produced by `next_byte`.
+fn decode_offset<F>(mut next_byte: F) -> Result<u64, read::Error>
+where
+    F: FnMut() -> Result<u8, read::Error>,
+{
+    let mut byte = next_byte()?;
+    let mut offset = u64::from(low_bits_of_byte(byte));
+
+    while byte & CONTINUATION_BIT != 0 {
+        if offset >= u64::MAX >> 7 {
+            return Err(read::Error::Overflow);
+        }
+        byte = next_byte()?;
+        offset = (offset + 1) << 7 | u64::from(low_bits_of_byte(byte));
+    }
+
+    Ok(offset)
+}
+
+/// Read an `OBJ_OFS_DELTA` offset from the given `std::io::Read`able and
+/// return it or an error if reading failed.
+pub fn read_offset<R>(r: &mut R) -> Result<u64, read::Error>
+where
+    R: io::Read,
+{
+    decode_offset(read::io_bytes(r))
+}
+
+/// Decode an `OBJ_OFS_DELTA` offset from the start of `buf` without consuming
+/// it. Returns the offset along with the length of its encoding.
+pub fn peek_offset(buf: &[u8]) -> Result<(u64, usize), read::Error> {
+    let mut len = 0;
+    let offset = decode_offset(read::slice_bytes(buf, &mut len))?;
+    Ok((offset, len))
+}
+
+/// Encode `offset` into `buf`, returning the length of the encoding.
+fn encode_offset(buf: &mut [u8; MAX_ENCODED_LEN], mut offset: u64) -> usize {
+    // Fill the buffer from the end, as the least significant group comes
+    // last.
+    let mut pos = MAX_ENCODED_LEN - 1;
+    buf[pos] = low_bits_of_u64(offset);
+    offset >>= 7;
+    while offset != 0 {
+        offset -= 1;
+        pos -= 1;
+        buf[pos] = low_bits_of_u64(offset) | CONTINUATION_BIT;
+        offset >>= 7;
+    }
+    buf.copy_within(pos.., 0);
+    MAX_ENCODED_LEN - pos
+}
+
+/// The number of bytes `write_offset` uses to encode `offset`.
+pub fn offset_len(offset: u64) -> usize {
+    encode_offset(&mut [0; MAX_ENCODED_LEN], offset)
+}
+
+/// Write an `OBJ_OFS_DELTA` offset to the given `std::io::Write`able. Returns
+/// the number of bytes written to `w`, or an error if writing failed.
+pub fn write_offset<W>(w: &mut W, offset: u64) -> Result<usize, write::Error>
+where
+    W: ?Sized + io::Write,
+{
+    let mut buf = [0; MAX_ENCODED_LEN];
+    let len = encode_offset(&mut buf, offset);
+    write::write_encoded(w, &buf[..len])
+}
+
+/// The type of an object in a pack.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ObjectType {
+    /// `OBJ_COMMIT`
+    Commit = 1,
+    /// `OBJ_TREE`
+    Tree = 2,
+    /// `OBJ_BLOB`
+    Blob = 3,
+    /// `OBJ_TAG`
+    Tag = 4,
+    /// `OBJ_OFS_DELTA`, a delta against an object at a relative offset.
+    OfsDelta = 6,
+    /// `OBJ_REF_DELTA`, a delta against an object named by its hash.
+    RefDelta = 7,
+}
+
+impl ObjectType {
+    /// The object type with the given 3-bit value, if there is one.
+    pub fn from_u8(val: u8) -> Option<ObjectType> {
+        match val {
+            1 => Some(ObjectType::Commit),
+            2 => Some(ObjectType::Tree),
+            3 => Some(ObjectType::Blob),
+            4 => Some(ObjectType::Tag),
+            6 => Some(ObjectType::OfsDelta),
+            7 => Some(ObjectType::RefDelta),
+            _ => None,
+        }
+    }
+}
+
+/// The header at the start of every object in a pack.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ObjectHeader {
+    /// The object's type.
+    pub kind: ObjectType,
+    /// The object's size once inflated. For deltas this is the size of the
+    /// delta data, not of the object it produces.
+    pub size: u64,
+}
+
+/// An error that occurred while reading an object header.
+#[derive(Debug)]
+pub enum Error {
+    /// The header could not be read.
+    Read(read::Error),
+    /// The header named a reserved or invalid object type, which is
+    /// carried here.
+    InvalidObjectType(u8),
+}
+
+impl From<read::Error> for Error {
+    fn from(e: read::Error) -> Self {
+        Error::Read(e)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Read(read::Error::IoError(e))
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match *self {
+            Error::Read(ref e) => write!(f, "leb128::git::Error: {}", e),
+            Error::InvalidObjectType(kind) => {
+                write!(f, "leb128::git::Error: Invalid object type {}", kind)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match *self {
+            Error::Read(ref e) => Some(e),
+            Error::InvalidObjectType(_) => None,
+        }
+    }
+}
+
+/// Decode an object header from the bytes produced by `next_byte`.
+fn decode_object_header<F>(mut next_byte: F) -> Result<ObjectHeader, Error>
+where
+    F: FnMut() -> Result<u8, read::Error>,
+{
+    let mut byte = next_byte()?;
+    let kind = (byte >> 4) & 0x7;
+    let kind = ObjectType::from_u8(kind).ok_or(Error::InvalidObjectType(kind))?;
+    let mut size = u64::from(byte & 0xf);
+    let mut shift = 4;
+
+    while byte & CONTINUATION_BIT != 0 {
+        byte = next_byte()?;
+        let low_bits = u64::from(low_bits_of_byte(byte));
+        if shift >= 64 || (low_bits << shift) >> shift != low_bits {
+            return Err(read::Error::Overflow.into());
+        }
+        size |= low_bits << shift;
+        shift += 7;
+    }
+
+    Ok(ObjectHeader { kind, size })
+}
+
+/// Read an object header from the given `std::io::Read`able and return it or
+/// an error if reading failed.
+pub fn read_object_header<R>(r: &mut R) -> Result<ObjectHeader, Error>
+where
+    R: io::Read,
+{
+    decode_object_header(read::io_bytes(r))
+}
+
+/// Decode an object header from the start of `buf` without consuming it.
+/// Returns the header along with the length of its encoding.
+pub fn peek_object_header(buf: &[u8]) -> Result<(ObjectHeader, usize), Error> {
+    let mut len = 0;
+    let header = decode_object_header(read::slice_bytes(buf, &mut len))?;
+    Ok((header, len))
+}
+
+/// Encode `header` into `buf`, returning the length of the encoding.
+fn encode_object_header(buf: &mut [u8; MAX_ENCODED_LEN], header: ObjectHeader) -> usize {
+    let mut byte = (header.kind as u8) << 4 | (header.size & 0xf) as u8;
+    let mut size = header.size >> 4;
+    let mut len = 0;
+    while size != 0 {
+        buf[len] = byte | CONTINUATION_BIT;
+        len += 1;
+        byte = low_bits_of_u64(size);
+        size >>= 7;
+    }
+    buf[len] = byte;
+    len + 1
+}
+
+/// The number of bytes `write_object_header` uses to encode `header`.
+pub fn object_header_len(header: ObjectHeader) -> usize {
+    encode_object_header(&mut [0; MAX_ENCODED_LEN], header)
+}
+
+/// Write an object header to the given `std::io::Write`able. Returns the
+/// number of bytes written to `w`, or an error if writing failed.
+pub fn write_object_header<W>(w: &mut W, header: ObjectHeader) -> Result<usize, write::Error>
+where
+    W: ?Sized + io::Write,
+{
+    let mut buf = [0; MAX_ENCODED_LEN];
+    let len = encode_object_header(&mut buf, header);
+    write::write_encoded(w, &buf[..len])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_offsets() {
+        for &(offset, encoded) in &[
+            (0u64, &[0x00][..]),
+            (127, &[0x7f]),
+            (128, &[0x80, 0x00]),
+            (16511, &[0xff, 0x7f]),
+            (16512, &[0x80, 0x80, 0x00]),
+        ] {
+            let mut buf = vec![];
+            assert_eq!(write_offset(&mut buf, offset).unwrap(), encoded.len());
+            assert_eq!(buf, encoded);
+            assert_eq!(offset_len(offset), encoded.len());
+            assert_eq!(read_offset(&mut &buf[..]).unwrap(), offset);
+            assert_eq!(peek_offset(&buf).unwrap(), (offset, encoded.len()));
+        }
+    }
+
+    #[test]
+    fn test_offset_bijective() {
+        // Every byte sequence decodes to a different number, so successive
+        // numbers never skip or repeat an encoding length.
+        for offset in 0..70000 {
+            let mut buf = vec![];
+            write_offset(&mut buf, offset).unwrap();
+            assert_eq!(peek_offset(&buf).unwrap(), (offset, buf.len()));
+        }
+
+        let mut buf = vec![];
+        write_offset(&mut buf, u64::MAX).unwrap();
+        assert_eq!(peek_offset(&buf).unwrap(), (u64::MAX, buf.len()));
+    }
+
+    #[test]
+    fn test_offset_overflow() {
+        let buf = [0xff; 11];
+        match peek_offset(&buf) {
+            Err(read::Error::Overflow) => {}
+            otherwise => panic!("Unexpected: {:?}", otherwise),
+        }
+        match peek_offset(&[0x80]) {
+            Err(read::Error::IoError(e)) => assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof),
+            otherwise => panic!("Unexpected: {:?}", otherwise),
+        }
+    }
+
+    #[test]
+    fn test_object_headers() {
+        for &(kind, size, encoded) in &[
+            (ObjectType::Commit, 0u64, &[0x10][..]),
+            (ObjectType::Tree, 15, &[0x2f]),
+            (ObjectType::Blob, 16, &[0xb0, 0x01]),
+            (ObjectType::Tag, 2047, &[0xcf, 0x7f]),
+            (ObjectType::OfsDelta, 2048, &[0xe0, 0x80, 0x01]),
+            (ObjectType::RefDelta, 1, &[0x71]),
+        ] {
+            let header = ObjectHeader { kind, size };
+            let mut buf = vec![];
+            assert_eq!(
+                write_object_header(&mut buf, header).unwrap(),
+                encoded.len()
+            );
+            assert_eq!(buf, encoded);
+            assert_eq!(object_header_len(header), encoded.len());
+            assert_eq!(read_object_header(&mut &buf[..]).unwrap(), header);
+            assert_eq!(peek_object_header(&buf).unwrap(), (header, encoded.len()));
+        }
+
+        let header = ObjectHeader {
+            kind: ObjectType::Blob,
+            size: u64::MAX,
+        };
+        let mut buf = vec![];
+        write_object_header(&mut buf, header).unwrap();
+        assert_eq!(peek_object_header(&buf).unwrap(), (header, buf.len()));
+    }
+
+    #[test]
+    fn test_object_header_errors() {
+        for &kind in &[0x00, 0x50] {
+            match peek_object_header(&[kind]) {
+                Err(Error::InvalidObjectType(k)) => assert_eq!(k, kind >> 4),
+                otherwise => panic!("Unexpected: {:?}", otherwise),
+            }
+        }
+
+        let buf = [0xbf, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x7f];
+        match peek_object_header(&buf) {
+            Err(Error::Read(read::Error::Overflow)) => {}
+            otherwise => panic!("Unexpected: {:?}", otherwise),
+        }
+
+        match read_object_header(&mut &[0xb0][..]) {
+            Err(Error::Read(read::Error::IoError(e))) => {
+                assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof)
+            }
+            otherwise => panic!("Unexpected: {:?}", otherwise),
+        }
+    }
+}
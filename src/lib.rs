@@ -56,16 +56,18 @@ pub fn low_bits_of_byte(byte: u8) -> u8 {
 #[doc(hidden)]
 #[inline]
 pub fn low_bits_of_u64(val: u64) -> u8 {
-    let byte = val & (std::u8::MAX as u64);
+    let byte = val & (u8::MAX as u64);
     low_bits_of_byte(byte as u8)
 }
 
 /// A module for reading signed and unsigned integers that have been LEB128
 /// encoded.
 pub mod read {
+    use super::zigzag::{zigzag_decode, ZigZag};
     use super::{low_bits_of_byte, CONTINUATION_BIT, SIGN_BIT};
     use std::fmt;
-    use std::io;
+    use std::io::{self, Read};
+    use std::str;
 
     /// An enumeration of the possible errors that can occur when reading a
     /// number encoded with LEB128.
@@ -75,6 +77,19 @@ pub mod read {
         IoError(io::Error),
         /// The number being read is larger than can be represented.
         Overflow,
+        /// A length or element count prefix is larger than the maximum the
+        /// caller allowed.
+        TooLong,
+        /// A length-prefixed string is not valid UTF-8.
+        InvalidUtf8(str::Utf8Error),
+        /// The number is padded with redundant bytes, in a context that
+        /// requires the shortest possible encoding.
+        NonMinimal,
+        /// The data buffered by a `std::io::BufRead`able ends part way through
+        /// a number, so it cannot be peeked at without consuming input. Only
+        /// the `peek_*_buffered` functions report this, as they cannot tell
+        /// whether more data would follow.
+        Incomplete,
     }
 
     impl From<io::Error> for Error {
@@ -85,51 +100,59 @@ pub mod read {
 
     impl fmt::Display for Error {
         fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
-            write!(
-                f,
-                "leb128::read::Error: {}",
-                ::std::error::Error::description(self)
-            )
-        }
-    }
-
-    impl ::std::error::Error for Error {
-        fn description(&self) -> &str {
             match *self {
-                Error::IoError(ref e) => e.description(),
-                Error::Overflow => "The number being read is larger than can be represented",
+                Error::IoError(ref e) => write!(f, "leb128::read::Error: {}", e),
+                Error::Overflow => write!(
+                    f,
+                    "leb128::read::Error: The number being read is larger than can be represented"
+                ),
+                Error::TooLong => write!(
+                    f,
+                    "leb128::read::Error: The length prefix is larger than the maximum allowed"
+                ),
+                Error::InvalidUtf8(ref e) => write!(f, "leb128::read::Error: {}", e),
+                Error::NonMinimal => write!(
+                    f,
+                    "leb128::read::Error: The number is not minimally encoded"
+                ),
+                Error::Incomplete => write!(
+                    f,
+                    "leb128::read::Error: The buffered data ends part way through the number"
+                ),
             }
         }
+    }
 
-        fn cause(&self) -> Option<&::std::error::Error> {
+    impl std::error::Error for Error {
+        fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
             match *self {
                 Error::IoError(ref e) => Some(e),
-                Error::Overflow => None,
+                Error::InvalidUtf8(ref e) => Some(e),
+                Error::Overflow | Error::TooLong | Error::NonMinimal | Error::Incomplete => None,
             }
         }
     }
 
-    /// Read an unsigned LEB128 number from the given `std::io::Read`able and
-    /// return it or an error if reading failed.
-    pub fn unsigned<R>(r: &mut R) -> Result<u64, Error>
+    /// Decode an unsigned LEB128 number from the bytes produced by
+    /// `next_byte`.
+    fn decode_unsigned<F>(mut next_byte: F) -> Result<u64, Error>
     where
-        R: io::Read,
+        F: FnMut() -> Result<u8, Error>,
     {
         let mut result = 0;
         let mut shift = 0;
 
         loop {
-            let mut buf = [0];
-            try!(r.read_exact(&mut buf));
+            let byte = next_byte()?;
 
-            if shift == 63 && buf[0] != 0x00 && buf[0] != 0x01 {
+            if shift == 63 && byte != 0x00 && byte != 0x01 {
                 return Err(Error::Overflow);
             }
 
-            let low_bits = low_bits_of_byte(buf[0]) as u64;
+            let low_bits = low_bits_of_byte(byte) as u64;
             result |= low_bits << shift;
 
-            if buf[0] & CONTINUATION_BIT == 0 {
+            if byte & CONTINUATION_BIT == 0 {
                 return Ok(result);
             }
 
@@ -137,11 +160,10 @@ pub mod read {
         }
     }
 
-    /// Read a signed LEB128 number from the given `std::io::Read`able and
-    /// return it or an error if reading failed.
-    pub fn signed<R>(r: &mut R) -> Result<i64, Error>
+    /// Decode a signed LEB128 number from the bytes produced by `next_byte`.
+    fn decode_signed<F>(mut next_byte: F) -> Result<i64, Error>
     where
-        R: io::Read,
+        F: FnMut() -> Result<u8, Error>,
     {
         let mut result = 0;
         let mut shift = 0;
@@ -149,10 +171,8 @@ pub mod read {
         let mut byte;
 
         loop {
-            let mut buf = [0];
-            try!(r.read_exact(&mut buf));
+            byte = next_byte()?;
 
-            byte = buf[0];
             if shift == 63 && byte != 0x00 && byte != 0x7f {
                 return Err(Error::Overflow);
             }
@@ -173,21 +193,484 @@ pub mod read {
 
         Ok(result)
     }
+
+    /// Return a closure yielding successive bytes of `buf`, counting how many
+    /// have been taken in `len`.
+    pub(crate) fn slice_bytes<'a>(
+        buf: &'a [u8],
+        len: &'a mut usize,
+    ) -> impl FnMut() -> Result<u8, Error> + 'a {
+        move || match buf.get(*len) {
+            Some(&byte) => {
+                *len += 1;
+                Ok(byte)
+            }
+            None => Err(Error::IoError(io::ErrorKind::UnexpectedEof.into())),
+        }
+    }
+
+    /// Return a closure yielding successive bytes read from `r`.
+    pub(crate) fn io_bytes<'a, R>(r: &'a mut R) -> impl FnMut() -> Result<u8, Error> + 'a
+    where
+        R: ?Sized + io::Read,
+    {
+        move || {
+            let mut buf = [0];
+            r.read_exact(&mut buf)?;
+            Ok(buf[0])
+        }
+    }
+
+    /// Read an unsigned LEB128 number from the given `std::io::Read`able and
+    /// return it or an error if reading failed.
+    ///
+    /// Bytes are consumed from `r` as they are decoded. Use
+    /// `unsigned_buffered` when a failed read must leave the input untouched.
+    pub fn unsigned<R>(r: &mut R) -> Result<u64, Error>
+    where
+        R: io::Read,
+    {
+        decode_unsigned(io_bytes(r))
+    }
+
+    /// Read a signed LEB128 number from the given `std::io::Read`able and
+    /// return it or an error if reading failed.
+    ///
+    /// Bytes are consumed from `r` as they are decoded. Use `signed_buffered`
+    /// when a failed read must leave the input untouched.
+    pub fn signed<R>(r: &mut R) -> Result<i64, Error>
+    where
+        R: io::Read,
+    {
+        decode_signed(io_bytes(r))
+    }
+
+    /// Read a ZigZag encoded signed number of any width from the given
+    /// `std::io::Read`able and return it or an error if reading failed. See
+    /// the `zigzag` module. Numbers that do not fit in `T` are reported as
+    /// `Error::Overflow`.
+    pub fn zigzag<R, T>(r: &mut R) -> Result<T, Error>
+    where
+        R: io::Read,
+        T: ZigZag,
+    {
+        T::from_i64(zigzag_decode(unsigned(r)?)).ok_or(Error::Overflow)
+    }
+
+    /// Decode an unsigned LEB128 number from the start of `buf` without
+    /// consuming it. Returns the number along with the length of its
+    /// encoding, or an error if `buf` does not begin with a valid number.
+    ///
+    /// ```
+    /// let buf = [0x80, 0x01, 0xff];
+    /// assert_eq!(leb128::read::peek_unsigned(&buf).unwrap(), (128, 2));
+    /// ```
+    pub fn peek_unsigned(buf: &[u8]) -> Result<(u64, usize), Error> {
+        let mut len = 0;
+        let val = decode_unsigned(slice_bytes(buf, &mut len))?;
+        Ok((val, len))
+    }
+
+    /// Decode a signed LEB128 number from the start of `buf` without
+    /// consuming it. Returns the number along with the length of its
+    /// encoding, or an error if `buf` does not begin with a valid number.
+    ///
+    /// ```
+    /// let buf = [0x80, 0x7f, 0xff];
+    /// assert_eq!(leb128::read::peek_signed(&buf).unwrap(), (-128, 2));
+    /// ```
+    pub fn peek_signed(buf: &[u8]) -> Result<(i64, usize), Error> {
+        let mut len = 0;
+        let val = decode_signed(slice_bytes(buf, &mut len))?;
+        Ok((val, len))
+    }
+
+    /// Decode a number from the data buffered by `r`, turning a number that
+    /// runs past the end of that data into `Error::Incomplete`.
+    fn peek_buffered<R, T, F>(r: &mut R, peek: F) -> Result<(T, usize), Error>
+    where
+        R: ?Sized + io::BufRead,
+        F: FnOnce(&[u8]) -> Result<(T, usize), Error>,
+    {
+        let buf = r.fill_buf()?;
+        match peek(buf) {
+            Err(Error::IoError(ref e))
+                if e.kind() == io::ErrorKind::UnexpectedEof && !buf.is_empty() =>
+            {
+                Err(Error::Incomplete)
+            }
+            result => result,
+        }
+    }
+
+    /// Decode an unsigned LEB128 number from the buffered data of the given
+    /// `std::io::BufRead`able without consuming it. Returns the number along
+    /// with the length of its encoding, which can be passed to `consume` to
+    /// move past it.
+    ///
+    /// Only the data returned by a single call to `fill_buf` is examined. A
+    /// number that continues past the end of that data is reported as
+    /// `Error::Incomplete`, while an empty buffer is reported as an
+    /// `UnexpectedEof` IO error.
+    pub fn peek_unsigned_buffered<R>(r: &mut R) -> Result<(u64, usize), Error>
+    where
+        R: ?Sized + io::BufRead,
+    {
+        peek_buffered(r, peek_unsigned)
+    }
+
+    /// Decode a signed LEB128 number from the buffered data of the given
+    /// `std::io::BufRead`able without consuming it. Returns the number along
+    /// with the length of its encoding, which can be passed to `consume` to
+    /// move past it.
+    ///
+    /// Only the data returned by a single call to `fill_buf` is examined. A
+    /// number that continues past the end of that data is reported as
+    /// `Error::Incomplete`, while an empty buffer is reported as an
+    /// `UnexpectedEof` IO error.
+    pub fn peek_signed_buffered<R>(r: &mut R) -> Result<(i64, usize), Error>
+    where
+        R: ?Sized + io::BufRead,
+    {
+        peek_buffered(r, peek_signed)
+    }
+
+    /// Read a number from `r`, consuming it only once it has been decoded
+    /// if it lies wholly within the buffered data, and falling back to a
+    /// consuming `decode` otherwise.
+    fn read_buffered<R, T, P, D>(r: &mut R, peek: P, decode: D) -> Result<T, Error>
+    where
+        R: ?Sized + io::BufRead,
+        P: FnOnce(&[u8]) -> Result<(T, usize), Error>,
+        D: FnOnce(&mut R) -> Result<T, Error>,
+    {
+        match peek_buffered(r, peek) {
+            Ok((val, len)) => {
+                r.consume(len);
+                Ok(val)
+            }
+            Err(Error::Incomplete) => decode(r),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Read an unsigned LEB128 number from the given `std::io::BufRead`able,
+    /// such as a `&[u8]`, and return it or an error if reading failed.
+    ///
+    /// When the number lies wholly within the buffered data it is consumed
+    /// only once it has been decoded, so on error `r` is left exactly where it
+    /// was. A number that continues past the end of the buffered data is read
+    /// on through `fill_buf` like `unsigned` does, so that it still decodes,
+    /// but an error part way through it then leaves `r` advanced.
+    ///
+    /// ```
+    /// // 128, then a number too large for a `u64`.
+    /// let buf = [0x80, 0x01, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x7f];
+    /// let mut readable = &buf[..];
+    /// assert_eq!(leb128::read::unsigned_buffered(&mut readable).unwrap(), 128);
+    /// assert!(leb128::read::unsigned_buffered(&mut readable).is_err());
+    /// assert_eq!(readable, &buf[2..]);
+    /// ```
+    pub fn unsigned_buffered<R>(r: &mut R) -> Result<u64, Error>
+    where
+        R: ?Sized + io::BufRead,
+    {
+        read_buffered(r, peek_unsigned, |r| decode_unsigned(io_bytes(r)))
+    }
+
+    /// Read a signed LEB128 number from the given `std::io::BufRead`able, such
+    /// as a `&[u8]`, and return it or an error if reading failed.
+    ///
+    /// When the number lies wholly within the buffered data it is consumed
+    /// only once it has been decoded, so on error `r` is left exactly where it
+    /// was. A number that continues past the end of the buffered data is read
+    /// on through `fill_buf` like `signed` does, so that it still decodes, but
+    /// an error part way through it then leaves `r` advanced.
+    pub fn signed_buffered<R>(r: &mut R) -> Result<i64, Error>
+    where
+        R: ?Sized + io::BufRead,
+    {
+        read_buffered(r, peek_signed, |r| decode_signed(io_bytes(r)))
+    }
+
+    /// Check a decoded length prefix against the caller's maximum.
+    fn check_len(len: u64, max_len: usize) -> Result<usize, Error> {
+        if len > max_len as u64 {
+            return Err(Error::TooLong);
+        }
+        Ok(len as usize)
+    }
+
+    /// Read a byte string prefixed by its unsigned LEB128 encoded length from
+    /// the given `std::io::Read`able.
+    ///
+    /// Lengths greater than `max_len` are rejected with `Error::TooLong`
+    /// before anything is allocated, and the buffer only grows as data
+    /// actually arrives, so a bogus length cannot cause a huge allocation.
+    pub fn bytes<R>(r: &mut R, max_len: usize) -> Result<Vec<u8>, Error>
+    where
+        R: io::Read,
+    {
+        let len = check_len(unsigned(r)?, max_len)?;
+
+        let mut buf = Vec::new();
+        r.take(len as u64).read_to_end(&mut buf)?;
+        if buf.len() < len {
+            return Err(Error::IoError(io::ErrorKind::UnexpectedEof.into()));
+        }
+        Ok(buf)
+    }
+
+    /// Read a UTF-8 string prefixed by its unsigned LEB128 encoded length in
+    /// bytes from the given `std::io::Read`able.
+    ///
+    /// Lengths greater than `max_len` are rejected with `Error::TooLong`, as
+    /// with `bytes`.
+    pub fn string<R>(r: &mut R, max_len: usize) -> Result<String, Error>
+    where
+        R: io::Read,
+    {
+        let buf = bytes(r, max_len)?;
+        String::from_utf8(buf).map_err(|e| Error::InvalidUtf8(e.utf8_error()))
+    }
+
+    /// Decode a byte string prefixed by its unsigned LEB128 encoded length
+    /// from the start of `buf`, borrowing it rather than copying. Returns the
+    /// byte string along with the total length of the prefix and contents.
+    ///
+    /// ```
+    /// let buf = [0x02, b'h', b'i', 0xff];
+    /// assert_eq!(leb128::read::peek_bytes(&buf, 16).unwrap(), (&b"hi"[..], 3));
+    /// ```
+    pub fn peek_bytes(buf: &[u8], max_len: usize) -> Result<(&[u8], usize), Error> {
+        let (len, prefix_len) = peek_unsigned(buf)?;
+        let len = check_len(len, max_len)?;
+        match buf[prefix_len..].get(..len) {
+            Some(contents) => Ok((contents, prefix_len + len)),
+            None => Err(Error::IoError(io::ErrorKind::UnexpectedEof.into())),
+        }
+    }
+
+    /// Decode a UTF-8 string prefixed by its unsigned LEB128 encoded length in
+    /// bytes from the start of `buf`, borrowing it rather than copying.
+    /// Returns the string along with the total length of the prefix and
+    /// contents.
+    pub fn peek_string(buf: &[u8], max_len: usize) -> Result<(&str, usize), Error> {
+        let (contents, len) = peek_bytes(buf, max_len)?;
+        let s = str::from_utf8(contents).map_err(Error::InvalidUtf8)?;
+        Ok((s, len))
+    }
+
+    /// The default maximum element count used by `Limits::default()`.
+    pub const DEFAULT_MAX_COUNT: usize = 1 << 20;
+
+    /// Limits applied to the element count of a length-prefixed vector before
+    /// any space is allocated for its elements.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub struct Limits {
+        /// The largest element count that will be accepted. Larger counts are
+        /// rejected with `Error::TooLong`.
+        pub max_count: usize,
+        /// The number of bytes known to remain in the input, counting from the
+        /// start of the element count, if known. Every element is assumed to
+        /// occupy at least one byte, so counts that could not possibly fit are
+        /// rejected with an `UnexpectedEof` IO error.
+        pub remaining_input: Option<usize>,
+    }
+
+    impl Limits {
+        /// Limits accepting at most `max_count` elements, with no knowledge of
+        /// how much input remains.
+        pub fn new(max_count: usize) -> Limits {
+            Limits {
+                max_count,
+                remaining_input: None,
+            }
+        }
+
+        /// These limits, additionally bounded by the number of bytes that
+        /// remain in the input.
+        pub fn with_remaining_input(self, remaining_input: usize) -> Limits {
+            Limits {
+                remaining_input: Some(remaining_input),
+                ..self
+            }
+        }
+    }
+
+    impl Default for Limits {
+        fn default() -> Limits {
+            Limits::new(DEFAULT_MAX_COUNT)
+        }
+    }
+
+    /// Read a vector of elements prefixed by its unsigned LEB128 encoded
+    /// element count from the given `std::io::Read`able, reading each element
+    /// with `read_elem`.
+    ///
+    /// The count is checked against `limits` before space is allocated for
+    /// the elements.
+    ///
+    /// ```
+    /// let buf = [0x02, 0x80, 0x01, 0x05];
+    /// let vec = leb128::read::vec_with(&mut &buf[..], Default::default(), |r| {
+    ///     leb128::read::unsigned(r)
+    /// }).unwrap();
+    /// assert_eq!(vec, [128, 5]);
+    /// ```
+    pub fn vec_with<R, T, F>(r: &mut R, limits: Limits, mut read_elem: F) -> Result<Vec<T>, Error>
+    where
+        R: io::Read,
+        F: FnMut(&mut R) -> Result<T, Error>,
+    {
+        let mut prefix_len = 0;
+        let count = decode_unsigned(|| {
+            let mut buf = [0];
+            r.read_exact(&mut buf)?;
+            prefix_len += 1;
+            Ok(buf[0])
+        })?;
+        let count = check_len(count, limits.max_count)?;
+        if let Some(remaining) = limits.remaining_input {
+            if count > remaining.saturating_sub(prefix_len) {
+                return Err(Error::IoError(io::ErrorKind::UnexpectedEof.into()));
+            }
+        }
+
+        let mut vec = Vec::with_capacity(count);
+        for _ in 0..count {
+            vec.push(read_elem(r)?);
+        }
+        Ok(vec)
+    }
+
+    /// Read a vector of unsigned LEB128 numbers prefixed by its unsigned
+    /// LEB128 encoded element count from the given `std::io::Read`able. The
+    /// count is checked against `limits` before anything is allocated.
+    pub fn vec_unsigned<R>(r: &mut R, limits: Limits) -> Result<Vec<u64>, Error>
+    where
+        R: io::Read,
+    {
+        vec_with(r, limits, |r| unsigned(r))
+    }
+
+    /// Read a vector of signed LEB128 numbers prefixed by its unsigned LEB128
+    /// encoded element count from the given `std::io::Read`able. The count is
+    /// checked against `limits` before anything is allocated.
+    pub fn vec_signed<R>(r: &mut R, limits: Limits) -> Result<Vec<i64>, Error>
+    where
+        R: io::Read,
+    {
+        vec_with(r, limits, |r| signed(r))
+    }
 }
 
 /// A module for writing integers encoded as LEB128.
 pub mod write {
+    use super::zigzag::{zigzag_encode, ZigZag};
     use super::{low_bits_of_u64, CONTINUATION_BIT};
+    use std::fmt;
     use std::io;
 
-    /// Write the given unsigned number using the LEB128 encoding to the given
-    /// `std::io::Write`able. Returns the number of bytes written to `w`, or an
-    /// error if writing failed.
-    pub fn unsigned<W>(w: &mut W, mut val: u64) -> Result<usize, io::Error>
+    /// The maximum number of bytes a 64-bit number can occupy when LEB128
+    /// encoded.
+    const MAX_ENCODED_LEN: usize = 10;
+
+    /// An error that occurred while writing a LEB128 encoded number.
+    ///
+    /// Each number is handed to the underlying writer as a single buffer, so
+    /// a failed write either wrote nothing or only a prefix of the encoding.
+    /// The length of that prefix is available through `bytes_written`.
+    #[derive(Debug)]
+    pub struct Error {
+        bytes_written: usize,
+        error: io::Error,
+    }
+
+    impl Error {
+        /// The number of bytes of the encoding that reached the writer before
+        /// the error occurred.
+        pub fn bytes_written(&self) -> usize {
+            self.bytes_written
+        }
+
+        /// The underlying IO error.
+        pub fn io_error(&self) -> &io::Error {
+            &self.error
+        }
+
+        /// Consume this error and return the underlying IO error.
+        pub fn into_io_error(self) -> io::Error {
+            self.error
+        }
+    }
+
+    /// An `InvalidInput` error for a number that cannot be written, reported
+    /// before anything reached the writer.
+    pub(crate) fn invalid_input(msg: &'static str) -> Error {
+        Error {
+            bytes_written: 0,
+            error: io::Error::new(io::ErrorKind::InvalidInput, msg),
+        }
+    }
+
+    impl From<Error> for io::Error {
+        fn from(e: Error) -> Self {
+            e.error
+        }
+    }
+
+    impl fmt::Display for Error {
+        fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+            write!(
+                f,
+                "leb128::write::Error: {} (after writing {} bytes)",
+                self.error, self.bytes_written
+            )
+        }
+    }
+
+    impl std::error::Error for Error {
+        fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+            Some(&self.error)
+        }
+    }
+
+    /// Write all of `buf` to `w`, keeping track of how much of it was written
+    /// if an error occurs part way through.
+    pub(crate) fn write_encoded<W>(w: &mut W, buf: &[u8]) -> Result<usize, Error>
     where
         W: ?Sized + io::Write,
     {
         let mut bytes_written = 0;
+        while bytes_written < buf.len() {
+            match w.write(&buf[bytes_written..]) {
+                Ok(0) => {
+                    return Err(Error {
+                        bytes_written,
+                        error: io::Error::new(
+                            io::ErrorKind::WriteZero,
+                            "failed to write whole buffer",
+                        ),
+                    });
+                }
+                Ok(n) => bytes_written += n,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(error) => {
+                    return Err(Error {
+                        bytes_written,
+                        error,
+                    });
+                }
+            }
+        }
+        Ok(bytes_written)
+    }
+
+    /// Encode the given unsigned number into `buf`, returning the length of
+    /// the encoding.
+    fn encode_unsigned(buf: &mut [u8; MAX_ENCODED_LEN], mut val: u64) -> usize {
+        let mut len = 0;
         loop {
             let mut byte = low_bits_of_u64(val);
             val >>= 7;
@@ -196,24 +679,19 @@ pub mod write {
                 byte |= CONTINUATION_BIT;
             }
 
-            let buf = [byte];
-            try!(w.write_all(&buf));
-            bytes_written += 1;
+            buf[len] = byte;
+            len += 1;
 
             if val == 0 {
-                return Ok(bytes_written);
+                return len;
             }
         }
     }
 
-    /// Write the given signed number using the LEB128 encoding to the given
-    /// `std::io::Write`able. Returns the number of bytes written to `w`, or an
-    /// error if writing failed.
-    pub fn signed<W>(w: &mut W, mut val: i64) -> Result<usize, io::Error>
-    where
-        W: ?Sized + io::Write,
-    {
-        let mut bytes_written = 0;
+    /// Encode the given signed number into `buf`, returning the length of the
+    /// encoding.
+    fn encode_signed(buf: &mut [u8; MAX_ENCODED_LEN], mut val: i64) -> usize {
+        let mut len = 0;
         loop {
             let mut byte = val as u8;
             // Keep the sign bit for testing
@@ -228,21 +706,220 @@ pub mod write {
                 byte |= CONTINUATION_BIT;
             }
 
-            let buf = [byte];
-            try!(w.write_all(&buf));
-            bytes_written += 1;
+            buf[len] = byte;
+            len += 1;
 
             if done {
-                return Ok(bytes_written);
+                return len;
             }
         }
     }
+
+    /// Write the given unsigned number using the LEB128 encoding to the given
+    /// `std::io::Write`able. Returns the number of bytes written to `w`, or an
+    /// error if writing failed.
+    ///
+    /// The number is encoded into a buffer on the stack and then handed to
+    /// `w` in one go, rather than one byte at a time.
+    pub fn unsigned<W>(w: &mut W, val: u64) -> Result<usize, Error>
+    where
+        W: ?Sized + io::Write,
+    {
+        let mut buf = [0; MAX_ENCODED_LEN];
+        let len = encode_unsigned(&mut buf, val);
+        write_encoded(w, &buf[..len])
+    }
+
+    /// Write the given signed number using the LEB128 encoding to the given
+    /// `std::io::Write`able. Returns the number of bytes written to `w`, or an
+    /// error if writing failed.
+    ///
+    /// The number is encoded into a buffer on the stack and then handed to
+    /// `w` in one go, rather than one byte at a time.
+    pub fn signed<W>(w: &mut W, val: i64) -> Result<usize, Error>
+    where
+        W: ?Sized + io::Write,
+    {
+        let mut buf = [0; MAX_ENCODED_LEN];
+        let len = encode_signed(&mut buf, val);
+        write_encoded(w, &buf[..len])
+    }
+
+    /// Write the given signed number of any width using ZigZag encoding to
+    /// the given `std::io::Write`able. See the `zigzag` module. Returns the
+    /// number of bytes written to `w`, or an error if writing failed.
+    pub fn zigzag<W, T>(w: &mut W, val: T) -> Result<usize, Error>
+    where
+        W: ?Sized + io::Write,
+        T: ZigZag,
+    {
+        unsigned(w, zigzag_encode(val.to_i64()))
+    }
+
+    /// Extend the `len` byte encoding in `buf` to exactly `width` bytes with
+    /// redundant continuation bytes carrying `fill` in their low bits.
+    fn pad(
+        buf: &mut [u8; MAX_ENCODED_LEN],
+        len: usize,
+        width: usize,
+        fill: u8,
+    ) -> Result<usize, Error> {
+        if width < len || width > MAX_ENCODED_LEN {
+            return Err(invalid_input(
+                "number cannot be encoded in the requested width",
+            ));
+        }
+
+        if len < width {
+            buf[len - 1] |= CONTINUATION_BIT;
+            for byte in &mut buf[len..width - 1] {
+                *byte = fill | CONTINUATION_BIT;
+            }
+            buf[width - 1] = fill;
+        }
+        Ok(width)
+    }
+
+    /// Write the given unsigned number using the LEB128 encoding, padded with
+    /// redundant continuation bytes so that it occupies exactly `width` bytes.
+    /// This is useful for reserving space for a number that will be patched
+    /// later. Returns an `InvalidInput` error without writing anything if the
+    /// number needs more than `width` bytes, or if `width` is more than the
+    /// ten bytes any 64-bit number can need.
+    ///
+    /// ```
+    /// let mut buf = vec![];
+    /// leb128::write::unsigned_padded(&mut buf, 3, 5).unwrap();
+    /// assert_eq!(buf, [0x83, 0x80, 0x80, 0x80, 0x00]);
+    /// ```
+    pub fn unsigned_padded<W>(w: &mut W, val: u64, width: usize) -> Result<usize, Error>
+    where
+        W: ?Sized + io::Write,
+    {
+        let mut buf = [0; MAX_ENCODED_LEN];
+        let len = encode_unsigned(&mut buf, val);
+        let len = pad(&mut buf, len, width, 0)?;
+        write_encoded(w, &buf[..len])
+    }
+
+    /// Write the given signed number using the LEB128 encoding, padded with
+    /// redundant continuation bytes so that it occupies exactly `width` bytes.
+    /// This is useful for reserving space for a number that will be patched
+    /// later. Returns an `InvalidInput` error without writing anything if the
+    /// number needs more than `width` bytes, or if `width` is more than the
+    /// ten bytes any 64-bit number can need.
+    ///
+    /// ```
+    /// let mut buf = vec![];
+    /// leb128::write::signed_padded(&mut buf, -2, 3).unwrap();
+    /// assert_eq!(buf, [0xfe, 0xff, 0x7f]);
+    /// ```
+    pub fn signed_padded<W>(w: &mut W, val: i64, width: usize) -> Result<usize, Error>
+    where
+        W: ?Sized + io::Write,
+    {
+        let mut buf = [0; MAX_ENCODED_LEN];
+        let len = encode_signed(&mut buf, val);
+        let fill = if val < 0 { !CONTINUATION_BIT } else { 0 };
+        let len = pad(&mut buf, len, width, fill)?;
+        write_encoded(w, &buf[..len])
+    }
+
+    /// Write the given byte string prefixed by its unsigned LEB128 encoded
+    /// length to the given `std::io::Write`able. Returns the total number of
+    /// bytes written to `w`, or an error if writing failed.
+    pub fn bytes<W>(w: &mut W, bytes: &[u8]) -> Result<usize, Error>
+    where
+        W: ?Sized + io::Write,
+    {
+        let prefix_len = unsigned(w, bytes.len() as u64)?;
+        match write_encoded(w, bytes) {
+            Ok(len) => Ok(prefix_len + len),
+            Err(mut e) => {
+                e.bytes_written += prefix_len;
+                Err(e)
+            }
+        }
+    }
+
+    /// Write the given string prefixed by its unsigned LEB128 encoded length
+    /// in bytes to the given `std::io::Write`able. Returns the total number of
+    /// bytes written to `w`, or an error if writing failed.
+    pub fn str<W>(w: &mut W, s: &str) -> Result<usize, Error>
+    where
+        W: ?Sized + io::Write,
+    {
+        bytes(w, s.as_bytes())
+    }
+
+    /// Write the given elements prefixed by their unsigned LEB128 encoded
+    /// count to the given `std::io::Write`able, writing each element with
+    /// `write_elem`. Returns the total number of bytes written to `w`, or an
+    /// error if writing failed.
+    pub fn vec_with<W, T, F>(w: &mut W, elems: &[T], mut write_elem: F) -> Result<usize, Error>
+    where
+        W: ?Sized + io::Write,
+        F: FnMut(&mut W, &T) -> Result<usize, Error>,
+    {
+        let mut total = unsigned(w, elems.len() as u64)?;
+        for elem in elems {
+            match write_elem(w, elem) {
+                Ok(len) => total += len,
+                Err(mut e) => {
+                    e.bytes_written += total;
+                    return Err(e);
+                }
+            }
+        }
+        Ok(total)
+    }
+
+    /// Write the given numbers as unsigned LEB128, prefixed by their unsigned
+    /// LEB128 encoded count. The whole vector is encoded up front and handed
+    /// to `w` in one go. Returns the total number of bytes written to `w`, or
+    /// an error if writing failed.
+    pub fn vec_unsigned<W>(w: &mut W, vals: &[u64]) -> Result<usize, Error>
+    where
+        W: ?Sized + io::Write,
+    {
+        let mut buf = Vec::with_capacity(vals.len() + 1);
+        vec_with(&mut buf, vals, |buf, &val| unsigned(buf, val))?;
+        write_encoded(w, &buf)
+    }
+
+    /// Write the given numbers as signed LEB128, prefixed by their unsigned
+    /// LEB128 encoded count. The whole vector is encoded up front and handed
+    /// to `w` in one go. Returns the total number of bytes written to `w`, or
+    /// an error if writing failed.
+    pub fn vec_signed<W>(w: &mut W, vals: &[i64]) -> Result<usize, Error>
+    where
+        W: ?Sized + io::Write,
+    {
+        let mut buf = Vec::with_capacity(vals.len() + 1);
+        vec_with(&mut buf, vals, |buf, &val| signed(buf, val))?;
+        write_encoded(w, &buf)
+    }
 }
 
+pub mod aps2;
+pub mod codec;
+pub mod delta;
+pub mod dex;
+pub mod dwarf;
+pub mod git;
+pub mod macho;
+pub mod oid;
+pub mod protobuf;
+pub mod reader;
+pub mod sqlite;
+pub mod vlq;
+pub mod wasm;
+pub mod writer;
+pub mod zigzag;
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std;
     use std::io;
 
     #[test]
@@ -409,7 +1086,10 @@ mod tests {
         let mut buf = [0; 1];
         let mut writable = &mut buf[..];
         match write::unsigned(&mut writable, 128) {
-            Err(e) => assert_eq!(e.kind(), io::ErrorKind::WriteZero),
+            Err(e) => {
+                assert_eq!(e.io_error().kind(), io::ErrorKind::WriteZero);
+                assert_eq!(e.bytes_written(), 1);
+            }
             otherwise => panic!("Unexpected: {:?}", otherwise),
         }
     }
@@ -419,11 +1099,200 @@ mod tests {
         let mut buf = [0; 1];
         let mut writable = &mut buf[..];
         match write::signed(&mut writable, 128) {
-            Err(e) => assert_eq!(e.kind(), io::ErrorKind::WriteZero),
+            Err(e) => {
+                assert_eq!(e.io_error().kind(), io::ErrorKind::WriteZero);
+                assert_eq!(e.bytes_written(), 1);
+            }
             otherwise => panic!("Unexpected: {:?}", otherwise),
         }
     }
 
+    #[test]
+    fn test_write_is_a_single_write() {
+        struct CountingWriter {
+            writes: usize,
+            bytes: Vec<u8>,
+        }
+
+        impl io::Write for CountingWriter {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                self.writes += 1;
+                self.bytes.extend_from_slice(buf);
+                Ok(buf.len())
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let mut w = CountingWriter {
+            writes: 0,
+            bytes: vec![],
+        };
+        assert_eq!(write::unsigned(&mut w, u64::MAX).unwrap(), 10);
+        assert_eq!(w.writes, 1);
+        assert_eq!(write::signed(&mut w, i64::MIN).unwrap(), 10);
+        assert_eq!(w.writes, 2);
+        assert_eq!(w.bytes.len(), 20);
+    }
+
+    #[test]
+    fn test_write_padded() {
+        let mut buf = vec![];
+        assert_eq!(write::unsigned_padded(&mut buf, 0, 1).unwrap(), 1);
+        assert_eq!(write::unsigned_padded(&mut buf, 128, 3).unwrap(), 3);
+        assert_eq!(write::signed_padded(&mut buf, 63, 2).unwrap(), 2);
+        assert_eq!(write::signed_padded(&mut buf, -65, 4).unwrap(), 4);
+        assert_eq!(
+            buf,
+            [0x00, 0x80, 0x81, 0x00, 0xbf, 0x00, 0xbf, 0xff, 0xff, 0x7f]
+        );
+
+        let mut readable = &buf[..];
+        assert_eq!(read::unsigned(&mut readable).unwrap(), 0);
+        assert_eq!(read::unsigned(&mut readable).unwrap(), 128);
+        assert_eq!(read::signed(&mut readable).unwrap(), 63);
+        assert_eq!(read::signed(&mut readable).unwrap(), -65);
+
+        let mut buf = vec![];
+        write::unsigned_padded(&mut buf, u64::MAX, 10).unwrap();
+        write::signed_padded(&mut buf, -1, 10).unwrap();
+        let mut readable = &buf[..];
+        assert_eq!(read::unsigned(&mut readable).unwrap(), u64::MAX);
+        assert_eq!(read::signed(&mut readable).unwrap(), -1);
+    }
+
+    #[test]
+    fn test_write_padded_too_narrow() {
+        let mut buf = vec![];
+        for &(val, width) in &[(128, 1), (0, 0), (0, 11)] {
+            match write::unsigned_padded(&mut buf, val, width) {
+                Err(e) => {
+                    assert_eq!(e.io_error().kind(), io::ErrorKind::InvalidInput);
+                    assert_eq!(e.bytes_written(), 0);
+                }
+                otherwise => panic!("Unexpected: {:?}", otherwise),
+            }
+        }
+        assert!(write::signed_padded(&mut buf, 64, 1).is_err());
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn test_length_prefixed() {
+        let mut buf = vec![];
+        assert_eq!(write::bytes(&mut buf, &[0xff; 200]).unwrap(), 202);
+        assert_eq!(write::str(&mut buf, "héllo").unwrap(), 7);
+        assert_eq!(write::str(&mut buf, "").unwrap(), 1);
+
+        let mut readable = &buf[..];
+        assert_eq!(read::bytes(&mut readable, 200).unwrap(), vec![0xff; 200]);
+        assert_eq!(read::string(&mut readable, 6).unwrap(), "héllo");
+        assert_eq!(read::string(&mut readable, 0).unwrap(), "");
+        assert!(readable.is_empty());
+
+        assert_eq!(read::peek_bytes(&buf, 200).unwrap().1, 202);
+        assert_eq!(read::peek_string(&buf[202..], 6).unwrap(), ("héllo", 7));
+    }
+
+    #[test]
+    fn test_length_prefixed_errors() {
+        let buf = [0x05, b'a', b'b'];
+        match read::bytes(&mut &buf[..], 4) {
+            Err(read::Error::TooLong) => {}
+            otherwise => panic!("Unexpected: {:?}", otherwise),
+        }
+        match read::bytes(&mut &buf[..], 5) {
+            Err(read::Error::IoError(e)) => assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof),
+            otherwise => panic!("Unexpected: {:?}", otherwise),
+        }
+        match read::peek_bytes(&buf, 5) {
+            Err(read::Error::IoError(e)) => assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof),
+            otherwise => panic!("Unexpected: {:?}", otherwise),
+        }
+
+        // A huge length is rejected without trying to allocate for it.
+        let buf = [0xff, 0xff, 0xff, 0xff, 0x0f];
+        match read::bytes(&mut &buf[..], usize::MAX) {
+            Err(read::Error::IoError(e)) => assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof),
+            otherwise => panic!("Unexpected: {:?}", otherwise),
+        }
+
+        let buf = [0x02, 0xc3, 0x28];
+        match read::string(&mut &buf[..], 2) {
+            Err(read::Error::InvalidUtf8(_)) => {}
+            otherwise => panic!("Unexpected: {:?}", otherwise),
+        }
+        match read::peek_string(&buf, 2) {
+            Err(read::Error::InvalidUtf8(_)) => {}
+            otherwise => panic!("Unexpected: {:?}", otherwise),
+        }
+
+        let mut buf = [0; 3];
+        match write::str(&mut &mut buf[..], "abc") {
+            Err(e) => {
+                assert_eq!(e.io_error().kind(), io::ErrorKind::WriteZero);
+                assert_eq!(e.bytes_written(), 3);
+            }
+            otherwise => panic!("Unexpected: {:?}", otherwise),
+        }
+    }
+
+    #[test]
+    fn test_vec() {
+        let mut buf = vec![];
+        assert_eq!(write::vec_unsigned(&mut buf, &[1, 128, 0]).unwrap(), 5);
+        assert_eq!(write::vec_signed(&mut buf, &[-1, 64]).unwrap(), 4);
+        assert_eq!(write::vec_unsigned(&mut buf, &[]).unwrap(), 1);
+        assert_eq!(
+            buf,
+            [0x03, 0x01, 0x80, 0x01, 0x00, 0x02, 0x7f, 0xc0, 0x00, 0x00]
+        );
+
+        let mut readable = &buf[..];
+        let limits = read::Limits::new(3).with_remaining_input(readable.len());
+        assert_eq!(
+            read::vec_unsigned(&mut readable, limits).unwrap(),
+            [1, 128, 0]
+        );
+        assert_eq!(
+            read::vec_signed(&mut readable, Default::default()).unwrap(),
+            [-1, 64]
+        );
+        assert!(read::vec_unsigned(&mut readable, read::Limits::new(0))
+            .unwrap()
+            .is_empty());
+        assert!(readable.is_empty());
+    }
+
+    #[test]
+    fn test_vec_limits() {
+        let buf = [0x03, 0x01, 0x02, 0x03];
+        match read::vec_unsigned(&mut &buf[..], read::Limits::new(2)) {
+            Err(read::Error::TooLong) => {}
+            otherwise => panic!("Unexpected: {:?}", otherwise),
+        }
+
+        // A count of 2^32 is rejected against the size of the input without
+        // reading any elements or allocating space for them.
+        let buf = [0x80, 0x80, 0x80, 0x80, 0x10, 0x00];
+        let limits = read::Limits::new(usize::MAX).with_remaining_input(buf.len());
+        match read::vec_with(&mut &buf[..], limits, |_| -> Result<u8, _> {
+            panic!("should not read any elements")
+        }) {
+            Err(read::Error::IoError(e)) => assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof),
+            otherwise => panic!("Unexpected: {:?}", otherwise),
+        }
+
+        // The remaining input counts from the start of the count itself.
+        let buf = [0x02, 0x01, 0x02];
+        let limits = read::Limits::default().with_remaining_input(2);
+        assert!(read::vec_unsigned(&mut &buf[..], limits).is_err());
+        let limits = read::Limits::default().with_remaining_input(3);
+        assert_eq!(read::vec_unsigned(&mut &buf[..], limits).unwrap(), [1, 2]);
+    }
+
     #[test]
     fn dogfood_signed() {
         fn inner(i: i64) {
@@ -441,7 +1310,7 @@ mod tests {
         for i in -513..513 {
             inner(i);
         }
-        inner(std::i64::MIN);
+        inner(i64::MIN);
     }
 
     #[test]
@@ -539,6 +1408,138 @@ mod tests {
         assert!(read::signed(&mut readable).is_err());
     }
 
+    #[test]
+    fn test_peek_does_not_consume() {
+        let buf = [2u8 | CONTINUATION_BIT, 1u8, 0x7e];
+        assert_eq!(read::peek_unsigned(&buf).unwrap(), (130, 2));
+        assert_eq!(read::peek_signed(&buf).unwrap(), (130, 2));
+        assert_eq!(read::peek_unsigned(&buf[2..]).unwrap(), (0x7e, 1));
+        assert_eq!(read::peek_signed(&buf[2..]).unwrap(), (-2, 1));
+    }
+
+    #[test]
+    fn test_peek_not_enough_data() {
+        let buf = [CONTINUATION_BIT, CONTINUATION_BIT];
+        match read::peek_unsigned(&buf) {
+            Err(read::Error::IoError(e)) => assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof),
+            otherwise => panic!("Unexpected: {:?}", otherwise),
+        }
+        match read::peek_signed(&[]) {
+            Err(read::Error::IoError(e)) => assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof),
+            otherwise => panic!("Unexpected: {:?}", otherwise),
+        }
+    }
+
+    #[test]
+    fn test_peek_overflow() {
+        let buf = [0xff; 11];
+        match read::peek_unsigned(&buf) {
+            Err(read::Error::Overflow) => {}
+            otherwise => panic!("Unexpected: {:?}", otherwise),
+        }
+        match read::peek_signed(&buf) {
+            Err(read::Error::Overflow) => {}
+            otherwise => panic!("Unexpected: {:?}", otherwise),
+        }
+    }
+
+    #[test]
+    fn test_peek_buffered() {
+        use std::io::BufRead;
+
+        let buf = [CONTINUATION_BIT, 1, 0x7f, CONTINUATION_BIT];
+        let mut readable = io::BufReader::new(&buf[..]);
+
+        assert_eq!(
+            read::peek_unsigned_buffered(&mut readable).unwrap(),
+            (128, 2)
+        );
+        assert_eq!(read::peek_signed_buffered(&mut readable).unwrap(), (128, 2));
+        readable.consume(2);
+
+        assert_eq!(read::peek_signed_buffered(&mut readable).unwrap(), (-1, 1));
+        readable.consume(1);
+
+        match read::peek_unsigned_buffered(&mut readable) {
+            Err(read::Error::Incomplete) => {}
+            otherwise => panic!("Unexpected: {:?}", otherwise),
+        }
+        assert_eq!(readable.fill_buf().unwrap(), &[CONTINUATION_BIT]);
+        readable.consume(1);
+
+        match read::peek_unsigned_buffered(&mut readable) {
+            Err(read::Error::IoError(e)) => assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof),
+            otherwise => panic!("Unexpected: {:?}", otherwise),
+        }
+    }
+
+    #[test]
+    fn test_peek_buffered_split() {
+        use std::io::BufRead;
+
+        // The number 128 straddles the end of the reader's buffer.
+        let buf = [0, 0, 0, CONTINUATION_BIT, 1];
+        let mut readable = io::BufReader::with_capacity(4, &buf[..]);
+        readable.fill_buf().unwrap();
+        readable.consume(3);
+
+        match read::peek_unsigned_buffered(&mut readable) {
+            Err(read::Error::Incomplete) => {}
+            otherwise => panic!("Unexpected: {:?}", otherwise),
+        }
+        assert_eq!(readable.fill_buf().unwrap(), &[CONTINUATION_BIT]);
+
+        // Reading goes on past the buffered byte.
+        assert_eq!(read::signed_buffered(&mut readable).unwrap(), 128);
+    }
+
+    #[test]
+    fn test_read_buffered_split() {
+        // Every three byte number straddles a two byte buffer.
+        let buf = [
+            CONTINUATION_BIT,
+            CONTINUATION_BIT,
+            1,
+            0xff,
+            0xff,
+            0x7f,
+            CONTINUATION_BIT,
+            CONTINUATION_BIT,
+        ];
+        let mut readable = io::BufReader::with_capacity(2, &buf[..]);
+        assert_eq!(read::unsigned_buffered(&mut readable).unwrap(), 1 << 14);
+        assert_eq!(read::signed_buffered(&mut readable).unwrap(), -1);
+
+        // The end of the input is reported as such.
+        match read::unsigned_buffered(&mut readable) {
+            Err(read::Error::IoError(e)) => assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof),
+            otherwise => panic!("Unexpected: {:?}", otherwise),
+        }
+    }
+
+    #[test]
+    fn test_read_buffered_leaves_input_on_error() {
+        let buf = [CONTINUATION_BIT, 1, 0xff, 0xff];
+        let mut readable = &buf[..];
+        assert_eq!(read::unsigned_buffered(&mut readable).unwrap(), 128);
+        assert_eq!(readable, &buf[2..]);
+
+        // A number cut short by the end of a slice gives the same error as
+        // `read::signed`.
+        match read::signed_buffered(&mut readable) {
+            Err(read::Error::IoError(e)) => assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof),
+            otherwise => panic!("Unexpected: {:?}", otherwise),
+        }
+
+        let overflow = [0xff; 11];
+        let mut readable = &overflow[..];
+        match read::unsigned_buffered(&mut readable) {
+            Err(read::Error::Overflow) => {}
+            otherwise => panic!("Unexpected: {:?}", otherwise),
+        }
+        assert_eq!(readable.len(), overflow.len());
+    }
+
     #[test]
     fn test_read_multiple() {
         let buf = [2u8 | CONTINUATION_BIT, 1u8, 1u8];
@@ -0,0 +1,682 @@
+//! Decode the LEB128 based structures in the dyld info of Mach-O images: the
+//! export trie, and the bind and rebase opcode streams.
+//!
+//! The export trie maps symbol names to their exported addresses. Each node
+//! holds a ULEB128 size of its terminal information (zero if no symbol ends
+//! there), the terminal information itself, and then its outgoing edges, each
+//! a NUL-terminated label followed by the ULEB128 offset of the child node.
+//!
+//! ```
+//! use leb128::macho::{self, ExportTarget};
+//!
+//! // A trie with a single symbol, `_main`, at 0x3f50.
+//! let trie = [
+//!     0x00, 0x01, b'_', b'm', b'a', b'i', b'n', 0x00, 0x09,
+//!     0x03, 0x00, 0xd0, 0x7e, 0x00,
+//! ];
+//! let exports = macho::exports(&trie).unwrap();
+//! assert_eq!(exports[0].symbol, "_main");
+//! assert_eq!(exports[0].target, ExportTarget::Address(0x3f50));
+//! ```
+//!
+//! Bind and rebase streams are sequences of one-byte opcodes, each with a
+//! four-bit immediate and, for some opcodes, ULEB128 or SLEB128 operands. The
+//! `BindOpcodes` and `RebaseOpcodes` iterators disassemble them.
+//!
+//! `LC_FUNCTION_STARTS` data is a zero-terminated list of ULEB128 deltas
+//! between function addresses; see the `delta` module.
+
+use super::delta::{self, Terminator};
+use super::reader::{self, ErrorKind, Leb128Reader};
+use super::write;
+use std::collections::HashSet;
+use std::fmt;
+use std::io;
+
+/// The mask for the kind of an exported symbol in its flags.
+pub const EXPORT_SYMBOL_FLAGS_KIND_MASK: u64 = 0x03;
+/// A regular exported symbol.
+pub const EXPORT_SYMBOL_FLAGS_KIND_REGULAR: u64 = 0x00;
+/// A thread-local exported symbol.
+pub const EXPORT_SYMBOL_FLAGS_KIND_THREAD_LOCAL: u64 = 0x01;
+/// An exported symbol with an absolute address.
+pub const EXPORT_SYMBOL_FLAGS_KIND_ABSOLUTE: u64 = 0x02;
+/// The exported symbol is a weak definition.
+pub const EXPORT_SYMBOL_FLAGS_WEAK_DEFINITION: u64 = 0x04;
+/// The exported symbol is re-exported from another library.
+pub const EXPORT_SYMBOL_FLAGS_REEXPORT: u64 = 0x08;
+/// The exported symbol is a stub with a resolver function.
+pub const EXPORT_SYMBOL_FLAGS_STUB_AND_RESOLVER: u64 = 0x10;
+
+const OPCODE_MASK: u8 = 0xf0;
+const IMMEDIATE_MASK: u8 = 0x0f;
+
+/// An error that occurred while decoding dyld info.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// A number, string or node could not be read.
+    Read(reader::Error),
+    /// An export trie edge pointed outside the trie, or at a node that had
+    /// already been reached. Carries the offset of the edge's child offset and
+    /// the child offset itself.
+    InvalidChildOffset(usize, u64),
+    /// A bind or rebase stream contained an unknown opcode. Carries the offset
+    /// and value of the opcode byte.
+    UnknownOpcode(usize, u8),
+}
+
+impl From<reader::Error> for Error {
+    fn from(e: reader::Error) -> Self {
+        Error::Read(e)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match *self {
+            Error::Read(ref e) => write!(f, "leb128::macho::Error: {}", e),
+            Error::InvalidChildOffset(offset, child) => write!(
+                f,
+                "leb128::macho::Error: Invalid export trie child offset {:#x} at offset {:#x}",
+                child, offset
+            ),
+            Error::UnknownOpcode(offset, opcode) => write!(
+                f,
+                "leb128::macho::Error: Unknown opcode {:#04x} at offset {:#x}",
+                opcode, offset
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match *self {
+            Error::Read(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// Where an exported symbol can be found.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ExportTarget {
+    /// The symbol's address, relative to the start of the image.
+    Address(u64),
+    /// A stub that calls `resolver` the first time it is used, both relative
+    /// to the start of the image.
+    StubAndResolver {
+        /// The address of the stub.
+        stub: u64,
+        /// The address of the resolver function.
+        resolver: u64,
+    },
+    /// A symbol defined in another library.
+    Reexport {
+        /// The ordinal of the library the symbol is imported from.
+        dylib_ordinal: u64,
+        /// The symbol's name in that library, if it differs from the exported
+        /// name.
+        imported_name: Option<String>,
+    },
+}
+
+/// An exported symbol found in an export trie.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Export {
+    /// The symbol's name.
+    pub symbol: String,
+    /// The `EXPORT_SYMBOL_FLAGS_*` flags of the symbol.
+    pub flags: u64,
+    /// Where the symbol can be found.
+    pub target: ExportTarget,
+}
+
+impl Export {
+    /// The address of the symbol (or of its stub), relative to the start of
+    /// the image, or `None` for re-exports.
+    pub fn address(&self) -> Option<u64> {
+        match self.target {
+            ExportTarget::Address(address) => Some(address),
+            ExportTarget::StubAndResolver { stub, .. } => Some(stub),
+            ExportTarget::Reexport { .. } => None,
+        }
+    }
+}
+
+/// Decode the terminal information of an export trie node.
+fn read_export_target(terminal: &mut Leb128Reader, flags: u64) -> Result<ExportTarget, Error> {
+    if flags & EXPORT_SYMBOL_FLAGS_REEXPORT != 0 {
+        let dylib_ordinal = terminal.read_uleb128()?;
+        let imported_name = match terminal.read_cstr()? {
+            "" => None,
+            name => Some(name.to_string()),
+        };
+        return Ok(ExportTarget::Reexport {
+            dylib_ordinal,
+            imported_name,
+        });
+    }
+
+    let address = terminal.read_uleb128()?;
+    if flags & EXPORT_SYMBOL_FLAGS_STUB_AND_RESOLVER != 0 {
+        let resolver = terminal.read_uleb128()?;
+        Ok(ExportTarget::StubAndResolver {
+            stub: address,
+            resolver,
+        })
+    } else {
+        Ok(ExportTarget::Address(address))
+    }
+}
+
+/// Walk the export trie in `trie`, returning every exported symbol in the
+/// order of the trie's edges.
+///
+/// Every node must be reachable by exactly one path, so tries with cycles or
+/// shared nodes are rejected with `Error::InvalidChildOffset`.
+pub fn exports(trie: &[u8]) -> Result<Vec<Export>, Error> {
+    let mut exports = vec![];
+    if trie.is_empty() {
+        return Ok(exports);
+    }
+
+    let mut visited = HashSet::new();
+    visited.insert(0);
+    let mut stack = vec![(0, vec![])];
+
+    while let Some((node, prefix)) = stack.pop() {
+        let mut reader = Leb128Reader::new(trie);
+        reader.seek(node)?;
+
+        let size_offset = reader.offset();
+        let terminal_size = reader.read_uleb128()?;
+        if terminal_size > reader.remaining() as u64 {
+            return Err(reader::Error::new(size_offset, ErrorKind::UnexpectedEof).into());
+        }
+        let mut terminal = reader.sub_reader(terminal_size as usize)?;
+
+        if terminal_size != 0 {
+            let symbol = String::from_utf8(prefix.clone())
+                .map_err(|_| reader::Error::new(node, ErrorKind::InvalidUtf8))?;
+            let flags = terminal.read_uleb128()?;
+            let target = read_export_target(&mut terminal, flags)?;
+            exports.push(Export {
+                symbol,
+                flags,
+                target,
+            });
+        }
+
+        let child_count = reader.read_u8()?;
+        let mut children = Vec::with_capacity(child_count as usize);
+        for _ in 0..child_count {
+            let label = reader.read_cstr_bytes()?;
+            let child_offset = reader.offset();
+            let child = reader.read_uleb128()?;
+            if child >= trie.len() as u64 || !visited.insert(child) {
+                return Err(Error::InvalidChildOffset(child_offset, child));
+            }
+
+            let mut symbol = prefix.clone();
+            symbol.extend_from_slice(label);
+            children.push((child as usize, symbol));
+        }
+
+        // Visit the children in the order of their edges.
+        stack.extend(children.into_iter().rev());
+    }
+
+    Ok(exports)
+}
+
+/// Iterate over the function addresses in the data of an
+/// `LC_FUNCTION_STARTS` load command, given the address of the `__TEXT`
+/// segment. Any zero padding after the terminating delta is ignored.
+pub fn function_starts(data: &[u8], text_address: u64) -> delta::Addresses<&[u8]> {
+    delta::addresses(data, text_address, Terminator::Delta(0))
+}
+
+/// Write the data of an `LC_FUNCTION_STARTS` load command for the given
+/// ascending function addresses, relative to the address of the `__TEXT`
+/// segment. Returns the number of bytes written to `w`, or an error if writing
+/// failed. Padding the data to pointer alignment is left to the caller.
+pub fn write_function_starts<W>(
+    w: &mut W,
+    text_address: u64,
+    addresses: &[u64],
+) -> Result<usize, write::Error>
+where
+    W: ?Sized + io::Write,
+{
+    delta::write(w, text_address, addresses, Terminator::Delta(0))
+}
+
+/// A disassembled bind opcode, as found in the bind, weak bind and lazy bind
+/// streams.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BindOpcode<'a> {
+    /// `BIND_OPCODE_DONE`
+    Done,
+    /// `BIND_OPCODE_SET_DYLIB_ORDINAL_IMM`
+    SetDylibOrdinalImm(u8),
+    /// `BIND_OPCODE_SET_DYLIB_ORDINAL_ULEB`
+    SetDylibOrdinalUleb(u64),
+    /// `BIND_OPCODE_SET_DYLIB_SPECIAL_IMM`, with the immediate sign extended
+    /// into one of the special ordinals 0, -1, -2 or -3.
+    SetDylibSpecialImm(i8),
+    /// `BIND_OPCODE_SET_SYMBOL_TRAILING_FLAGS_IMM`
+    SetSymbolTrailingFlagsImm {
+        /// The `BIND_SYMBOL_FLAGS_*` flags.
+        flags: u8,
+        /// The symbol's name.
+        symbol: &'a str,
+    },
+    /// `BIND_OPCODE_SET_TYPE_IMM`
+    SetTypeImm(u8),
+    /// `BIND_OPCODE_SET_ADDEND_SLEB`
+    SetAddendSleb(i64),
+    /// `BIND_OPCODE_SET_SEGMENT_AND_OFFSET_ULEB`
+    SetSegmentAndOffsetUleb {
+        /// The index of the segment.
+        segment: u8,
+        /// The offset within the segment.
+        offset: u64,
+    },
+    /// `BIND_OPCODE_ADD_ADDR_ULEB`
+    AddAddrUleb(u64),
+    /// `BIND_OPCODE_DO_BIND`
+    DoBind,
+    /// `BIND_OPCODE_DO_BIND_ADD_ADDR_ULEB`
+    DoBindAddAddrUleb(u64),
+    /// `BIND_OPCODE_DO_BIND_ADD_ADDR_IMM_SCALED`
+    DoBindAddAddrImmScaled(u8),
+    /// `BIND_OPCODE_DO_BIND_ULEB_TIMES_SKIPPING_ULEB`
+    DoBindUlebTimesSkippingUleb {
+        /// The number of binds.
+        count: u64,
+        /// The number of bytes skipped after each bind, in addition to the
+        /// pointer size.
+        skip: u64,
+    },
+    /// `BIND_OPCODE_THREADED` with `BIND_SUBOPCODE_THREADED_SET_BIND_ORDINAL_TABLE_SIZE_ULEB`
+    ThreadedSetBindOrdinalTableSizeUleb(u64),
+    /// `BIND_OPCODE_THREADED` with `BIND_SUBOPCODE_THREADED_APPLY`
+    ThreadedApply,
+}
+
+/// An iterator over the opcodes in a bind stream, yielding each along with
+/// its offset.
+///
+/// Iteration continues past `BindOpcode::Done`, since lazy bind streams hold
+/// one sequence per symbol, and stops after the first error.
+#[derive(Clone, Debug)]
+pub struct BindOpcodes<'a> {
+    reader: Leb128Reader<'a>,
+    failed: bool,
+}
+
+impl<'a> BindOpcodes<'a> {
+    /// Iterate over the opcodes in `buf`.
+    pub fn new(buf: &'a [u8]) -> BindOpcodes<'a> {
+        BindOpcodes {
+            reader: Leb128Reader::new(buf),
+            failed: false,
+        }
+    }
+
+    fn read_opcode(&mut self) -> Result<BindOpcode<'a>, Error> {
+        let offset = self.reader.offset();
+        let byte = self.reader.read_u8()?;
+        let immediate = byte & IMMEDIATE_MASK;
+
+        Ok(match byte & OPCODE_MASK {
+            0x00 => BindOpcode::Done,
+            0x10 => BindOpcode::SetDylibOrdinalImm(immediate),
+            0x20 => BindOpcode::SetDylibOrdinalUleb(self.reader.read_uleb128()?),
+            0x30 => BindOpcode::SetDylibSpecialImm(if immediate == 0 {
+                0
+            } else {
+                (OPCODE_MASK | immediate) as i8
+            }),
+            0x40 => BindOpcode::SetSymbolTrailingFlagsImm {
+                flags: immediate,
+                symbol: self.reader.read_cstr()?,
+            },
+            0x50 => BindOpcode::SetTypeImm(immediate),
+            0x60 => BindOpcode::SetAddendSleb(self.reader.read_sleb128()?),
+            0x70 => BindOpcode::SetSegmentAndOffsetUleb {
+                segment: immediate,
+                offset: self.reader.read_uleb128()?,
+            },
+            0x80 => BindOpcode::AddAddrUleb(self.reader.read_uleb128()?),
+            0x90 => BindOpcode::DoBind,
+            0xa0 => BindOpcode::DoBindAddAddrUleb(self.reader.read_uleb128()?),
+            0xb0 => BindOpcode::DoBindAddAddrImmScaled(immediate),
+            0xc0 => BindOpcode::DoBindUlebTimesSkippingUleb {
+                count: self.reader.read_uleb128()?,
+                skip: self.reader.read_uleb128()?,
+            },
+            0xd0 => match immediate {
+                0x00 => {
+                    BindOpcode::ThreadedSetBindOrdinalTableSizeUleb(self.reader.read_uleb128()?)
+                }
+                0x01 => BindOpcode::ThreadedApply,
+                _ => return Err(Error::UnknownOpcode(offset, byte)),
+            },
+            _ => return Err(Error::UnknownOpcode(offset, byte)),
+        })
+    }
+}
+
+impl<'a> Iterator for BindOpcodes<'a> {
+    type Item = Result<(usize, BindOpcode<'a>), Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed || self.reader.is_empty() {
+            return None;
+        }
+        let offset = self.reader.offset();
+        let result = self.read_opcode().map(|opcode| (offset, opcode));
+        self.failed = result.is_err();
+        Some(result)
+    }
+}
+
+/// A disassembled rebase opcode.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RebaseOpcode {
+    /// `REBASE_OPCODE_DONE`
+    Done,
+    /// `REBASE_OPCODE_SET_TYPE_IMM`
+    SetTypeImm(u8),
+    /// `REBASE_OPCODE_SET_SEGMENT_AND_OFFSET_ULEB`
+    SetSegmentAndOffsetUleb {
+        /// The index of the segment.
+        segment: u8,
+        /// The offset within the segment.
+        offset: u64,
+    },
+    /// `REBASE_OPCODE_ADD_ADDR_ULEB`
+    AddAddrUleb(u64),
+    /// `REBASE_OPCODE_ADD_ADDR_IMM_SCALED`
+    AddAddrImmScaled(u8),
+    /// `REBASE_OPCODE_DO_REBASE_IMM_TIMES`
+    DoRebaseImmTimes(u8),
+    /// `REBASE_OPCODE_DO_REBASE_ULEB_TIMES`
+    DoRebaseUlebTimes(u64),
+    /// `REBASE_OPCODE_DO_REBASE_ADD_ADDR_ULEB`
+    DoRebaseAddAddrUleb(u64),
+    /// `REBASE_OPCODE_DO_REBASE_ULEB_TIMES_SKIPPING_ULEB`
+    DoRebaseUlebTimesSkippingUleb {
+        /// The number of rebases.
+        count: u64,
+        /// The number of bytes skipped after each rebase, in addition to the
+        /// pointer size.
+        skip: u64,
+    },
+}
+
+/// An iterator over the opcodes in a rebase stream, yielding each along with
+/// its offset.
+///
+/// Iteration stops after the first error.
+#[derive(Clone, Debug)]
+pub struct RebaseOpcodes<'a> {
+    reader: Leb128Reader<'a>,
+    failed: bool,
+}
+
+impl<'a> RebaseOpcodes<'a> {
+    /// Iterate over the opcodes in `buf`.
+    pub fn new(buf: &'a [u8]) -> RebaseOpcodes<'a> {
+        RebaseOpcodes {
+            reader: Leb128Reader::new(buf),
+            failed: false,
+        }
+    }
+
+    fn read_opcode(&mut self) -> Result<RebaseOpcode, Error> {
+        let offset = self.reader.offset();
+        let byte = self.reader.read_u8()?;
+        let immediate = byte & IMMEDIATE_MASK;
+
+        Ok(match byte & OPCODE_MASK {
+            0x00 => RebaseOpcode::Done,
+            0x10 => RebaseOpcode::SetTypeImm(immediate),
+            0x20 => RebaseOpcode::SetSegmentAndOffsetUleb {
+                segment: immediate,
+                offset: self.reader.read_uleb128()?,
+            },
+            0x30 => RebaseOpcode::AddAddrUleb(self.reader.read_uleb128()?),
+            0x40 => RebaseOpcode::AddAddrImmScaled(immediate),
+            0x50 => RebaseOpcode::DoRebaseImmTimes(immediate),
+            0x60 => RebaseOpcode::DoRebaseUlebTimes(self.reader.read_uleb128()?),
+            0x70 => RebaseOpcode::DoRebaseAddAddrUleb(self.reader.read_uleb128()?),
+            0x80 => RebaseOpcode::DoRebaseUlebTimesSkippingUleb {
+                count: self.reader.read_uleb128()?,
+                skip: self.reader.read_uleb128()?,
+            },
+            _ => return Err(Error::UnknownOpcode(offset, byte)),
+        })
+    }
+}
+
+impl<'a> Iterator for RebaseOpcodes<'a> {
+    type Item = Result<(usize, RebaseOpcode), Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed || self.reader.is_empty() {
+            return None;
+        }
+        let offset = self.reader.offset();
+        let result = self.read_opcode().map(|opcode| (offset, opcode));
+        self.failed = result.is_err();
+        Some(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Exports `_foo`, `_bar` (a stub with a resolver) and `_baz` (re-exported
+    // from library 2 as `_qux`).
+    #[rustfmt::skip]
+    const TRIE: &[u8] = &[
+        // 0: the root, with one edge "_" to 5.
+        0x00, 0x01, b'_', 0x00, 0x05,
+        // 5: edges "foo" to 16 and "ba" to 21.
+        0x00, 0x02, b'f', b'o', b'o', 0x00, 0x10, b'b', b'a', 0x00, 0x15,
+        // 16: `_foo` at 0x1000.
+        0x03, 0x00, 0x80, 0x20, 0x00,
+        // 21: edges "r" to 29 and "z" to 36.
+        0x00, 0x02, b'r', 0x00, 0x1d, b'z', 0x00, 0x24,
+        // 29: `_bar`, a stub at 0x2000 with a resolver at 0x2010.
+        0x05, 0x10, 0x80, 0x40, 0x90, 0x40, 0x00,
+        // 36: `_baz`, re-exported.
+        0x07, 0x08, 0x02, b'_', b'q', b'u', b'x', 0x00, 0x00,
+    ];
+
+    #[test]
+    fn test_exports() {
+        let exports = exports(TRIE).unwrap();
+        assert_eq!(
+            exports,
+            vec![
+                Export {
+                    symbol: "_foo".to_string(),
+                    flags: 0,
+                    target: ExportTarget::Address(0x1000),
+                },
+                Export {
+                    symbol: "_bar".to_string(),
+                    flags: EXPORT_SYMBOL_FLAGS_STUB_AND_RESOLVER,
+                    target: ExportTarget::StubAndResolver {
+                        stub: 0x2000,
+                        resolver: 0x2010,
+                    },
+                },
+                Export {
+                    symbol: "_baz".to_string(),
+                    flags: EXPORT_SYMBOL_FLAGS_REEXPORT,
+                    target: ExportTarget::Reexport {
+                        dylib_ordinal: 2,
+                        imported_name: Some("_qux".to_string()),
+                    },
+                },
+            ]
+        );
+        assert_eq!(exports[1].address(), Some(0x2000));
+        assert_eq!(exports[2].address(), None);
+    }
+
+    #[test]
+    fn test_empty_trie() {
+        assert_eq!(super::exports(&[]).unwrap(), vec![]);
+        assert_eq!(super::exports(&[0x00, 0x00]).unwrap(), vec![]);
+    }
+
+    #[test]
+    fn test_invalid_tries() {
+        // An edge back to the root.
+        let mut trie = TRIE.to_vec();
+        trie[25] = 0x00;
+        match super::exports(&trie) {
+            Err(Error::InvalidChildOffset(25, 0)) => {}
+            otherwise => panic!("Unexpected: {:?}", otherwise),
+        }
+
+        // An edge past the end.
+        let mut trie = TRIE.to_vec();
+        trie[4] = 0x7f;
+        match super::exports(&trie) {
+            Err(Error::InvalidChildOffset(4, 0x7f)) => {}
+            otherwise => panic!("Unexpected: {:?}", otherwise),
+        }
+
+        // A terminal size larger than the trie.
+        let mut trie = TRIE.to_vec();
+        trie[16] = 0x7f;
+        match super::exports(&trie) {
+            Err(Error::Read(e)) => {
+                assert_eq!(e, reader::Error::new(16, ErrorKind::UnexpectedEof))
+            }
+            otherwise => panic!("Unexpected: {:?}", otherwise),
+        }
+    }
+
+    #[test]
+    fn test_function_starts() {
+        let data = [0xd0, 0x7e, 0x20, 0x90, 0x01, 0x00, 0x00, 0x00];
+        let starts: Vec<u64> = function_starts(&data, 0x1_0000_0000)
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(starts, [0x1_0000_3f50, 0x1_0000_3f70, 0x1_0000_4000]);
+
+        let mut buf = vec![];
+        write_function_starts(&mut buf, 0x1_0000_0000, &starts).unwrap();
+        assert_eq!(buf, &data[..6]);
+    }
+
+    #[test]
+    fn test_bind_opcodes() {
+        let stream = [
+            0x11, 0x40, b'_', b'p', b'u', b't', b's', 0x00, 0x51, 0x72, 0x10, 0x90, 0x60, 0x78,
+            0xa0, 0x08, 0xb1, 0xc0, 0x03, 0x08, 0x3e, 0x30, 0x20, 0xac, 0x02, 0x00, 0xd0, 0x04,
+            0xd1, 0x00,
+        ];
+        let opcodes: Vec<_> = BindOpcodes::new(&stream).map(Result::unwrap).collect();
+        assert_eq!(
+            opcodes,
+            vec![
+                (0, BindOpcode::SetDylibOrdinalImm(1)),
+                (
+                    1,
+                    BindOpcode::SetSymbolTrailingFlagsImm {
+                        flags: 0,
+                        symbol: "_puts",
+                    }
+                ),
+                (8, BindOpcode::SetTypeImm(1)),
+                (
+                    9,
+                    BindOpcode::SetSegmentAndOffsetUleb {
+                        segment: 2,
+                        offset: 0x10,
+                    }
+                ),
+                (11, BindOpcode::DoBind),
+                (12, BindOpcode::SetAddendSleb(-8)),
+                (14, BindOpcode::DoBindAddAddrUleb(8)),
+                (16, BindOpcode::DoBindAddAddrImmScaled(1)),
+                (
+                    17,
+                    BindOpcode::DoBindUlebTimesSkippingUleb { count: 3, skip: 8 }
+                ),
+                (20, BindOpcode::SetDylibSpecialImm(-2)),
+                (21, BindOpcode::SetDylibSpecialImm(0)),
+                (22, BindOpcode::SetDylibOrdinalUleb(300)),
+                (25, BindOpcode::Done),
+                (26, BindOpcode::ThreadedSetBindOrdinalTableSizeUleb(4)),
+                (28, BindOpcode::ThreadedApply),
+                (29, BindOpcode::Done),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_rebase_opcodes() {
+        let stream = [
+            0x11, 0x22, 0x20, 0x30, 0x08, 0x41, 0x53, 0x60, 0x02, 0x70, 0x08, 0x80, 0x02, 0x10,
+            0x00,
+        ];
+        let opcodes: Vec<_> = RebaseOpcodes::new(&stream).map(Result::unwrap).collect();
+        assert_eq!(
+            opcodes,
+            vec![
+                (0, RebaseOpcode::SetTypeImm(1)),
+                (
+                    1,
+                    RebaseOpcode::SetSegmentAndOffsetUleb {
+                        segment: 2,
+                        offset: 0x20,
+                    }
+                ),
+                (3, RebaseOpcode::AddAddrUleb(8)),
+                (5, RebaseOpcode::AddAddrImmScaled(1)),
+                (6, RebaseOpcode::DoRebaseImmTimes(3)),
+                (7, RebaseOpcode::DoRebaseUlebTimes(2)),
+                (9, RebaseOpcode::DoRebaseAddAddrUleb(8)),
+                (
+                    11,
+                    RebaseOpcode::DoRebaseUlebTimesSkippingUleb { count: 2, skip: 16 }
+                ),
+                (14, RebaseOpcode::Done),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_opcode_errors() {
+        let mut opcodes = BindOpcodes::new(&[0x90, 0xd5, 0x90]);
+        assert_eq!(opcodes.next(), Some(Ok((0, BindOpcode::DoBind))));
+        assert_eq!(opcodes.next(), Some(Err(Error::UnknownOpcode(1, 0xd5))));
+        assert_eq!(opcodes.next(), None);
+
+        let mut opcodes = RebaseOpcodes::new(&[0x90]);
+        assert_eq!(opcodes.next(), Some(Err(Error::UnknownOpcode(0, 0x90))));
+        assert_eq!(opcodes.next(), None);
+
+        // A truncated operand reports the offset of the operand.
+        let mut opcodes = RebaseOpcodes::new(&[0x11, 0x30, 0x80]);
+        opcodes.next();
+        assert_eq!(
+            opcodes.next(),
+            Some(Err(Error::Read(reader::Error::new(
+                2,
+                ErrorKind::UnexpectedEof
+            ))))
+        );
+    }
+}
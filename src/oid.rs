@@ -0,0 +1,439 @@
+//! ASN.1 OBJECT IDENTIFIERs, as found in X.509 certificates and SNMP
+//! packets, converted between dotted decimal strings and the content bytes of
+//! their DER encoding.
+//!
+//! Each arc is encoded as a big-endian base 128 number (see the `vlq`
+//! module), except that the first two arcs `X.Y` are packed together into the
+//! single number `40 * X + Y`. Arcs are not limited to 64 bits: UUID based
+//! identifiers under `2.25` have 128-bit arcs, and arbitrarily large arcs are
+//! supported.
+//!
+//! ```
+//! use leb128::oid::ObjectIdentifier;
+//!
+//! let rsa: ObjectIdentifier = "1.2.840.113549".parse().unwrap();
+//! assert_eq!(rsa.as_der(), [0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d]);
+//!
+//! let parsed = ObjectIdentifier::from_der(&[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d]).unwrap();
+//! assert_eq!(parsed.to_string(), "1.2.840.113549");
+//! ```
+
+use super::{read, vlq, CONTINUATION_BIT};
+use std::fmt;
+use std::str::FromStr;
+
+/// An error that occurred while converting an object identifier.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// The DER content bytes were empty.
+    Empty,
+    /// The DER content bytes ended in the middle of an arc.
+    Truncated,
+    /// An arc in the DER content bytes began with a redundant `0x80` byte.
+    NonMinimal,
+    /// A dotted string contained an arc that was not a decimal number without
+    /// leading zeros.
+    InvalidArc,
+    /// A dotted string had fewer than two arcs.
+    TooFewArcs,
+    /// The first arc was not 0, 1 or 2, or the second arc was 40 or more
+    /// under a first arc of 0 or 1.
+    InvalidRootArcs,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        let description = match *self {
+            Error::Empty => "The object identifier is empty",
+            Error::Truncated => "The object identifier ends in the middle of an arc",
+            Error::NonMinimal => "An arc is not minimally encoded",
+            Error::InvalidArc => "An arc is not a decimal number",
+            Error::TooFewArcs => "An object identifier needs at least two arcs",
+            Error::InvalidRootArcs => "The first two arcs are out of range",
+        };
+        write!(f, "leb128::oid::Error: {}", description)
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Decimal digits (most significant first) of an arc too large for a `u64`.
+type Digits = Vec<u8>;
+
+/// Multiply `digits` by `mul` and add `add`, in place.
+fn digits_mul_add(digits: &mut Digits, mul: u32, add: u32) {
+    let mut carry = add;
+    for digit in digits.iter_mut().rev() {
+        let val = u32::from(*digit) * mul + carry;
+        *digit = (val % 10) as u8;
+        carry = val / 10;
+    }
+    while carry != 0 {
+        digits.insert(0, (carry % 10) as u8);
+        carry /= 10;
+    }
+}
+
+/// Divide `digits` by `div` in place, returning the remainder.
+fn digits_div_rem(digits: &mut Digits, div: u32) -> u32 {
+    let mut rem = 0;
+    for digit in digits.iter_mut() {
+        let val = rem * 10 + u32::from(*digit);
+        *digit = (val / div) as u8;
+        rem = val % div;
+    }
+    let leading_zeros = digits.iter().take_while(|&&d| d == 0).count();
+    digits.drain(..leading_zeros.min(digits.len() - 1));
+    rem
+}
+
+fn digits_to_string(digits: &[u8]) -> String {
+    digits.iter().map(|&d| char::from(b'0' + d)).collect()
+}
+
+/// The value of a single subidentifier in the DER encoding.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Arc {
+    Small(u64),
+    Big(Digits),
+}
+
+impl Arc {
+    /// Decode the subidentifier at the start of `der`, returning it along with
+    /// the length of its encoding.
+    fn decode(der: &[u8]) -> Result<(Arc, usize), Error> {
+        match vlq::peek_strict(der) {
+            Ok((val, len)) => Ok((Arc::Small(val), len)),
+            Err(read::Error::NonMinimal) => Err(Error::NonMinimal),
+            Err(read::Error::Overflow) => {
+                let len = der
+                    .iter()
+                    .position(|&b| b & CONTINUATION_BIT == 0)
+                    .ok_or(Error::Truncated)?
+                    + 1;
+                let mut digits = vec![0];
+                for &byte in &der[..len] {
+                    digits_mul_add(&mut digits, 128, u32::from(byte & !CONTINUATION_BIT));
+                }
+                Ok((Arc::Big(digits), len))
+            }
+            Err(_) => Err(Error::Truncated),
+        }
+    }
+
+    /// Append the encoding of this subidentifier to `der`.
+    fn encode(&self, der: &mut Vec<u8>) {
+        match *self {
+            Arc::Small(val) => {
+                vlq::write(der, val).expect("writing to a Vec cannot fail");
+            }
+            Arc::Big(ref digits) => {
+                let mut digits = digits.clone();
+                let mut groups = vec![];
+                while digits != [0] {
+                    groups.push(digits_div_rem(&mut digits, 128) as u8);
+                }
+                for (i, &group) in groups.iter().enumerate().rev() {
+                    der.push(if i == 0 {
+                        group
+                    } else {
+                        group | CONTINUATION_BIT
+                    });
+                }
+            }
+        }
+    }
+
+    /// Parse a decimal arc from a dotted string.
+    fn parse(s: &str) -> Result<Arc, Error> {
+        let bytes = s.as_bytes();
+        if bytes.is_empty()
+            || !bytes.iter().all(u8::is_ascii_digit)
+            || (bytes.len() > 1 && bytes[0] == b'0')
+        {
+            return Err(Error::InvalidArc);
+        }
+        Ok(match s.parse() {
+            Ok(val) => Arc::Small(val),
+            Err(_) => Arc::Big(bytes.iter().map(|&b| b - b'0').collect()),
+        })
+    }
+
+    /// Add a small number to this arc.
+    fn add(self, n: u64) -> Arc {
+        match self {
+            Arc::Small(val) => match val.checked_add(n) {
+                Some(sum) => Arc::Small(sum),
+                None => Arc::Big(val.to_string().bytes().map(|b| b - b'0').collect()).add(n),
+            },
+            Arc::Big(mut digits) => {
+                digits_mul_add(&mut digits, 1, n as u32);
+                Arc::Big(digits)
+            }
+        }
+    }
+}
+
+impl fmt::Display for Arc {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match *self {
+            Arc::Small(val) => write!(f, "{}", val),
+            Arc::Big(ref digits) => f.write_str(&digits_to_string(digits)),
+        }
+    }
+}
+
+/// An ASN.1 OBJECT IDENTIFIER, held as the content bytes of its DER encoding.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ObjectIdentifier {
+    der: Vec<u8>,
+}
+
+impl ObjectIdentifier {
+    /// Validate the content bytes of a DER encoded object identifier, without
+    /// the tag and length. Arcs padded with redundant leading `0x80` bytes
+    /// are rejected.
+    pub fn from_der(der: &[u8]) -> Result<ObjectIdentifier, Error> {
+        if der.is_empty() {
+            return Err(Error::Empty);
+        }
+        let mut rest = der;
+        while !rest.is_empty() {
+            let (_, len) = Arc::decode(rest)?;
+            rest = &rest[len..];
+        }
+        Ok(ObjectIdentifier { der: der.to_vec() })
+    }
+
+    /// Build an object identifier from arcs that each fit in a `u64`.
+    pub fn from_arcs(arcs: &[u64]) -> Result<ObjectIdentifier, Error> {
+        if arcs.len() < 2 {
+            return Err(Error::TooFewArcs);
+        }
+        let mut der = vec![];
+        root_arc(arcs[0], Arc::Small(arcs[1]))?.encode(&mut der);
+        for &arc in &arcs[2..] {
+            Arc::Small(arc).encode(&mut der);
+        }
+        Ok(ObjectIdentifier { der })
+    }
+
+    /// The content bytes of the DER encoding, without the tag and length.
+    pub fn as_der(&self) -> &[u8] {
+        &self.der
+    }
+
+    /// The arcs of this object identifier, or `None` if any of them is too
+    /// large for a `u64`.
+    pub fn arcs(&self) -> Option<Vec<u64>> {
+        let mut arcs = vec![];
+        for (i, arc) in self.subidentifiers().enumerate() {
+            let val = match arc {
+                Arc::Small(val) => val,
+                Arc::Big(_) => return None,
+            };
+            if i == 0 {
+                let first = (val / 40).min(2);
+                arcs.push(first);
+                arcs.push(val - 40 * first);
+            } else {
+                arcs.push(val);
+            }
+        }
+        Some(arcs)
+    }
+
+    fn subidentifiers(&self) -> Subidentifiers<'_> {
+        Subidentifiers { rest: &self.der }
+    }
+}
+
+/// Pack the first two arcs into a single subidentifier.
+fn root_arc(first: u64, second: Arc) -> Result<Arc, Error> {
+    match (first, &second) {
+        (0, &Arc::Small(second)) | (1, &Arc::Small(second)) if second < 40 => {}
+        (2, _) => {}
+        _ => return Err(Error::InvalidRootArcs),
+    }
+    Ok(second.add(40 * first))
+}
+
+/// Iterates over the subidentifiers of an already validated DER encoding.
+struct Subidentifiers<'a> {
+    rest: &'a [u8],
+}
+
+impl<'a> Iterator for Subidentifiers<'a> {
+    type Item = Arc;
+
+    fn next(&mut self) -> Option<Arc> {
+        if self.rest.is_empty() {
+            return None;
+        }
+        let (arc, len) = Arc::decode(self.rest).expect("already validated");
+        self.rest = &self.rest[len..];
+        Some(arc)
+    }
+}
+
+impl fmt::Display for ObjectIdentifier {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        for (i, arc) in self.subidentifiers().enumerate() {
+            if i != 0 {
+                write!(f, ".{}", arc)?;
+                continue;
+            }
+            match arc {
+                Arc::Small(val) if val < 80 => write!(f, "{}.{}", val / 40, val % 40)?,
+                Arc::Small(val) => write!(f, "2.{}", val - 80)?,
+                Arc::Big(mut digits) => {
+                    // Subtract 80 by adding its ten's complement and dropping
+                    // the carry out of the top digit.
+                    let len = digits.len();
+                    let mut complement = vec![9; len];
+                    complement[len - 1] = 0;
+                    complement[len - 2] = 2;
+                    let mut carry = 0;
+                    for (d, c) in digits.iter_mut().zip(complement).rev() {
+                        let sum = *d + c + carry;
+                        *d = sum % 10;
+                        carry = sum / 10;
+                    }
+                    let leading_zeros = digits.iter().take_while(|&&d| d == 0).count();
+                    write!(f, "2.{}", digits_to_string(&digits[leading_zeros..]))?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for ObjectIdentifier {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<ObjectIdentifier, Error> {
+        let mut arcs = s.split('.');
+        let first = match Arc::parse(arcs.next().unwrap_or(""))? {
+            Arc::Small(first) => first,
+            Arc::Big(_) => return Err(Error::InvalidRootArcs),
+        };
+        let second = Arc::parse(arcs.next().ok_or(Error::TooFewArcs)?)?;
+
+        let mut der = vec![];
+        root_arc(first, second)?.encode(&mut der);
+        for arc in arcs {
+            Arc::parse(arc)?.encode(&mut der);
+        }
+        Ok(ObjectIdentifier { der })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(dotted: &str, der: &[u8]) {
+        let oid: ObjectIdentifier = dotted.parse().unwrap();
+        assert_eq!(oid.as_der(), der, "{}", dotted);
+        let oid = ObjectIdentifier::from_der(der).unwrap();
+        assert_eq!(oid.to_string(), dotted);
+    }
+
+    #[test]
+    fn test_well_known() {
+        check("1.2.840.113549", &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d]);
+        check(
+            "1.2.840.113549.1.1.11",
+            &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x0b],
+        );
+        check("2.5.4.3", &[0x55, 0x04, 0x03]);
+        check(
+            "1.3.6.1.2.1.1.1.0",
+            &[0x2b, 0x06, 0x01, 0x02, 0x01, 0x01, 0x01, 0x00],
+        );
+        check("0.0", &[0x00]);
+        check("0.39", &[0x27]);
+        check("1.0", &[0x28]);
+        check("2.0", &[0x50]);
+        check("2.999.3", &[0x88, 0x37, 0x03]);
+    }
+
+    #[test]
+    fn test_arcs() {
+        let oid = ObjectIdentifier::from_arcs(&[1, 2, 840, 113549]).unwrap();
+        assert_eq!(oid.to_string(), "1.2.840.113549");
+        assert_eq!(oid.arcs(), Some(vec![1, 2, 840, 113549]));
+
+        let oid = ObjectIdentifier::from_arcs(&[2, 100, u64::MAX]).unwrap();
+        assert_eq!(oid.arcs(), Some(vec![2, 100, u64::MAX]));
+        assert_eq!(oid.to_string(), format!("2.100.{}", u64::MAX));
+
+        assert_eq!(ObjectIdentifier::from_arcs(&[1]), Err(Error::TooFewArcs));
+        assert_eq!(
+            ObjectIdentifier::from_arcs(&[1, 40]),
+            Err(Error::InvalidRootArcs)
+        );
+        assert_eq!(
+            ObjectIdentifier::from_arcs(&[3, 1]),
+            Err(Error::InvalidRootArcs)
+        );
+    }
+
+    #[test]
+    fn test_uuid_arcs() {
+        // The example from ITU-T X.667.
+        let dotted = "2.25.329800735698586629295641978511506172918";
+        let der = [
+            0x69, 0x83, 0xf0, 0x9d, 0xa7, 0xeb, 0xcf, 0xde, 0xe0, 0xc7, 0xa1, 0xa7, 0xb2, 0xc0,
+            0x94, 0x8c, 0xc8, 0xf9, 0xd7, 0x76,
+        ];
+        check(dotted, &der);
+
+        let oid: ObjectIdentifier = dotted.parse().unwrap();
+        assert_eq!(oid.arcs(), None);
+
+        // A huge second arc under 2 is packed with the first.
+        check("2.340282366920938463463374607431768211456.1", &{
+            let mut der = vec![0x84];
+            der.extend_from_slice(&[0x80; 17]);
+            der.extend_from_slice(&[0x50, 0x01]);
+            der
+        });
+    }
+
+    #[test]
+    fn test_invalid_der() {
+        assert_eq!(ObjectIdentifier::from_der(&[]), Err(Error::Empty));
+        assert_eq!(
+            ObjectIdentifier::from_der(&[0x2a, 0x86]),
+            Err(Error::Truncated)
+        );
+        assert_eq!(
+            ObjectIdentifier::from_der(&[0x2a, 0x80, 0x01]),
+            Err(Error::NonMinimal)
+        );
+        assert_eq!(
+            ObjectIdentifier::from_der(&[0x80, 0x2a]),
+            Err(Error::NonMinimal)
+        );
+    }
+
+    #[test]
+    fn test_invalid_strings() {
+        for &(s, err) in &[
+            ("", Error::InvalidArc),
+            ("1", Error::TooFewArcs),
+            ("1.", Error::InvalidArc),
+            ("1..2", Error::InvalidArc),
+            ("1.2.", Error::InvalidArc),
+            ("1.02", Error::InvalidArc),
+            ("1.-2", Error::InvalidArc),
+            ("1.2.x", Error::InvalidArc),
+            ("1.40", Error::InvalidRootArcs),
+            ("3.1", Error::InvalidRootArcs),
+            ("100000000000000000000.1", Error::InvalidRootArcs),
+        ] {
+            assert_eq!(s.parse::<ObjectIdentifier>(), Err(err), "{:?}", s);
+        }
+    }
+}
@@ -0,0 +1,464 @@
+//! Protocol Buffers varints and a lightweight wire-format field reader.
+//!
+//! Protobuf varints are unsigned LEB128 limited to ten bytes. Negative `int32`
+//! and `int64` values are sign-extended to 64 bits and so always take all ten
+//! bytes, while `sint32` and `sint64` values use ZigZag encoding (see the
+//! `zigzag` module).
+//!
+//! ```
+//! use leb128::protobuf::{FieldValue, Fields};
+//!
+//! // `message Test { int32 a = 1; string b = 2; }` with `a = 150` and
+//! // `b = "hi"`.
+//! let msg = [0x08, 0x96, 0x01, 0x12, 0x02, b'h', b'i'];
+//! let fields = Fields::new(&msg).collect::<Result<Vec<_>, _>>().unwrap();
+//! assert_eq!(fields[0].number, 1);
+//! assert_eq!(fields[0].value, FieldValue::Varint(150));
+//! assert_eq!(fields[1].number, 2);
+//! assert_eq!(fields[1].value, FieldValue::LengthDelimited(b"hi"));
+//! ```
+
+use super::reader::{self, Leb128Reader};
+use super::{read, write, CONTINUATION_BIT};
+use std::fmt;
+use std::io;
+
+/// The maximum number of bytes in a protobuf varint.
+pub const MAX_VARINT_LEN: usize = 10;
+
+/// The largest valid field number.
+pub const MAX_FIELD_NUMBER: u32 = (1 << 29) - 1;
+
+/// Decode a varint from the start of `buf` the way strict decoders (such as
+/// Go's `protowire`) do: the varint must end within ten bytes, and the tenth
+/// byte may only contribute the single bit that is left of a 64-bit number.
+/// Returns the number along with the length of its encoding.
+pub fn decode_varint(buf: &[u8]) -> Result<(u64, usize), read::Error> {
+    read::peek_unsigned(buf)
+}
+
+/// Decode a varint from the start of `buf` the way lenient decoders (such as
+/// the C++ runtime) do: the varint must end within ten bytes, but any bits in
+/// the tenth byte beyond those a 64-bit number can hold are discarded. Returns
+/// the number along with the length of its encoding.
+pub fn decode_varint_lenient(buf: &[u8]) -> Result<(u64, usize), read::Error> {
+    let len = match buf.iter().position(|&b| b & CONTINUATION_BIT == 0) {
+        Some(pos) if pos < MAX_VARINT_LEN => pos + 1,
+        Some(_) => return Err(read::Error::Overflow),
+        None if buf.len() >= MAX_VARINT_LEN => return Err(read::Error::Overflow),
+        None => return Err(read::Error::IoError(io::ErrorKind::UnexpectedEof.into())),
+    };
+
+    let mut varint = [0; MAX_VARINT_LEN];
+    varint[..len].copy_from_slice(&buf[..len]);
+    if len == MAX_VARINT_LEN {
+        varint[MAX_VARINT_LEN - 1] &= 1;
+    }
+    read::peek_unsigned(&varint[..len])
+}
+
+/// Decode an `int32` varint from the start of `buf`. As in the reference
+/// implementations, the varint is decoded as 64 bits and then truncated, so
+/// both the 5-byte and the sign-extended 10-byte encodings of negative
+/// numbers are accepted.
+pub fn decode_int32(buf: &[u8]) -> Result<(i32, usize), read::Error> {
+    let (val, len) = decode_varint(buf)?;
+    Ok((val as u32 as i32, len))
+}
+
+/// Decode an `int64` varint from the start of `buf`.
+pub fn decode_int64(buf: &[u8]) -> Result<(i64, usize), read::Error> {
+    let (val, len) = decode_varint(buf)?;
+    Ok((val as i64, len))
+}
+
+/// Write a varint to the given `std::io::Write`able. Returns the number of
+/// bytes written to `w`, or an error if writing failed.
+pub fn encode_varint<W>(w: &mut W, val: u64) -> Result<usize, write::Error>
+where
+    W: ?Sized + io::Write,
+{
+    write::unsigned(w, val)
+}
+
+/// Write an `int32` varint to the given `std::io::Write`able. Negative numbers
+/// are sign-extended to 64 bits and so take ten bytes, as protobuf requires.
+/// Returns the number of bytes written to `w`, or an error if writing failed.
+pub fn encode_int32<W>(w: &mut W, val: i32) -> Result<usize, write::Error>
+where
+    W: ?Sized + io::Write,
+{
+    write::unsigned(w, val as i64 as u64)
+}
+
+/// Write an `int64` varint to the given `std::io::Write`able. Returns the
+/// number of bytes written to `w`, or an error if writing failed.
+pub fn encode_int64<W>(w: &mut W, val: i64) -> Result<usize, write::Error>
+where
+    W: ?Sized + io::Write,
+{
+    write::unsigned(w, val as u64)
+}
+
+/// Write a field tag to the given `std::io::Write`able. Returns the number of
+/// bytes written to `w`, or an error if writing failed.
+///
+/// Field numbers of zero or greater than `MAX_FIELD_NUMBER` are rejected with
+/// an `InvalidInput` error before anything is written.
+pub fn encode_tag<W>(w: &mut W, number: u32, wire_type: WireType) -> Result<usize, write::Error>
+where
+    W: ?Sized + io::Write,
+{
+    if !(1..=MAX_FIELD_NUMBER).contains(&number) {
+        return Err(write::invalid_input("invalid protobuf field number"));
+    }
+    write::unsigned(w, u64::from(number) << 3 | wire_type as u64)
+}
+
+/// The wire types a field can have.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WireType {
+    /// A varint.
+    Varint = 0,
+    /// Eight little-endian bytes.
+    Fixed64 = 1,
+    /// A varint length followed by that many bytes.
+    LengthDelimited = 2,
+    /// The start of a group. Groups are deprecated and not supported by
+    /// `Fields`.
+    StartGroup = 3,
+    /// The end of a group. Groups are deprecated and not supported by
+    /// `Fields`.
+    EndGroup = 4,
+    /// Four little-endian bytes.
+    Fixed32 = 5,
+}
+
+impl WireType {
+    /// The wire type with the given value, if there is one.
+    pub fn from_u8(val: u8) -> Option<WireType> {
+        match val {
+            0 => Some(WireType::Varint),
+            1 => Some(WireType::Fixed64),
+            2 => Some(WireType::LengthDelimited),
+            3 => Some(WireType::StartGroup),
+            4 => Some(WireType::EndGroup),
+            5 => Some(WireType::Fixed32),
+            _ => None,
+        }
+    }
+}
+
+/// The value of a field read by `Fields`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FieldValue<'a> {
+    /// A varint, still in its unsigned form. Use `zigzag::zigzag_decode` for
+    /// `sint32` and `sint64` fields, or truncate for `int32` fields.
+    Varint(u64),
+    /// A 64-bit value.
+    Fixed64(u64),
+    /// A byte string, embedded message or packed repeated field, borrowed
+    /// from the message.
+    LengthDelimited(&'a [u8]),
+    /// A 32-bit value.
+    Fixed32(u32),
+}
+
+/// A single field read by `Fields`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Field<'a> {
+    /// The field number.
+    pub number: u32,
+    /// The wire type from the field's tag.
+    pub wire_type: WireType,
+    /// The field's value.
+    pub value: FieldValue<'a>,
+}
+
+/// An error that occurred while reading the fields of a message.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// A varint or value could not be read.
+    Read(reader::Error),
+    /// A tag had an unknown or unsupported wire type. Carries the offset of
+    /// the tag and the wire type.
+    InvalidWireType(usize, u8),
+    /// A tag had a field number of zero or one greater than
+    /// `MAX_FIELD_NUMBER`. Carries the offset of the tag and the field number.
+    InvalidFieldNumber(usize, u64),
+}
+
+impl From<reader::Error> for Error {
+    fn from(e: reader::Error) -> Self {
+        Error::Read(e)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match *self {
+            Error::Read(ref e) => write!(f, "leb128::protobuf::Error: {}", e),
+            Error::InvalidWireType(offset, wire_type) => write!(
+                f,
+                "leb128::protobuf::Error: Unsupported wire type {} at offset {:#x}",
+                wire_type, offset
+            ),
+            Error::InvalidFieldNumber(offset, number) => write!(
+                f,
+                "leb128::protobuf::Error: Invalid field number {} at offset {:#x}",
+                number, offset
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match *self {
+            Error::Read(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// An iterator over the fields of a protobuf message in wire format.
+///
+/// Iteration stops after the first error.
+#[derive(Clone, Debug)]
+pub struct Fields<'a> {
+    reader: Leb128Reader<'a>,
+    failed: bool,
+}
+
+impl<'a> Fields<'a> {
+    /// Iterate over the fields of the message in `buf`.
+    pub fn new(buf: &'a [u8]) -> Fields<'a> {
+        Fields {
+            reader: Leb128Reader::new(buf),
+            failed: false,
+        }
+    }
+
+    /// The offset of the next field within the message.
+    pub fn offset(&self) -> usize {
+        self.reader.offset()
+    }
+
+    fn read_field(&mut self) -> Result<Field<'a>, Error> {
+        let offset = self.reader.offset();
+        let tag = self.reader.read_uleb128()?;
+
+        let wire_type = match WireType::from_u8((tag & 0x7) as u8) {
+            Some(WireType::StartGroup) | Some(WireType::EndGroup) | None => {
+                return Err(Error::InvalidWireType(offset, (tag & 0x7) as u8));
+            }
+            Some(wire_type) => wire_type,
+        };
+        let number = tag >> 3;
+        if number == 0 || number > u64::from(MAX_FIELD_NUMBER) {
+            return Err(Error::InvalidFieldNumber(offset, number));
+        }
+
+        let value = match wire_type {
+            WireType::Varint => FieldValue::Varint(self.reader.read_uleb128()?),
+            WireType::Fixed64 => {
+                let mut bytes = [0; 8];
+                bytes.copy_from_slice(self.reader.read_bytes(8)?);
+                FieldValue::Fixed64(u64::from_le_bytes(bytes))
+            }
+            WireType::LengthDelimited => {
+                let len_offset = self.reader.offset();
+                let len = self.reader.read_uleb128()?;
+                if len > self.reader.remaining() as u64 {
+                    return Err(
+                        reader::Error::new(len_offset, reader::ErrorKind::UnexpectedEof).into(),
+                    );
+                }
+                FieldValue::LengthDelimited(self.reader.read_bytes(len as usize)?)
+            }
+            WireType::Fixed32 => {
+                let mut bytes = [0; 4];
+                bytes.copy_from_slice(self.reader.read_bytes(4)?);
+                FieldValue::Fixed32(u32::from_le_bytes(bytes))
+            }
+            WireType::StartGroup | WireType::EndGroup => unreachable!(),
+        };
+
+        Ok(Field {
+            number: number as u32,
+            wire_type,
+            value,
+        })
+    }
+}
+
+impl<'a> Iterator for Fields<'a> {
+    type Item = Result<Field<'a>, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed || self.reader.is_empty() {
+            return None;
+        }
+        let result = self.read_field();
+        self.failed = result.is_err();
+        Some(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use zigzag;
+
+    fn fields(buf: &[u8]) -> Result<Vec<Field<'_>>, Error> {
+        Fields::new(buf).collect()
+    }
+
+    #[test]
+    fn test_decode_varint() {
+        assert_eq!(decode_varint(&[0x96, 0x01]).unwrap(), (150, 2));
+        let max = [0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01];
+        assert_eq!(decode_varint(&max).unwrap(), (u64::MAX, 10));
+        assert_eq!(decode_varint_lenient(&max).unwrap(), (u64::MAX, 10));
+
+        // Excess bits in the tenth byte are an error for strict decoders and
+        // discarded by lenient ones.
+        let excess = [0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x7f];
+        assert!(decode_varint(&excess).is_err());
+        assert_eq!(decode_varint_lenient(&excess).unwrap(), (1 << 63, 10));
+
+        // Neither accepts more than ten bytes.
+        let too_long = [
+            0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x00,
+        ];
+        match decode_varint_lenient(&too_long) {
+            Err(read::Error::Overflow) => {}
+            otherwise => panic!("Unexpected: {:?}", otherwise),
+        }
+        assert!(decode_varint(&too_long).is_err());
+
+        match decode_varint_lenient(&[0x80, 0x80]) {
+            Err(read::Error::IoError(e)) => assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof),
+            otherwise => panic!("Unexpected: {:?}", otherwise),
+        }
+    }
+
+    #[test]
+    fn test_int32() {
+        let mut buf = vec![];
+        assert_eq!(encode_int32(&mut buf, -1).unwrap(), 10);
+        assert_eq!(
+            buf,
+            [0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01]
+        );
+        assert_eq!(decode_int32(&buf).unwrap(), (-1, 10));
+        assert_eq!(decode_int64(&buf).unwrap(), (-1, 10));
+
+        // Some encoders write negative int32 values in five bytes.
+        assert_eq!(
+            decode_int32(&[0xff, 0xff, 0xff, 0xff, 0x0f]).unwrap(),
+            (-1, 5)
+        );
+
+        let mut buf = vec![];
+        assert_eq!(encode_int32(&mut buf, i32::MIN).unwrap(), 10);
+        assert_eq!(decode_int32(&buf).unwrap(), (i32::MIN, 10));
+
+        let mut buf = vec![];
+        assert_eq!(encode_int64(&mut buf, 300).unwrap(), 2);
+        assert_eq!(buf, [0xac, 0x02]);
+    }
+
+    #[test]
+    fn test_fields() {
+        let mut msg = vec![];
+        encode_tag(&mut msg, 1, WireType::Varint).unwrap();
+        encode_int32(&mut msg, -2).unwrap();
+        encode_tag(&mut msg, 2, WireType::LengthDelimited).unwrap();
+        write::str(&mut msg, "testing").unwrap();
+        encode_tag(&mut msg, 3, WireType::Fixed32).unwrap();
+        msg.extend_from_slice(&[0x01, 0x02, 0x03, 0x04]);
+        encode_tag(&mut msg, 4, WireType::Fixed64).unwrap();
+        msg.extend_from_slice(&[0xff; 8]);
+        encode_tag(&mut msg, MAX_FIELD_NUMBER, WireType::Varint).unwrap();
+        encode_varint(&mut msg, zigzag::zigzag_encode(-2)).unwrap();
+
+        assert_eq!(&msg[..3], &[0x08, 0xfe, 0xff]);
+        assert_eq!(
+            &msg[11..20],
+            &[0x12, 0x07, b't', b'e', b's', b't', b'i', b'n', b'g']
+        );
+        assert_eq!(msg[20], 0x1d);
+        assert_eq!(msg[25], 0x21);
+        assert_eq!(&msg[34..], &[0xf8, 0xff, 0xff, 0xff, 0x0f, 0x03]);
+
+        let fields = fields(&msg).unwrap();
+        assert_eq!(fields.len(), 5);
+        assert_eq!(fields[0].number, 1);
+        assert_eq!(fields[0].wire_type, WireType::Varint);
+        assert_eq!(fields[0].value, FieldValue::Varint(-2i64 as u64));
+        assert_eq!(fields[1].value, FieldValue::LengthDelimited(b"testing"));
+        assert_eq!(fields[2].wire_type, WireType::Fixed32);
+        assert_eq!(fields[2].value, FieldValue::Fixed32(0x04030201));
+        assert_eq!(fields[3].value, FieldValue::Fixed64(u64::MAX));
+        assert_eq!(fields[4].number, MAX_FIELD_NUMBER);
+        assert_eq!(fields[4].value, FieldValue::Varint(3));
+    }
+
+    #[test]
+    fn test_encode_tag_rejects_invalid_numbers() {
+        for &number in &[0, MAX_FIELD_NUMBER + 1] {
+            let mut buf = vec![];
+            match encode_tag(&mut buf, number, WireType::Varint) {
+                Err(e) => assert_eq!(e.io_error().kind(), io::ErrorKind::InvalidInput),
+                otherwise => panic!("Unexpected: {:?}", otherwise),
+            }
+            assert!(buf.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_embedded_message() {
+        // `message Test3 { Test1 c = 3; }` with `c.a = 150`.
+        let msg = [0x1a, 0x03, 0x08, 0x96, 0x01];
+        let outer = fields(&msg).unwrap();
+        assert_eq!(outer.len(), 1);
+        let inner = match outer[0].value {
+            FieldValue::LengthDelimited(bytes) => fields(bytes).unwrap(),
+            otherwise => panic!("Unexpected: {:?}", otherwise),
+        };
+        assert_eq!(inner[0].value, FieldValue::Varint(150));
+    }
+
+    #[test]
+    fn test_field_errors() {
+        assert_eq!(
+            fields(&[0x08, 0x01, 0x0b]),
+            Err(Error::InvalidWireType(2, 3))
+        );
+        assert_eq!(fields(&[0x0e]), Err(Error::InvalidWireType(0, 6)));
+        assert_eq!(fields(&[0x00, 0x01]), Err(Error::InvalidFieldNumber(0, 0)));
+        assert_eq!(
+            fields(&[0x80, 0x80, 0x80, 0x80, 0x10, 0x00]),
+            Err(Error::InvalidFieldNumber(0, 1 << 29))
+        );
+        assert_eq!(
+            fields(&[0x12, 0x05, 0x01]),
+            Err(Error::Read(reader::Error::new(
+                1,
+                reader::ErrorKind::UnexpectedEof
+            )))
+        );
+        assert_eq!(
+            fields(&[0x1d, 0x01, 0x02]),
+            Err(Error::Read(reader::Error::new(
+                1,
+                reader::ErrorKind::UnexpectedEof
+            )))
+        );
+
+        let mut iter = Fields::new(&[0x08, 0x80]);
+        assert!(iter.next().unwrap().is_err());
+        assert!(iter.next().is_none());
+    }
+}
@@ -0,0 +1,477 @@
+//! A cursor for reading LEB128 encoded numbers and raw bytes out of a byte
+//! slice while keeping track of the absolute offset.
+
+use super::read;
+use std::fmt;
+use std::str;
+
+/// The kinds of errors that can occur when reading from a `Leb128Reader`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// The input ended before the value being read was complete.
+    UnexpectedEof,
+    /// The number being read is larger than can be represented.
+    Overflow,
+    /// A length prefix is larger than the maximum the caller allowed.
+    TooLong,
+    /// A length-prefixed string is not valid UTF-8.
+    InvalidUtf8,
+    /// The number is padded with redundant bytes, in a context that requires
+    /// the shortest possible encoding.
+    NonMinimal,
+}
+
+/// An error that occurred when reading from a `Leb128Reader`, along with the
+/// absolute offset of the value that could not be read.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Error {
+    offset: usize,
+    kind: ErrorKind,
+}
+
+impl Error {
+    /// Create a new error of the given kind at the given absolute offset.
+    pub fn new(offset: usize, kind: ErrorKind) -> Error {
+        Error { offset, kind }
+    }
+
+    /// The absolute offset of the value that could not be read.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// What kind of error this is.
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        let description = match self.kind {
+            ErrorKind::UnexpectedEof => "Unexpected end of input",
+            ErrorKind::Overflow => "The number being read is larger than can be represented",
+            ErrorKind::TooLong => "The length prefix is larger than the maximum allowed",
+            ErrorKind::InvalidUtf8 => "The string is not valid UTF-8",
+            ErrorKind::NonMinimal => "The number is not minimally encoded",
+        };
+        write!(
+            f,
+            "leb128::reader::Error: {} at offset {:#x}",
+            description, self.offset
+        )
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// A cursor over a byte slice that reads LEB128 encoded numbers and raw
+/// bytes, tracking the absolute offset of everything it reads.
+///
+/// ```
+/// use leb128::reader::Leb128Reader;
+///
+/// let mut reader = Leb128Reader::new(&[0xe5, 0x8e, 0x26, 0x7f, 0xaa]);
+/// assert_eq!(reader.read_uleb128().unwrap(), 624485);
+/// assert_eq!(reader.read_sleb128().unwrap(), -1);
+/// assert_eq!(reader.offset(), 4);
+/// assert_eq!(reader.read_u8().unwrap(), 0xaa);
+/// assert_eq!(reader.remaining(), 0);
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct Leb128Reader<'a> {
+    buf: &'a [u8],
+    start: usize,
+    pos: usize,
+}
+
+impl<'a> Leb128Reader<'a> {
+    /// Create a new reader positioned at the start of `buf`, which is taken
+    /// to begin at offset zero.
+    pub fn new(buf: &'a [u8]) -> Leb128Reader<'a> {
+        Leb128Reader::with_offset(buf, 0)
+    }
+
+    /// Create a new reader positioned at the start of `buf`, which is taken
+    /// to begin at the given absolute offset.
+    pub fn with_offset(buf: &'a [u8], offset: usize) -> Leb128Reader<'a> {
+        Leb128Reader {
+            buf,
+            start: offset,
+            pos: 0,
+        }
+    }
+
+    /// The absolute offset of the next byte to be read.
+    pub fn offset(&self) -> usize {
+        self.start + self.pos
+    }
+
+    /// The number of bytes left to read.
+    pub fn remaining(&self) -> usize {
+        self.buf.len() - self.pos
+    }
+
+    /// The bytes left to read, without consuming them.
+    pub fn rest(&self) -> &'a [u8] {
+        &self.buf[self.pos..]
+    }
+
+    /// Returns `true` if there are no bytes left to read.
+    pub fn is_empty(&self) -> bool {
+        self.remaining() == 0
+    }
+
+    fn error(&self, kind: ErrorKind) -> Error {
+        Error::new(self.offset(), kind)
+    }
+
+    fn read_error(&self, e: read::Error) -> Error {
+        match e {
+            read::Error::IoError(_) | read::Error::Incomplete => {
+                self.error(ErrorKind::UnexpectedEof)
+            }
+            read::Error::Overflow => self.error(ErrorKind::Overflow),
+            read::Error::TooLong => self.error(ErrorKind::TooLong),
+            read::Error::InvalidUtf8(_) => self.error(ErrorKind::InvalidUtf8),
+            read::Error::NonMinimal => self.error(ErrorKind::NonMinimal),
+        }
+    }
+
+    /// Move to the given absolute offset, which must lie within the bytes
+    /// this reader was created over.
+    pub fn seek(&mut self, offset: usize) -> Result<(), Error> {
+        match offset.checked_sub(self.start) {
+            Some(pos) if pos <= self.buf.len() => {
+                self.pos = pos;
+                Ok(())
+            }
+            _ => Err(Error::new(offset, ErrorKind::UnexpectedEof)),
+        }
+    }
+
+    /// Advance past the next `n` bytes.
+    pub fn skip(&mut self, n: usize) -> Result<(), Error> {
+        self.read_bytes(n).map(|_| ())
+    }
+
+    /// Read a single byte.
+    pub fn read_u8(&mut self) -> Result<u8, Error> {
+        let byte = self.peek()?;
+        self.pos += 1;
+        Ok(byte)
+    }
+
+    /// Read the next `n` bytes, borrowing them from the underlying slice.
+    pub fn read_bytes(&mut self, n: usize) -> Result<&'a [u8], Error> {
+        if n > self.remaining() {
+            return Err(self.error(ErrorKind::UnexpectedEof));
+        }
+        let bytes = &self.buf[self.pos..self.pos + n];
+        self.pos += n;
+        Ok(bytes)
+    }
+
+    /// Read an unsigned LEB128 number.
+    ///
+    /// On error the reader is left where it was, and the error reports the
+    /// offset of the start of the number.
+    pub fn read_uleb128(&mut self) -> Result<u64, Error> {
+        let (val, len) = self.peek_uleb128_len()?;
+        self.pos += len;
+        Ok(val)
+    }
+
+    /// Read a signed LEB128 number.
+    ///
+    /// On error the reader is left where it was, and the error reports the
+    /// offset of the start of the number.
+    pub fn read_sleb128(&mut self) -> Result<i64, Error> {
+        let (val, len) = self.peek_sleb128_len()?;
+        self.pos += len;
+        Ok(val)
+    }
+
+    /// Read a value with a slice decoder such as `read::peek_unsigned` or
+    /// `wasm::peek_varuint32`, which returns the value decoded from the start
+    /// of its input along with the length of its encoding.
+    ///
+    /// On error the reader is left where it was, and the error reports the
+    /// offset of the start of the value.
+    pub fn read_with<T, F>(&mut self, peek: F) -> Result<T, Error>
+    where
+        F: FnOnce(&'a [u8]) -> Result<(T, usize), read::Error>,
+    {
+        let (val, len) = peek(self.rest()).map_err(|e| self.read_error(e))?;
+        self.pos += len;
+        Ok(val)
+    }
+
+    /// Read a byte string prefixed by its unsigned LEB128 encoded length,
+    /// borrowing it from the underlying slice. Lengths greater than `max_len`
+    /// are rejected.
+    pub fn read_prefixed_bytes(&mut self, max_len: usize) -> Result<&'a [u8], Error> {
+        let (bytes, len) =
+            read::peek_bytes(self.rest(), max_len).map_err(|e| self.read_error(e))?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    /// Read a UTF-8 string prefixed by its unsigned LEB128 encoded length in
+    /// bytes, borrowing it from the underlying slice. Lengths greater than
+    /// `max_len` are rejected.
+    pub fn read_prefixed_str(&mut self, max_len: usize) -> Result<&'a str, Error> {
+        let (s, len) = read::peek_string(self.rest(), max_len).map_err(|e| self.read_error(e))?;
+        self.pos += len;
+        Ok(s)
+    }
+
+    /// Read a NUL-terminated byte string, borrowing it from the underlying
+    /// slice without the terminator.
+    pub fn read_cstr_bytes(&mut self) -> Result<&'a [u8], Error> {
+        let rest = self.rest();
+        match rest.iter().position(|&byte| byte == 0) {
+            Some(len) => {
+                self.pos += len + 1;
+                Ok(&rest[..len])
+            }
+            None => Err(self.error(ErrorKind::UnexpectedEof)),
+        }
+    }
+
+    /// Read a NUL-terminated UTF-8 string, borrowing it from the underlying
+    /// slice without the terminator.
+    pub fn read_cstr(&mut self) -> Result<&'a str, Error> {
+        let start = self.pos;
+        let bytes = self.read_cstr_bytes()?;
+        str::from_utf8(bytes).map_err(|_| {
+            self.pos = start;
+            self.error(ErrorKind::InvalidUtf8)
+        })
+    }
+
+    /// Read a vector of elements prefixed by its unsigned LEB128 encoded
+    /// element count, reading each element with `read_elem`.
+    ///
+    /// Counts greater than `max_count`, or greater than the number of bytes
+    /// remaining, are rejected before space is allocated for the elements. On
+    /// error the reader is left where it was.
+    pub fn read_vec_with<T, F>(&mut self, max_count: usize, read_elem: F) -> Result<Vec<T>, Error>
+    where
+        F: FnMut(&mut Leb128Reader<'a>) -> Result<T, Error>,
+    {
+        let start = self.pos;
+        let result = self.read_vec_from(max_count, read_elem);
+        if result.is_err() {
+            self.pos = start;
+        }
+        result
+    }
+
+    fn read_vec_from<T, F>(&mut self, max_count: usize, mut read_elem: F) -> Result<Vec<T>, Error>
+    where
+        F: FnMut(&mut Leb128Reader<'a>) -> Result<T, Error>,
+    {
+        let offset = self.offset();
+        let count = self.read_uleb128()?;
+        if count > max_count as u64 {
+            return Err(Error::new(offset, ErrorKind::TooLong));
+        }
+        if count > self.remaining() as u64 {
+            return Err(Error::new(offset, ErrorKind::UnexpectedEof));
+        }
+
+        let mut vec = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            vec.push(read_elem(self)?);
+        }
+        Ok(vec)
+    }
+
+    /// Return the next byte without consuming it.
+    pub fn peek(&self) -> Result<u8, Error> {
+        self.buf
+            .get(self.pos)
+            .cloned()
+            .ok_or_else(|| self.error(ErrorKind::UnexpectedEof))
+    }
+
+    /// Decode the next unsigned LEB128 number without consuming it.
+    pub fn peek_uleb128(&self) -> Result<u64, Error> {
+        self.peek_uleb128_len().map(|(val, _)| val)
+    }
+
+    /// Decode the next signed LEB128 number without consuming it.
+    pub fn peek_sleb128(&self) -> Result<i64, Error> {
+        self.peek_sleb128_len().map(|(val, _)| val)
+    }
+
+    fn peek_uleb128_len(&self) -> Result<(u64, usize), Error> {
+        read::peek_unsigned(self.rest()).map_err(|e| self.read_error(e))
+    }
+
+    fn peek_sleb128_len(&self) -> Result<(i64, usize), Error> {
+        read::peek_signed(self.rest()).map_err(|e| self.read_error(e))
+    }
+
+    /// Split off the next `len` bytes into a new reader and advance past
+    /// them. The new reader continues to report absolute offsets.
+    pub fn sub_reader(&mut self, len: usize) -> Result<Leb128Reader<'a>, Error> {
+        let offset = self.offset();
+        let bytes = self.read_bytes(len)?;
+        Ok(Leb128Reader::with_offset(bytes, offset))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_values() {
+        let buf = [0x02, 0x80, 0x01, 0x7e, 0xaa, 0xbb, 0xcc];
+        let mut reader = Leb128Reader::new(&buf);
+        assert_eq!(reader.read_uleb128(), Ok(2));
+        assert_eq!(reader.peek_uleb128(), Ok(128));
+        assert_eq!(reader.offset(), 1);
+        assert_eq!(reader.read_uleb128(), Ok(128));
+        assert_eq!(reader.read_sleb128(), Ok(-2));
+        assert_eq!(reader.read_u8(), Ok(0xaa));
+        assert_eq!(reader.read_bytes(2), Ok(&[0xbb, 0xcc][..]));
+        assert!(reader.is_empty());
+        assert_eq!(
+            reader.read_u8(),
+            Err(Error::new(7, ErrorKind::UnexpectedEof))
+        );
+    }
+
+    #[test]
+    fn test_error_leaves_position() {
+        let buf = [0x00, 0x80, 0x80];
+        let mut reader = Leb128Reader::with_offset(&buf, 0x100);
+        assert_eq!(reader.read_uleb128(), Ok(0));
+        assert_eq!(
+            reader.read_uleb128(),
+            Err(Error::new(0x101, ErrorKind::UnexpectedEof))
+        );
+        assert_eq!(reader.offset(), 0x101);
+        assert_eq!(reader.remaining(), 2);
+
+        let buf = [0xff; 11];
+        let mut reader = Leb128Reader::new(&buf);
+        assert_eq!(
+            reader.read_sleb128(),
+            Err(Error::new(0, ErrorKind::Overflow))
+        );
+        assert_eq!(reader.offset(), 0);
+    }
+
+    #[test]
+    fn test_prefixed() {
+        let buf = [0x02, b'h', b'i', 0x01, 0xff, 0x05, b'x'];
+        let mut reader = Leb128Reader::with_offset(&buf, 8);
+        assert_eq!(reader.read_prefixed_str(2), Ok("hi"));
+        assert_eq!(
+            reader.read_prefixed_str(2),
+            Err(Error::new(11, ErrorKind::InvalidUtf8))
+        );
+        assert_eq!(reader.read_prefixed_bytes(1), Ok(&[0xff][..]));
+        assert_eq!(
+            reader.read_prefixed_bytes(4),
+            Err(Error::new(13, ErrorKind::TooLong))
+        );
+        assert_eq!(
+            reader.read_prefixed_bytes(5),
+            Err(Error::new(13, ErrorKind::UnexpectedEof))
+        );
+        assert_eq!(reader.remaining(), 2);
+    }
+
+    #[test]
+    fn test_read_with() {
+        let buf = [0x80, 0x00, 0x80];
+        let mut reader = Leb128Reader::with_offset(&buf, 4);
+        assert_eq!(reader.read_with(read::peek_unsigned), Ok(0));
+        assert_eq!(
+            reader.read_with(read::peek_unsigned),
+            Err(Error::new(6, ErrorKind::UnexpectedEof))
+        );
+        assert_eq!(reader.read_with(|buf| Ok((buf[0], 1))), Ok(0x80));
+        assert!(reader.is_empty());
+    }
+
+    #[test]
+    fn test_cstr() {
+        let buf = [b'h', b'i', 0x00, 0x00, 0xff, 0x00, b'x'];
+        let mut reader = Leb128Reader::with_offset(&buf, 8);
+        assert_eq!(reader.read_cstr(), Ok("hi"));
+        assert_eq!(reader.read_cstr(), Ok(""));
+        assert_eq!(
+            reader.read_cstr(),
+            Err(Error::new(12, ErrorKind::InvalidUtf8))
+        );
+        assert_eq!(reader.read_cstr_bytes(), Ok(&[0xff][..]));
+        assert_eq!(
+            reader.read_cstr_bytes(),
+            Err(Error::new(14, ErrorKind::UnexpectedEof))
+        );
+        assert_eq!(reader.remaining(), 1);
+    }
+
+    #[test]
+    fn test_read_vec() {
+        let buf = [0x02, 0x7f, 0x01, 0x03, 0x01];
+        let mut reader = Leb128Reader::new(&buf);
+        assert_eq!(
+            reader.read_vec_with(2, |r| r.read_sleb128()),
+            Ok(vec![-1, 1])
+        );
+        assert_eq!(
+            reader.read_vec_with(2, |r| r.read_u8()),
+            Err(Error::new(3, ErrorKind::TooLong))
+        );
+        assert_eq!(
+            reader.read_vec_with(3, |r| r.read_u8()),
+            Err(Error::new(3, ErrorKind::UnexpectedEof))
+        );
+        assert_eq!(reader.offset(), 3);
+    }
+
+    #[test]
+    fn test_seek() {
+        let buf = [0x01, 0x02, 0x03];
+        let mut reader = Leb128Reader::with_offset(&buf, 10);
+        assert_eq!(reader.seek(12), Ok(()));
+        assert_eq!(reader.read_u8(), Ok(0x03));
+        assert_eq!(reader.seek(10), Ok(()));
+        assert_eq!(reader.read_u8(), Ok(0x01));
+        assert_eq!(reader.seek(13), Ok(()));
+        assert!(reader.is_empty());
+        assert_eq!(reader.seek(9), Err(Error::new(9, ErrorKind::UnexpectedEof)));
+        assert_eq!(
+            reader.seek(14),
+            Err(Error::new(14, ErrorKind::UnexpectedEof))
+        );
+    }
+
+    #[test]
+    fn test_sub_reader() {
+        let buf = [0x03, 0x80, 0x01, 0x05, 0x06];
+        let mut reader = Leb128Reader::new(&buf);
+        let len = reader.read_uleb128().unwrap() as usize;
+        let mut sub = reader.sub_reader(len).unwrap();
+        assert_eq!(reader.offset(), 4);
+        assert_eq!(reader.read_u8(), Ok(0x06));
+
+        assert_eq!(sub.offset(), 1);
+        assert_eq!(sub.remaining(), 3);
+        assert_eq!(sub.read_uleb128(), Ok(128));
+        assert_eq!(sub.read_u8(), Ok(0x05));
+        assert_eq!(sub.read_u8(), Err(Error::new(4, ErrorKind::UnexpectedEof)));
+
+        assert_eq!(
+            reader.sub_reader(1).unwrap_err(),
+            Error::new(5, ErrorKind::UnexpectedEof)
+        );
+    }
+}
@@ -0,0 +1,131 @@
+//! Read and write the variable length integers used in SQLite database files,
+//! for example in record headers and b-tree cells.
+//!
+//! SQLite varints are big-endian base 128 (see the `vlq` module) with one
+//! twist: they are at most nine bytes long, and the ninth byte contributes all
+//! eight of its bits. This lets every 64-bit number fit in nine bytes.
+//!
+//! ```
+//! let mut buf = vec![];
+//! leb128::sqlite::write_varint(&mut buf, 300).unwrap();
+//! assert_eq!(buf, [0x82, 0x2c]);
+//! assert_eq!(leb128::sqlite::read_varint(&buf).unwrap(), (300, 2));
+//! ```
+
+use super::{low_bits_of_byte, low_bits_of_u64, read, vlq, write, CONTINUATION_BIT};
+use std::io;
+
+/// The maximum number of bytes in a SQLite varint.
+pub const MAX_VARINT_LEN: usize = 9;
+
+/// Numbers above this need all nine bytes.
+const MAX_EIGHT_BYTE_VARINT: u64 = (1 << 56) - 1;
+
+/// Decode a varint from the start of `buf`. Returns the number along with the
+/// length of its encoding, or an `UnexpectedEof` IO error if `buf` ends in the
+/// middle of the varint.
+pub fn read_varint(buf: &[u8]) -> Result<(u64, usize), read::Error> {
+    let mut result = 0;
+    for (i, &byte) in buf.iter().take(MAX_VARINT_LEN).enumerate() {
+        if i == MAX_VARINT_LEN - 1 {
+            return Ok((result << 8 | u64::from(byte), MAX_VARINT_LEN));
+        }
+        result = result << 7 | u64::from(low_bits_of_byte(byte));
+        if byte & CONTINUATION_BIT == 0 {
+            return Ok((result, i + 1));
+        }
+    }
+    Err(read::Error::IoError(io::ErrorKind::UnexpectedEof.into()))
+}
+
+/// The number of bytes `write_varint` uses to encode `val`.
+pub fn varint_len(val: u64) -> usize {
+    if val > MAX_EIGHT_BYTE_VARINT {
+        MAX_VARINT_LEN
+    } else {
+        vlq::encoded_len(val)
+    }
+}
+
+/// Write a varint to the given `std::io::Write`able. Returns the number of
+/// bytes written to `w`, or an error if writing failed.
+pub fn write_varint<W>(w: &mut W, val: u64) -> Result<usize, write::Error>
+where
+    W: ?Sized + io::Write,
+{
+    if val <= MAX_EIGHT_BYTE_VARINT {
+        return vlq::write(w, val);
+    }
+
+    // The top 56 bits go in the first eight bytes, and the low eight bits in
+    // the ninth.
+    let mut buf = [0; MAX_VARINT_LEN];
+    for (i, byte) in buf[..MAX_VARINT_LEN - 1].iter_mut().enumerate() {
+        let shift = 8 + 7 * (MAX_VARINT_LEN - 2 - i);
+        *byte = low_bits_of_u64(val >> shift) | CONTINUATION_BIT;
+    }
+    buf[MAX_VARINT_LEN - 1] = val as u8;
+    write::write_encoded(w, &buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(val: u64, encoded: &[u8]) {
+        let mut buf = vec![];
+        assert_eq!(write_varint(&mut buf, val).unwrap(), encoded.len());
+        assert_eq!(buf, encoded, "{:#x}", val);
+        assert_eq!(varint_len(val), encoded.len());
+        assert_eq!(read_varint(&buf).unwrap(), (val, encoded.len()));
+    }
+
+    #[test]
+    fn test_lengths() {
+        check(0, &[0x00]);
+        check(0x7f, &[0x7f]);
+        check(0x80, &[0x81, 0x00]);
+        check(0x3fff, &[0xff, 0x7f]);
+        check(0x4000, &[0x81, 0x80, 0x00]);
+        check(
+            (1 << 56) - 1,
+            &[0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x7f],
+        );
+    }
+
+    #[test]
+    fn test_nine_bytes() {
+        check(
+            1 << 56,
+            &[0x80, 0xc0, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x00],
+        );
+        check(
+            (1 << 56) | 0xff,
+            &[0x80, 0xc0, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0xff],
+        );
+        check(
+            0x0123_4567_89ab_cdef,
+            &[0x80, 0xc8, 0xe8, 0xd6, 0xbc, 0xa6, 0xd7, 0xcd, 0xef],
+        );
+        check(u64::MAX, &[0xff; 9]);
+    }
+
+    #[test]
+    fn test_ninth_byte_ends_varint() {
+        // The ninth byte ends the varint even if its high bit is set.
+        let buf = [0xff; 12];
+        assert_eq!(read_varint(&buf).unwrap(), (u64::MAX, 9));
+    }
+
+    #[test]
+    fn test_not_enough_data() {
+        for len in 0..MAX_VARINT_LEN {
+            match read_varint(&[0xff; 9][..len]) {
+                Err(read::Error::IoError(e)) => {
+                    assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof)
+                }
+                otherwise => panic!("Unexpected: {:?}", otherwise),
+            }
+        }
+    }
+}
@@ -0,0 +1,213 @@
+//! Read and write the big-endian variant of base 128, often called VLQ
+//! ("variable length quantity"), as used by MIDI delta-times, ASN.1 object
+//! identifier arcs and several container formats.
+//!
+//! Each byte carries seven bits of the number, most significant group first,
+//! with the high bit set on every byte except the last.
+//!
+//! ```
+//! let mut buf = vec![];
+//! leb128::vlq::write(&mut buf, 0x4000).unwrap();
+//! assert_eq!(buf, [0x81, 0x80, 0x00]);
+//!
+//! let val = leb128::vlq::read(&mut &buf[..]).unwrap();
+//! assert_eq!(val, 0x4000);
+//! ```
+//!
+//! Note that MIDI limits delta-times to four bytes (`0x0fff_ffff`), which
+//! callers should check for themselves.
+
+use super::read::{self, Error};
+use super::{low_bits_of_byte, low_bits_of_u64, write, CONTINUATION_BIT};
+use std::io;
+
+/// The maximum number of bytes a 64-bit number can occupy when VLQ encoded.
+pub const MAX_ENCODED_LEN: usize = 10;
+
+/// Decode a VLQ number from the bytes produced by `next_byte`. When `strict`
+/// is set, a leading `0x80` byte (a redundant zero group) is rejected.
+fn decode<F>(mut next_byte: F, strict: bool) -> Result<u64, Error>
+where
+    F: FnMut() -> Result<u8, Error>,
+{
+    let mut result: u64 = 0;
+    let mut first = true;
+
+    loop {
+        let byte = next_byte()?;
+
+        if strict && first && byte == CONTINUATION_BIT {
+            return Err(Error::NonMinimal);
+        }
+        first = false;
+
+        if result >> (64 - 7) != 0 {
+            return Err(Error::Overflow);
+        }
+        result = result << 7 | u64::from(low_bits_of_byte(byte));
+
+        if byte & CONTINUATION_BIT == 0 {
+            return Ok(result);
+        }
+    }
+}
+
+fn read_with<R>(r: &mut R, strict: bool) -> Result<u64, Error>
+where
+    R: io::Read,
+{
+    decode(read::io_bytes(r), strict)
+}
+
+fn peek_with(buf: &[u8], strict: bool) -> Result<(u64, usize), Error> {
+    let mut len = 0;
+    let val = decode(read::slice_bytes(buf, &mut len), strict)?;
+    Ok((val, len))
+}
+
+/// Read a VLQ number from the given `std::io::Read`able and return it or an
+/// error if reading failed. Redundant leading `0x80` bytes are accepted.
+pub fn read<R>(r: &mut R) -> Result<u64, Error>
+where
+    R: io::Read,
+{
+    read_with(r, false)
+}
+
+/// Read a VLQ number from the given `std::io::Read`able and return it or an
+/// error if reading failed. Numbers padded with a redundant leading `0x80`
+/// byte are rejected with `Error::NonMinimal`.
+pub fn read_strict<R>(r: &mut R) -> Result<u64, Error>
+where
+    R: io::Read,
+{
+    read_with(r, true)
+}
+
+/// Decode a VLQ number from the start of `buf` without consuming it. Returns
+/// the number along with the length of its encoding. Redundant leading `0x80`
+/// bytes are accepted.
+pub fn peek(buf: &[u8]) -> Result<(u64, usize), Error> {
+    peek_with(buf, false)
+}
+
+/// Decode a VLQ number from the start of `buf` without consuming it. Returns
+/// the number along with the length of its encoding. Numbers padded with a
+/// redundant leading `0x80` byte are rejected with `Error::NonMinimal`.
+pub fn peek_strict(buf: &[u8]) -> Result<(u64, usize), Error> {
+    peek_with(buf, true)
+}
+
+/// The number of bytes `write` uses to encode `val`.
+pub fn encoded_len(val: u64) -> usize {
+    let bits = 64 - val.leading_zeros() as usize;
+    if bits == 0 {
+        1
+    } else {
+        bits.div_ceil(7)
+    }
+}
+
+/// Encode `val` into `buf`, returning the length of the encoding.
+fn encode(buf: &mut [u8; MAX_ENCODED_LEN], val: u64) -> usize {
+    let len = encoded_len(val);
+    for (i, byte) in buf[..len].iter_mut().enumerate() {
+        let shift = 7 * (len - 1 - i);
+        *byte = low_bits_of_u64(val >> shift);
+        if i + 1 < len {
+            *byte |= CONTINUATION_BIT;
+        }
+    }
+    len
+}
+
+/// Write the given number using the VLQ encoding to the given
+/// `std::io::Write`able. Returns the number of bytes written to `w`, or an
+/// error if writing failed.
+pub fn write<W>(w: &mut W, val: u64) -> Result<usize, write::Error>
+where
+    W: ?Sized + io::Write,
+{
+    let mut buf = [0; MAX_ENCODED_LEN];
+    let len = encode(&mut buf, val);
+    write::write_encoded(w, &buf[..len])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Examples from the Standard MIDI File specification.
+    const MIDI_EXAMPLES: &[(u64, &[u8])] = &[
+        (0x00000000, &[0x00]),
+        (0x00000040, &[0x40]),
+        (0x0000007f, &[0x7f]),
+        (0x00000080, &[0x81, 0x00]),
+        (0x00002000, &[0xc0, 0x00]),
+        (0x00003fff, &[0xff, 0x7f]),
+        (0x00004000, &[0x81, 0x80, 0x00]),
+        (0x00100000, &[0xc0, 0x80, 0x00]),
+        (0x001fffff, &[0xff, 0xff, 0x7f]),
+        (0x00200000, &[0x81, 0x80, 0x80, 0x00]),
+        (0x08000000, &[0xc0, 0x80, 0x80, 0x00]),
+        (0x0fffffff, &[0xff, 0xff, 0xff, 0x7f]),
+    ];
+
+    #[test]
+    fn test_midi_examples() {
+        for &(val, encoded) in MIDI_EXAMPLES {
+            let mut buf = vec![];
+            assert_eq!(write(&mut buf, val).unwrap(), encoded.len());
+            assert_eq!(buf, encoded);
+            assert_eq!(encoded_len(val), encoded.len());
+            assert_eq!(read(&mut &buf[..]).unwrap(), val);
+            assert_eq!(read_strict(&mut &buf[..]).unwrap(), val);
+            assert_eq!(peek(&buf).unwrap(), (val, encoded.len()));
+        }
+    }
+
+    #[test]
+    fn test_u64_max() {
+        let mut buf = vec![];
+        assert_eq!(write(&mut buf, u64::MAX).unwrap(), 10);
+        assert_eq!(buf[0], 0x81);
+        assert_eq!(peek_strict(&buf).unwrap(), (u64::MAX, 10));
+    }
+
+    #[test]
+    fn test_overflow() {
+        let buf = [0x82, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x00];
+        match peek(&buf) {
+            Err(Error::Overflow) => {}
+            otherwise => panic!("Unexpected: {:?}", otherwise),
+        }
+        let buf = [0xff; 16];
+        match read(&mut &buf[..]) {
+            Err(Error::Overflow) => {}
+            otherwise => panic!("Unexpected: {:?}", otherwise),
+        }
+    }
+
+    #[test]
+    fn test_strict() {
+        let buf = [0x80, 0x80, 0x7f];
+        assert_eq!(peek(&buf).unwrap(), (0x7f, 3));
+        match peek_strict(&buf) {
+            Err(Error::NonMinimal) => {}
+            otherwise => panic!("Unexpected: {:?}", otherwise),
+        }
+        match read_strict(&mut &buf[..]) {
+            Err(Error::NonMinimal) => {}
+            otherwise => panic!("Unexpected: {:?}", otherwise),
+        }
+        assert_eq!(peek_strict(&[0x00]).unwrap(), (0, 1));
+    }
+
+    #[test]
+    fn test_not_enough_data() {
+        match peek(&[0x81, 0x80]) {
+            Err(Error::IoError(e)) => assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof),
+            otherwise => panic!("Unexpected: {:?}", otherwise),
+        }
+    }
+}
@@ -0,0 +1,468 @@
+//! Read and write the LEB128 integer types of the WebAssembly binary format,
+//! with exactly the validation the specification requires.
+//!
+//! An N-bit WebAssembly integer may take at most ceil(N / 7) bytes, and the
+//! unused bits of its last byte must be zero (for unsigned types) or copies of
+//! the sign bit (for signed types). Redundant padding within those bytes is
+//! allowed. `read::unsigned` and `read::signed` are more lenient, so use these
+//! functions when validating modules.
+//!
+//! ```
+//! use leb128::wasm;
+//!
+//! // A padded zero is a valid `varuint32`...
+//! assert_eq!(wasm::peek_varuint32(&[0x80, 0x80, 0x80, 0x80, 0x00]).unwrap(), (0, 5));
+//! // ...but it may not take six bytes.
+//! assert!(wasm::peek_varuint32(&[0x80, 0x80, 0x80, 0x80, 0x80, 0x00]).is_err());
+//!
+//! // The empty block type.
+//! assert_eq!(wasm::peek_varint33(&[0x40]).unwrap(), (-64, 1));
+//! ```
+//!
+//! Encodings that are too long or have invalid unused bits are both rejected
+//! with `read::Error::Overflow`.
+
+use super::{low_bits_of_byte, read, write, CONTINUATION_BIT, SIGN_BIT};
+use std::io;
+use std::str;
+
+pub mod code;
+pub mod names;
+pub mod reloc;
+pub mod sections;
+
+/// Decode an unsigned integer of the given bit width from the bytes produced
+/// by `next_byte`.
+fn decode_unsigned<F>(mut next_byte: F, bits: u32) -> Result<u64, read::Error>
+where
+    F: FnMut() -> Result<u8, read::Error>,
+{
+    let max_len = bits.div_ceil(7);
+    let mut result = 0;
+
+    for i in 0..max_len {
+        let byte = next_byte()?;
+        let low_bits = u64::from(low_bits_of_byte(byte));
+
+        if i == max_len - 1 {
+            let used_bits = bits - 7 * i;
+            if byte & CONTINUATION_BIT != 0 || low_bits >> used_bits != 0 {
+                return Err(read::Error::Overflow);
+            }
+        }
+
+        result |= low_bits << (7 * i);
+        if byte & CONTINUATION_BIT == 0 {
+            break;
+        }
+    }
+
+    Ok(result)
+}
+
+/// Decode a signed integer of the given bit width from the bytes produced by
+/// `next_byte`.
+fn decode_signed<F>(mut next_byte: F, bits: u32) -> Result<i64, read::Error>
+where
+    F: FnMut() -> Result<u8, read::Error>,
+{
+    let max_len = bits.div_ceil(7);
+    let mut result = 0;
+    let mut shift = 0;
+
+    for i in 0..max_len {
+        let byte = next_byte()?;
+        let low_bits = u64::from(low_bits_of_byte(byte));
+
+        if i == max_len - 1 {
+            // The sign bit and the unused bits above it must all be equal.
+            let used_bits = bits - 7 * i;
+            let sign_and_unused = low_bits >> (used_bits - 1);
+            let all_set = (1 << (8 - used_bits)) - 1;
+            if byte & CONTINUATION_BIT != 0 || (sign_and_unused != 0 && sign_and_unused != all_set)
+            {
+                return Err(read::Error::Overflow);
+            }
+        }
+
+        result |= low_bits << shift;
+        shift += 7;
+        if byte & CONTINUATION_BIT == 0 {
+            if shift < 64 && byte & SIGN_BIT != 0 {
+                result |= !0 << shift;
+            }
+            break;
+        }
+    }
+
+    Ok(result as i64)
+}
+
+fn peek_unsigned(buf: &[u8], bits: u32) -> Result<(u64, usize), read::Error> {
+    let mut len = 0;
+    let val = decode_unsigned(read::slice_bytes(buf, &mut len), bits)?;
+    Ok((val, len))
+}
+
+fn peek_signed(buf: &[u8], bits: u32) -> Result<(i64, usize), read::Error> {
+    let mut len = 0;
+    let val = decode_signed(read::slice_bytes(buf, &mut len), bits)?;
+    Ok((val, len))
+}
+
+/// Read a `varuint1` from the given `std::io::Read`able.
+pub fn read_varuint1<R>(r: &mut R) -> Result<bool, read::Error>
+where
+    R: io::Read,
+{
+    decode_unsigned(read::io_bytes(r), 1).map(|val| val != 0)
+}
+
+/// Read a `varint7` from the given `std::io::Read`able.
+pub fn read_varint7<R>(r: &mut R) -> Result<i8, read::Error>
+where
+    R: io::Read,
+{
+    decode_signed(read::io_bytes(r), 7).map(|val| val as i8)
+}
+
+/// Read a `varuint7` from the given `std::io::Read`able.
+pub fn read_varuint7<R>(r: &mut R) -> Result<u8, read::Error>
+where
+    R: io::Read,
+{
+    decode_unsigned(read::io_bytes(r), 7).map(|val| val as u8)
+}
+
+/// Read a `varuint32` from the given `std::io::Read`able.
+pub fn read_varuint32<R>(r: &mut R) -> Result<u32, read::Error>
+where
+    R: io::Read,
+{
+    decode_unsigned(read::io_bytes(r), 32).map(|val| val as u32)
+}
+
+/// Read a `varint32` from the given `std::io::Read`able.
+pub fn read_varint32<R>(r: &mut R) -> Result<i32, read::Error>
+where
+    R: io::Read,
+{
+    decode_signed(read::io_bytes(r), 32).map(|val| val as i32)
+}
+
+/// Read a `varint33`, as used for block types, from the given
+/// `std::io::Read`able.
+pub fn read_varint33<R>(r: &mut R) -> Result<i64, read::Error>
+where
+    R: io::Read,
+{
+    decode_signed(read::io_bytes(r), 33)
+}
+
+/// Read a `varint64` from the given `std::io::Read`able.
+pub fn read_varint64<R>(r: &mut R) -> Result<i64, read::Error>
+where
+    R: io::Read,
+{
+    decode_signed(read::io_bytes(r), 64)
+}
+
+/// Decode a `varuint1` from the start of `buf` without consuming it. Returns
+/// the value along with the length of its encoding.
+pub fn peek_varuint1(buf: &[u8]) -> Result<(bool, usize), read::Error> {
+    peek_unsigned(buf, 1).map(|(val, len)| (val != 0, len))
+}
+
+/// Decode a `varint7` from the start of `buf` without consuming it. Returns
+/// the value along with the length of its encoding.
+pub fn peek_varint7(buf: &[u8]) -> Result<(i8, usize), read::Error> {
+    peek_signed(buf, 7).map(|(val, len)| (val as i8, len))
+}
+
+/// Decode a `varuint7` from the start of `buf` without consuming it. Returns
+/// the value along with the length of its encoding.
+pub fn peek_varuint7(buf: &[u8]) -> Result<(u8, usize), read::Error> {
+    peek_unsigned(buf, 7).map(|(val, len)| (val as u8, len))
+}
+
+/// Decode a `varuint32` from the start of `buf` without consuming it.
+/// Returns the value along with the length of its encoding.
+pub fn peek_varuint32(buf: &[u8]) -> Result<(u32, usize), read::Error> {
+    peek_unsigned(buf, 32).map(|(val, len)| (val as u32, len))
+}
+
+/// Decode a `varint32` from the start of `buf` without consuming it. Returns
+/// the value along with the length of its encoding.
+pub fn peek_varint32(buf: &[u8]) -> Result<(i32, usize), read::Error> {
+    peek_signed(buf, 32).map(|(val, len)| (val as i32, len))
+}
+
+/// Decode a `varint33` from the start of `buf` without consuming it. Returns
+/// the value along with the length of its encoding.
+pub fn peek_varint33(buf: &[u8]) -> Result<(i64, usize), read::Error> {
+    peek_signed(buf, 33)
+}
+
+/// Decode a `varint64` from the start of `buf` without consuming it. Returns
+/// the value along with the length of its encoding.
+pub fn peek_varint64(buf: &[u8]) -> Result<(i64, usize), read::Error> {
+    peek_signed(buf, 64)
+}
+
+/// Decode a name (a `varuint32` length followed by that many bytes of UTF-8)
+/// from the start of `buf` without consuming it. Returns the name along with
+/// the length of its encoding.
+pub fn peek_name(buf: &[u8]) -> Result<(&str, usize), read::Error> {
+    let (len, prefix_len) = peek_varuint32(buf)?;
+    let bytes = buf[prefix_len..]
+        .get(..len as usize)
+        .ok_or_else(|| read::Error::IoError(io::ErrorKind::UnexpectedEof.into()))?;
+    let name = str::from_utf8(bytes).map_err(read::Error::InvalidUtf8)?;
+    Ok((name, prefix_len + bytes.len()))
+}
+
+/// Write a `varuint1` to the given `std::io::Write`able.
+pub fn write_varuint1<W>(w: &mut W, val: bool) -> Result<usize, write::Error>
+where
+    W: ?Sized + io::Write,
+{
+    write::unsigned(w, u64::from(val))
+}
+
+/// Write a `varint7` to the given `std::io::Write`able. Values outside
+/// `-64..=63` are rejected with an `InvalidInput` error.
+pub fn write_varint7<W>(w: &mut W, val: i8) -> Result<usize, write::Error>
+where
+    W: ?Sized + io::Write,
+{
+    if !(-64..=63).contains(&val) {
+        return Err(write::invalid_input("value does not fit in a varint7"));
+    }
+    write::signed(w, i64::from(val))
+}
+
+/// Write a `varuint7` to the given `std::io::Write`able. Values above 127 are
+/// rejected with an `InvalidInput` error.
+pub fn write_varuint7<W>(w: &mut W, val: u8) -> Result<usize, write::Error>
+where
+    W: ?Sized + io::Write,
+{
+    if val > 127 {
+        return Err(write::invalid_input("value does not fit in a varuint7"));
+    }
+    write::unsigned(w, u64::from(val))
+}
+
+/// Write a `varuint32` to the given `std::io::Write`able.
+pub fn write_varuint32<W>(w: &mut W, val: u32) -> Result<usize, write::Error>
+where
+    W: ?Sized + io::Write,
+{
+    write::unsigned(w, u64::from(val))
+}
+
+/// Write a `varint32` to the given `std::io::Write`able.
+pub fn write_varint32<W>(w: &mut W, val: i32) -> Result<usize, write::Error>
+where
+    W: ?Sized + io::Write,
+{
+    write::signed(w, i64::from(val))
+}
+
+/// Write a `varint33` to the given `std::io::Write`able. Values that do not
+/// fit in 33 bits are rejected with an `InvalidInput` error.
+pub fn write_varint33<W>(w: &mut W, val: i64) -> Result<usize, write::Error>
+where
+    W: ?Sized + io::Write,
+{
+    if !(-(1 << 32)..1 << 32).contains(&val) {
+        return Err(write::invalid_input("value does not fit in a varint33"));
+    }
+    write::signed(w, val)
+}
+
+/// Write a `varint64` to the given `std::io::Write`able.
+pub fn write_varint64<W>(w: &mut W, val: i64) -> Result<usize, write::Error>
+where
+    W: ?Sized + io::Write,
+{
+    write::signed(w, val)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_malformed<T: std::fmt::Debug>(result: Result<(T, usize), read::Error>) {
+        match result {
+            Err(read::Error::Overflow) => {}
+            otherwise => panic!("Unexpected: {:?}", otherwise),
+        }
+    }
+
+    // Cases from the specification's binary-leb128.wast.
+    #[test]
+    fn test_varuint32() {
+        assert_eq!(
+            peek_varuint32(&[0x80, 0x80, 0x80, 0x80, 0x00]).unwrap(),
+            (0, 5)
+        );
+        assert_eq!(
+            peek_varuint32(&[0xff, 0xff, 0xff, 0xff, 0x0f]).unwrap(),
+            (u32::MAX, 5)
+        );
+        // Integer representation too long.
+        assert_malformed(peek_varuint32(&[0x82, 0x80, 0x80, 0x80, 0x80, 0x00]));
+        assert_malformed(peek_varuint32(&[0x80, 0x80, 0x80, 0x80, 0x80, 0x00]));
+        // Integer too large.
+        assert_malformed(peek_varuint32(&[0x82, 0x80, 0x80, 0x80, 0x70]));
+        assert_malformed(peek_varuint32(&[0x82, 0x80, 0x80, 0x80, 0x40]));
+        assert_malformed(peek_varuint32(&[0x82, 0x80, 0x80, 0x80, 0x10]));
+    }
+
+    #[test]
+    fn test_varint32() {
+        assert_eq!(
+            peek_varint32(&[0xff, 0xff, 0xff, 0xff, 0x7f]).unwrap(),
+            (-1, 5)
+        );
+        assert_eq!(
+            peek_varint32(&[0x80, 0x80, 0x80, 0x80, 0x78]).unwrap(),
+            (i32::MIN, 5)
+        );
+        assert_eq!(
+            peek_varint32(&[0xff, 0xff, 0xff, 0xff, 0x07]).unwrap(),
+            (i32::MAX, 5)
+        );
+        // Integer representation too long.
+        assert_malformed(peek_varint32(&[0xff, 0xff, 0xff, 0xff, 0xff, 0x7f]));
+        assert_malformed(peek_varint32(&[0x80, 0x80, 0x80, 0x80, 0x80, 0x00]));
+        // Integer too large.
+        assert_malformed(peek_varint32(&[0x80, 0x80, 0x80, 0x80, 0x70]));
+        assert_malformed(peek_varint32(&[0xff, 0xff, 0xff, 0xff, 0x0f]));
+        assert_malformed(peek_varint32(&[0x80, 0x80, 0x80, 0x80, 0x1f]));
+        assert_malformed(peek_varint32(&[0xff, 0xff, 0xff, 0xff, 0x4f]));
+    }
+
+    #[test]
+    fn test_varint64() {
+        let mut max = [0xff; 10];
+        max[9] = 0x00;
+        assert_eq!(peek_varint64(&max).unwrap(), (i64::MAX, 10));
+        let mut min = [0x80; 10];
+        min[9] = 0x7f;
+        assert_eq!(peek_varint64(&min).unwrap(), (i64::MIN, 10));
+
+        for &last in &[0x7e, 0x02] {
+            let mut buf = [0x80; 10];
+            buf[9] = last;
+            assert_malformed(peek_varint64(&buf));
+        }
+        for &last in &[0x01, 0x41] {
+            let mut buf = [0xff; 10];
+            buf[9] = last;
+            assert_malformed(peek_varint64(&buf));
+        }
+        let mut too_long = [0xff; 11];
+        too_long[10] = 0x7f;
+        assert_malformed(peek_varint64(&too_long));
+    }
+
+    #[test]
+    fn test_varint33() {
+        assert_eq!(peek_varint33(&[0x40]).unwrap(), (-64, 1));
+        assert_eq!(peek_varint33(&[0x7f]).unwrap(), (-1, 1));
+        assert_eq!(
+            peek_varint33(&[0xff, 0xff, 0xff, 0xff, 0x0f]).unwrap(),
+            (0xffff_ffff, 5)
+        );
+        assert_eq!(
+            peek_varint33(&[0x80, 0x80, 0x80, 0x80, 0x70]).unwrap(),
+            (-(1 << 32), 5)
+        );
+        assert_malformed(peek_varint33(&[0x80, 0x80, 0x80, 0x80, 0x10]));
+        assert_malformed(peek_varint33(&[0xff, 0xff, 0xff, 0xff, 0x6f]));
+        assert_malformed(peek_varint33(&[0x80, 0x80, 0x80, 0x80, 0x80, 0x00]));
+    }
+
+    #[test]
+    fn test_small_types() {
+        assert_eq!(peek_varuint1(&[0x00]).unwrap(), (false, 1));
+        assert_eq!(peek_varuint1(&[0x01]).unwrap(), (true, 1));
+        assert_malformed(peek_varuint1(&[0x02]));
+        assert_malformed(peek_varuint1(&[0x81, 0x00]));
+
+        assert_eq!(peek_varint7(&[0x40]).unwrap(), (-64, 1));
+        assert_eq!(peek_varint7(&[0x3f]).unwrap(), (63, 1));
+        assert_malformed(peek_varint7(&[0x80, 0x7f]));
+
+        assert_eq!(peek_varuint7(&[0x7f]).unwrap(), (127, 1));
+        assert_malformed(peek_varuint7(&[0x80, 0x00]));
+    }
+
+    #[test]
+    fn test_name() {
+        assert_eq!(peek_name(b"\x04name\x00").unwrap(), ("name", 5));
+        assert_eq!(peek_name(b"\x80\x00").unwrap(), ("", 2));
+        match peek_name(b"\x05name") {
+            Err(read::Error::IoError(e)) => assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof),
+            otherwise => panic!("Unexpected: {:?}", otherwise),
+        }
+        match peek_name(b"\x01\xff") {
+            Err(read::Error::InvalidUtf8(_)) => {}
+            otherwise => panic!("Unexpected: {:?}", otherwise),
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for &val in &[
+            0,
+            1,
+            -1,
+            63,
+            -64,
+            64,
+            -65,
+            i64::from(i32::MIN),
+            1 << 32,
+            i64::MIN,
+            i64::MAX,
+        ] {
+            let mut buf = vec![];
+            write_varint64(&mut buf, val).unwrap();
+            assert_eq!(read_varint64(&mut &buf[..]).unwrap(), val);
+
+            let mut buf = vec![];
+            match write_varint33(&mut buf, val) {
+                Ok(_) => assert_eq!(read_varint33(&mut &buf[..]).unwrap(), val),
+                Err(e) => {
+                    assert_eq!(e.io_error().kind(), io::ErrorKind::InvalidInput);
+                    assert!(!(-(1 << 32)..1 << 32).contains(&val));
+                }
+            }
+        }
+        for &val in &[0, 1, 0x7f, 0x80, u32::MAX] {
+            let mut buf = vec![];
+            write_varuint32(&mut buf, val).unwrap();
+            assert_eq!(read_varuint32(&mut &buf[..]).unwrap(), val);
+            let mut buf = vec![];
+            write_varint32(&mut buf, val as i32).unwrap();
+            assert_eq!(read_varint32(&mut &buf[..]).unwrap(), val as i32);
+        }
+
+        let mut buf = vec![];
+        write_varuint1(&mut buf, true).unwrap();
+        write_varint7(&mut buf, -64).unwrap();
+        write_varuint7(&mut buf, 127).unwrap();
+        assert_eq!(buf, [0x01, 0x40, 0x7f]);
+        let mut readable = &buf[..];
+        assert!(read_varuint1(&mut readable).unwrap());
+        assert_eq!(read_varint7(&mut readable).unwrap(), -64);
+        assert_eq!(read_varuint7(&mut readable).unwrap(), 127);
+
+        assert!(write_varint7(&mut buf, 64).is_err());
+        assert!(write_varuint7(&mut buf, 128).is_err());
+        assert_eq!(buf.len(), 3);
+    }
+}
@@ -0,0 +1,542 @@
+//! Decode the function bodies of a WebAssembly code section into their local
+//! declarations and instructions, with each instruction's immediates and the
+//! bytes it spans.
+//!
+//! The decoder covers the MVP instruction set along with the sign extension,
+//! non-trapping float-to-int, bulk memory, reference types, multi-value and
+//! tail call proposals. Other opcodes, such as SIMD, are reported as unknown.
+//!
+//! ```
+//! use leb128::wasm::code::{FunctionBody, Immediate};
+//!
+//! // (local i32) i32.const 42 local.set 0 call 7 end
+//! let body = [0x01, 0x01, 0x7f, 0x41, 0x2a, 0x21, 0x00, 0x10, 0x07, 0x0b];
+//! let body = FunctionBody::parse(&body, 0).unwrap();
+//! assert_eq!(body.locals[0].ty, 0x7f);
+//!
+//! let calls: Vec<_> = body
+//!     .instructions()
+//!     .map(Result::unwrap)
+//!     .filter(|instr| instr.opcode == 0x10)
+//!     .collect();
+//! assert_eq!(calls[0].immediate, Immediate::Index(7));
+//! assert_eq!(calls[0].immediate_offset, 8);
+//! ```
+
+use super::super::reader::{self, ErrorKind, Leb128Reader};
+use super::{peek_varint32, peek_varint33, peek_varint64, peek_varuint32};
+use std::fmt;
+
+/// The prefix byte of the non-trapping float-to-int, bulk memory and table
+/// instructions.
+pub const MISC_PREFIX: u8 = 0xfc;
+
+/// An error that occurred while decoding a function body.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// A count, opcode or immediate could not be read.
+    Read(reader::Error),
+    /// An opcode was not recognized. Carries the offset and opcode.
+    UnknownOpcode(usize, u8),
+    /// A prefixed opcode was not recognized. Carries the offset, prefix and
+    /// opcode.
+    UnknownPrefixedOpcode(usize, u8, u32),
+}
+
+impl From<reader::Error> for Error {
+    fn from(e: reader::Error) -> Self {
+        Error::Read(e)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match *self {
+            Error::Read(ref e) => write!(f, "leb128::wasm::code::Error: {}", e),
+            Error::UnknownOpcode(offset, opcode) => write!(
+                f,
+                "leb128::wasm::code::Error: Unknown opcode {:#04x} at offset {:#x}",
+                opcode, offset
+            ),
+            Error::UnknownPrefixedOpcode(offset, prefix, opcode) => write!(
+                f,
+                "leb128::wasm::code::Error: Unknown opcode {:#04x} {} at offset {:#x}",
+                prefix, opcode, offset
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match *self {
+            Error::Read(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// Read a `varuint32` count of elements that each take at least one byte,
+/// rejecting counts larger than the rest of the input.
+fn read_count(reader: &mut Leb128Reader) -> Result<u32, reader::Error> {
+    let offset = reader.offset();
+    let count = reader.read_with(peek_varuint32)?;
+    if count as usize > reader.remaining() {
+        return Err(reader::Error::new(offset, ErrorKind::UnexpectedEof));
+    }
+    Ok(count)
+}
+
+/// A run of local variables of the same type.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Local {
+    /// The number of locals.
+    pub count: u32,
+    /// The value type of the locals, such as `0x7f` for `i32`.
+    pub ty: u8,
+}
+
+/// The type of a `block`, `loop` or `if`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BlockType {
+    /// No parameters or results.
+    Empty,
+    /// A single result of the given value type.
+    Value(u8),
+    /// The function type at the given index in the type section.
+    TypeIndex(u32),
+}
+
+/// The immediates of an instruction.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Immediate {
+    /// The instruction has no immediates.
+    None,
+    /// The type of a `block`, `loop` or `if`.
+    BlockType(BlockType),
+    /// A single index, such as a label, function, local, global, table,
+    /// memory, data segment or element segment index.
+    Index(u32),
+    /// Two indices, as for `memory.init` (data segment and memory),
+    /// `memory.copy` (destination and source memories), `table.init` (element
+    /// segment and table) and `table.copy` (destination and source tables).
+    Indices(u32, u32),
+    /// The targets of a `br_table`.
+    BrTable {
+        /// The label indices of the targets.
+        targets: Vec<u32>,
+        /// The label index used when the operand is out of range.
+        default: u32,
+    },
+    /// The type and table of a `call_indirect` or `return_call_indirect`.
+    CallIndirect {
+        /// The index of the function type.
+        type_index: u32,
+        /// The index of the table.
+        table_index: u32,
+    },
+    /// The alignment exponent and offset of a load or store.
+    MemArg {
+        /// The base 2 logarithm of the alignment.
+        align: u32,
+        /// The constant offset added to the address.
+        offset: u32,
+    },
+    /// The value of an `i32.const`.
+    I32(i32),
+    /// The value of an `i64.const`.
+    I64(i64),
+    /// The bits of the value of an `f32.const`.
+    F32(u32),
+    /// The bits of the value of an `f64.const`.
+    F64(u64),
+    /// The value types of a typed `select`.
+    ValueTypes(Vec<u8>),
+    /// The reference type of a `ref.null`.
+    RefType(u8),
+}
+
+/// A decoded instruction.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Instruction {
+    /// The opcode, or the prefix byte for prefixed instructions.
+    pub opcode: u8,
+    /// The opcode following the prefix byte of a prefixed instruction.
+    pub prefixed_opcode: Option<u32>,
+    /// The instruction's immediates.
+    pub immediate: Immediate,
+    /// The offset of the instruction.
+    pub offset: usize,
+    /// The offset of the instruction's first immediate, just after its
+    /// opcode.
+    pub immediate_offset: usize,
+    /// The length of the instruction, including its opcode and immediates.
+    pub len: usize,
+}
+
+/// Read the immediates of an unprefixed instruction.
+fn read_immediate(
+    reader: &mut Leb128Reader,
+    offset: usize,
+    opcode: u8,
+) -> Result<Immediate, Error> {
+    Ok(match opcode {
+        0x00 | 0x01 | 0x05 | 0x0b | 0x0f | 0x1a | 0x1b | 0x45..=0xc4 | 0xd1 => Immediate::None,
+        0x02..=0x04 => Immediate::BlockType(match reader.read_with(peek_varint33)? {
+            -0x40 => BlockType::Empty,
+            index if index >= 0 => BlockType::TypeIndex(index as u32),
+            ty => BlockType::Value((ty & 0x7f) as u8),
+        }),
+        0x0c | 0x0d | 0x10 | 0x12 | 0x20..=0x26 | 0x3f | 0x40 | 0xd2 => {
+            Immediate::Index(reader.read_with(peek_varuint32)?)
+        }
+        0x0e => {
+            let count = read_count(reader)?;
+            let mut targets = Vec::with_capacity(count as usize);
+            for _ in 0..count {
+                targets.push(reader.read_with(peek_varuint32)?);
+            }
+            Immediate::BrTable {
+                targets,
+                default: reader.read_with(peek_varuint32)?,
+            }
+        }
+        0x11 | 0x13 => Immediate::CallIndirect {
+            type_index: reader.read_with(peek_varuint32)?,
+            table_index: reader.read_with(peek_varuint32)?,
+        },
+        0x1c => {
+            let count = read_count(reader)?;
+            Immediate::ValueTypes(reader.read_bytes(count as usize)?.to_vec())
+        }
+        0x28..=0x3e => Immediate::MemArg {
+            align: reader.read_with(peek_varuint32)?,
+            offset: reader.read_with(peek_varuint32)?,
+        },
+        0x41 => Immediate::I32(reader.read_with(peek_varint32)?),
+        0x42 => Immediate::I64(reader.read_with(peek_varint64)?),
+        0x43 => {
+            let mut bits = [0; 4];
+            bits.copy_from_slice(reader.read_bytes(4)?);
+            Immediate::F32(u32::from_le_bytes(bits))
+        }
+        0x44 => {
+            let mut bits = [0; 8];
+            bits.copy_from_slice(reader.read_bytes(8)?);
+            Immediate::F64(u64::from_le_bytes(bits))
+        }
+        0xd0 => Immediate::RefType(reader.read_u8()?),
+        _ => return Err(Error::UnknownOpcode(offset, opcode)),
+    })
+}
+
+/// Read the immediates of an instruction with the `MISC_PREFIX` prefix.
+fn read_misc_immediate(
+    reader: &mut Leb128Reader,
+    offset: usize,
+    opcode: u32,
+) -> Result<Immediate, Error> {
+    Ok(match opcode {
+        0..=7 => Immediate::None,
+        9 | 11 | 13 | 15..=17 => Immediate::Index(reader.read_with(peek_varuint32)?),
+        8 | 10 | 12 | 14 => Immediate::Indices(
+            reader.read_with(peek_varuint32)?,
+            reader.read_with(peek_varuint32)?,
+        ),
+        _ => return Err(Error::UnknownPrefixedOpcode(offset, MISC_PREFIX, opcode)),
+    })
+}
+
+/// A function body from the code section.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FunctionBody<'a> {
+    /// The body's local variable declarations.
+    pub locals: Vec<Local>,
+    /// The offset of the body's instructions.
+    pub offset: usize,
+    /// The body's instructions, ending with `end`.
+    pub code: &'a [u8],
+}
+
+impl<'a> FunctionBody<'a> {
+    /// Decode the local declarations of the function body in `body`, which
+    /// starts at `offset` within the module, excluding its size.
+    pub fn parse(body: &'a [u8], offset: usize) -> Result<FunctionBody<'a>, Error> {
+        let mut reader = Leb128Reader::with_offset(body, offset);
+        let count = read_count(&mut reader)?;
+        let mut locals = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            locals.push(Local {
+                count: reader.read_with(peek_varuint32)?,
+                ty: reader.read_u8()?,
+            });
+        }
+
+        Ok(FunctionBody {
+            locals,
+            offset: reader.offset(),
+            code: reader.rest(),
+        })
+    }
+
+    /// Iterate over the body's instructions.
+    pub fn instructions(&self) -> Instructions<'a> {
+        Instructions {
+            reader: Leb128Reader::with_offset(self.code, self.offset),
+            failed: false,
+        }
+    }
+}
+
+/// An iterator over the instructions of a function body.
+///
+/// Iteration stops after the first error.
+#[derive(Clone, Debug)]
+pub struct Instructions<'a> {
+    reader: Leb128Reader<'a>,
+    failed: bool,
+}
+
+impl<'a> Instructions<'a> {
+    fn read_instruction(&mut self) -> Result<Instruction, Error> {
+        let offset = self.reader.offset();
+        let opcode = self.reader.read_u8()?;
+
+        let (prefixed_opcode, immediate_offset, immediate) = if opcode == MISC_PREFIX {
+            let prefixed_opcode = self.reader.read_with(peek_varuint32)?;
+            let immediate_offset = self.reader.offset();
+            let immediate = read_misc_immediate(&mut self.reader, offset, prefixed_opcode)?;
+            (Some(prefixed_opcode), immediate_offset, immediate)
+        } else {
+            let immediate_offset = self.reader.offset();
+            let immediate = read_immediate(&mut self.reader, offset, opcode)?;
+            (None, immediate_offset, immediate)
+        };
+
+        Ok(Instruction {
+            opcode,
+            prefixed_opcode,
+            immediate,
+            offset,
+            immediate_offset,
+            len: self.reader.offset() - offset,
+        })
+    }
+}
+
+impl<'a> Iterator for Instructions<'a> {
+    type Item = Result<Instruction, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed || self.reader.is_empty() {
+            return None;
+        }
+        let result = self.read_instruction();
+        self.failed = result.is_err();
+        Some(result)
+    }
+}
+
+/// An iterator over the function bodies in the payload of a code section.
+///
+/// Iteration stops after the first error.
+#[derive(Clone, Debug)]
+pub struct FunctionBodies<'a> {
+    reader: Leb128Reader<'a>,
+    remaining: u32,
+}
+
+impl<'a> FunctionBodies<'a> {
+    /// Iterate over the function bodies in the code section payload read by
+    /// `reader`, such as the one returned by `sections::Section::reader`.
+    pub fn new(mut reader: Leb128Reader<'a>) -> Result<FunctionBodies<'a>, Error> {
+        let remaining = read_count(&mut reader)?;
+        Ok(FunctionBodies { reader, remaining })
+    }
+
+    fn read_body(&mut self) -> Result<FunctionBody<'a>, Error> {
+        let size_offset = self.reader.offset();
+        let size = self.reader.read_with(peek_varuint32)?;
+        if size as usize > self.reader.remaining() {
+            return Err(reader::Error::new(size_offset, ErrorKind::UnexpectedEof).into());
+        }
+        let body = self.reader.sub_reader(size as usize)?;
+        FunctionBody::parse(body.rest(), body.offset())
+    }
+}
+
+impl<'a> Iterator for FunctionBodies<'a> {
+    type Item = Result<FunctionBody<'a>, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let result = self.read_body();
+        self.remaining = if result.is_ok() {
+            self.remaining - 1
+        } else {
+            0
+        };
+        Some(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn instructions(code: &[u8]) -> Vec<Instruction> {
+        let body = FunctionBody {
+            locals: vec![],
+            offset: 0x100,
+            code,
+        };
+        body.instructions().map(Result::unwrap).collect()
+    }
+
+    fn immediates(code: &[u8]) -> Vec<Immediate> {
+        instructions(code)
+            .into_iter()
+            .map(|instr| instr.immediate)
+            .collect()
+    }
+
+    #[test]
+    fn test_control() {
+        #[rustfmt::skip]
+        let code = [
+            0x02, 0x40,             // block
+            0x03, 0x7f,             // loop (result i32)
+            0x04, 0x05,             // if (type 5)
+            0x0c, 0x01,             // br 1
+            0x0e, 0x02, 0x00, 0x01, 0x02, // br_table 0 1 2
+            0x11, 0x03, 0x00,       // call_indirect (type 3)
+            0x05, 0x0b, 0x0b, 0x0b, // else end end end
+        ];
+        assert_eq!(
+            immediates(&code),
+            vec![
+                Immediate::BlockType(BlockType::Empty),
+                Immediate::BlockType(BlockType::Value(0x7f)),
+                Immediate::BlockType(BlockType::TypeIndex(5)),
+                Immediate::Index(1),
+                Immediate::BrTable {
+                    targets: vec![0, 1],
+                    default: 2,
+                },
+                Immediate::CallIndirect {
+                    type_index: 3,
+                    table_index: 0,
+                },
+                Immediate::None,
+                Immediate::None,
+                Immediate::None,
+                Immediate::None,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_constants_and_memory() {
+        #[rustfmt::skip]
+        let code = [
+            0x41, 0x80, 0x7f,                                     // i32.const -128
+            0x42, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x00, // i64.const max
+            0x43, 0x00, 0x00, 0x80, 0x3f,                         // f32.const 1.0
+            0x44, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xf0, 0x3f, // f64.const 1.0
+            0x28, 0x02, 0x90, 0x03,                               // i32.load align=4 offset=400
+            0x40, 0x00,                                           // memory.grow
+            0x6a,                                                 // i32.add
+            0x1c, 0x01, 0x7e,                                     // select (result i64)
+            0xd0, 0x70,                                           // ref.null func
+            0xfc, 0x08, 0x01, 0x00,                               // memory.init 1
+            0xfc, 0x00,                                           // i32.trunc_sat_f32_s
+        ];
+        let instrs = instructions(&code);
+        let immediates: Vec<_> = instrs.iter().map(|instr| instr.immediate.clone()).collect();
+        assert_eq!(
+            immediates,
+            vec![
+                Immediate::I32(-128),
+                Immediate::I64(i64::MAX),
+                Immediate::F32(1.0f32.to_bits()),
+                Immediate::F64(1.0f64.to_bits()),
+                Immediate::MemArg {
+                    align: 2,
+                    offset: 400,
+                },
+                Immediate::Index(0),
+                Immediate::None,
+                Immediate::ValueTypes(vec![0x7e]),
+                Immediate::RefType(0x70),
+                Immediate::Indices(1, 0),
+                Immediate::None,
+            ]
+        );
+
+        // The spans cover the whole body without gaps.
+        let mut offset = 0x100;
+        for instr in &instrs {
+            assert_eq!(instr.offset, offset);
+            offset += instr.len;
+        }
+        assert_eq!(offset, 0x100 + code.len());
+        assert_eq!(instrs[9].prefixed_opcode, Some(8));
+        assert_eq!(instrs[9].immediate_offset, instrs[9].offset + 2);
+    }
+
+    #[test]
+    fn test_function_bodies() {
+        #[rustfmt::skip]
+        let section = [
+            0x02,
+            // (local i32 i32 f64) nop end
+            0x07, 0x02, 0x02, 0x7f, 0x01, 0x7c, 0x01, 0x0b,
+            // end
+            0x02, 0x00, 0x0b,
+        ];
+        let bodies: Vec<_> = FunctionBodies::new(Leb128Reader::with_offset(&section, 0x20))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(bodies.len(), 2);
+        assert_eq!(
+            bodies[0].locals,
+            vec![Local { count: 2, ty: 0x7f }, Local { count: 1, ty: 0x7c }]
+        );
+        assert_eq!(bodies[0].offset, 0x27);
+        assert_eq!(bodies[0].code, [0x01, 0x0b]);
+        assert_eq!(bodies[1].locals, vec![]);
+        assert_eq!(bodies[1].offset, 0x2b);
+    }
+
+    #[test]
+    fn test_errors() {
+        let body = FunctionBody::parse(&[0x00, 0x01, 0xfd, 0x0c], 0x10).unwrap();
+        let mut instrs = body.instructions();
+        assert!(instrs.next().unwrap().is_ok());
+        assert_eq!(instrs.next(), Some(Err(Error::UnknownOpcode(0x12, 0xfd))));
+        assert_eq!(instrs.next(), None);
+
+        let body = FunctionBody::parse(&[0x00, 0xfc, 0x12], 0).unwrap();
+        assert_eq!(
+            body.instructions().next(),
+            Some(Err(Error::UnknownPrefixedOpcode(1, MISC_PREFIX, 0x12)))
+        );
+
+        // An i32.const with six bytes.
+        let body =
+            FunctionBody::parse(&[0x00, 0x41, 0x80, 0x80, 0x80, 0x80, 0x80, 0x00], 0).unwrap();
+        assert_eq!(
+            body.instructions().next(),
+            Some(Err(Error::Read(reader::Error::new(2, ErrorKind::Overflow))))
+        );
+
+        // A local declaration count larger than the body.
+        assert_eq!(
+            FunctionBody::parse(&[0x05, 0x01, 0x7f], 0),
+            Err(Error::Read(reader::Error::new(0, ErrorKind::UnexpectedEof)))
+        );
+    }
+}
@@ -0,0 +1,382 @@
+//! Read and write the WebAssembly `name` custom section, which maps module,
+//! function and local indices to names for debuggers and symbolizers.
+//!
+//! The section is a sequence of subsections, each a one-byte id and a
+//! length-prefixed payload, in increasing id order. Names are stored in name
+//! maps: vectors of unsigned LEB128 indices and length-prefixed UTF-8 strings.
+//!
+//! ```
+//! use leb128::wasm::names::{self, NameSection, Naming};
+//!
+//! let section = NameSection {
+//!     module: Some("demo".to_string()),
+//!     functions: vec![Naming { index: 0, name: "main".to_string() }],
+//!     ..Default::default()
+//! };
+//!
+//! let mut buf = vec![];
+//! names::write(&mut buf, &section).unwrap();
+//! assert_eq!(buf[..7], [0x00, 0x05, 0x04, b'd', b'e', b'm', b'o']);
+//! assert_eq!(names::read(&buf).unwrap(), section);
+//! ```
+
+use super::super::read::{self, Limits};
+use super::super::write;
+use std::fmt;
+use std::io::{self, Read};
+
+/// The id of the module name subsection.
+pub const MODULE: u8 = 0;
+/// The id of the function names subsection.
+pub const FUNCTIONS: u8 = 1;
+/// The id of the local names subsection.
+pub const LOCALS: u8 = 2;
+
+/// An error that occurred while reading a name section.
+#[derive(Debug)]
+pub enum Error {
+    /// A subsection, index or name could not be read.
+    Read(read::Error),
+    /// A subsection appeared after one with the same or a larger id. Carries
+    /// the id.
+    MisplacedSubsection(u8),
+    /// A subsection had bytes left over after its contents. Carries the id.
+    TrailingBytes(u8),
+}
+
+impl From<read::Error> for Error {
+    fn from(e: read::Error) -> Self {
+        Error::Read(e)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Read(read::Error::IoError(e))
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match *self {
+            Error::Read(ref e) => write!(f, "leb128::wasm::names::Error: {}", e),
+            Error::MisplacedSubsection(id) => write!(
+                f,
+                "leb128::wasm::names::Error: Subsection {} is out of order",
+                id
+            ),
+            Error::TrailingBytes(id) => write!(
+                f,
+                "leb128::wasm::names::Error: Trailing bytes in subsection {}",
+                id
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match *self {
+            Error::Read(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// A name for the entity at an index.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Naming {
+    /// The index of the function or local.
+    pub index: u32,
+    /// The name.
+    pub name: String,
+}
+
+/// The names of the locals of one function.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LocalNames {
+    /// The index of the function.
+    pub function: u32,
+    /// The names of its locals.
+    pub locals: Vec<Naming>,
+}
+
+/// The contents of a name section.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct NameSection {
+    /// The module's name, if the section has one.
+    pub module: Option<String>,
+    /// The names of functions.
+    pub functions: Vec<Naming>,
+    /// The names of locals, grouped by function.
+    pub locals: Vec<LocalNames>,
+    /// Subsections other than the three above, as their id and payload, in
+    /// the order they appeared.
+    pub other: Vec<(u8, Vec<u8>)>,
+}
+
+fn read_index(r: &mut &[u8]) -> Result<u32, read::Error> {
+    let index = read::unsigned(r)?;
+    if index > u64::from(u32::MAX) {
+        return Err(read::Error::Overflow);
+    }
+    Ok(index as u32)
+}
+
+fn read_name_map(r: &mut &[u8]) -> Result<Vec<Naming>, read::Error> {
+    let limits = Limits::default().with_remaining_input(r.len());
+    read::vec_with(r, limits, |r| {
+        Ok(Naming {
+            index: read_index(r)?,
+            name: read::string(r, r.len())?,
+        })
+    })
+}
+
+/// Read a name section from `payload`, the section's contents after its
+/// `name` name.
+pub fn read(payload: &[u8]) -> Result<NameSection, Error> {
+    let mut r = payload;
+    let mut section = NameSection::default();
+    let mut last_id = None;
+
+    while !r.is_empty() {
+        let mut id = [0];
+        r.read_exact(&mut id)?;
+        let id = id[0];
+        if last_id.is_some_and(|last| id <= last) {
+            return Err(Error::MisplacedSubsection(id));
+        }
+        last_id = Some(id);
+
+        let max_len = r.len();
+        let contents = read::bytes(&mut r, max_len)?;
+        let mut sub = &contents[..];
+        match id {
+            MODULE => section.module = Some(read::string(&mut sub, contents.len())?),
+            FUNCTIONS => section.functions = read_name_map(&mut sub)?,
+            LOCALS => {
+                let limits = Limits::default().with_remaining_input(sub.len());
+                section.locals = read::vec_with(&mut sub, limits, |r| {
+                    Ok(LocalNames {
+                        function: read_index(r)?,
+                        locals: read_name_map(r)?,
+                    })
+                })?;
+            }
+            _ => {
+                section.other.push((id, contents));
+                continue;
+            }
+        }
+        if !sub.is_empty() {
+            return Err(Error::TrailingBytes(id));
+        }
+    }
+
+    Ok(section)
+}
+
+fn write_name_map(w: &mut Vec<u8>, names: &[Naming]) -> Result<usize, write::Error> {
+    write::vec_with(w, names, |w, naming| {
+        let len = write::unsigned(w, u64::from(naming.index))?;
+        Ok(len + write::str(w, &naming.name)?)
+    })
+}
+
+/// Write `section` as the payload of a name section, with its subsections in
+/// increasing id order. Empty function and local name maps are left out. The
+/// whole payload is encoded up front and handed to `w` in one go. Returns the
+/// number of bytes written to `w`, or an error if writing failed.
+///
+/// Sections whose `other` subsections use the id of the module, function or
+/// local names, or reuse an id, are rejected with an `InvalidInput` error
+/// before anything is written.
+pub fn write<W>(w: &mut W, section: &NameSection) -> Result<usize, write::Error>
+where
+    W: ?Sized + io::Write,
+{
+    if section.other.iter().any(|&(id, _)| id <= LOCALS) {
+        return Err(write::invalid_input(
+            "other name subsections must not use a known id",
+        ));
+    }
+
+    let mut subsections: Vec<(u8, Vec<u8>)> = vec![];
+    if let Some(ref module) = section.module {
+        let mut sub = vec![];
+        write::str(&mut sub, module)?;
+        subsections.push((MODULE, sub));
+    }
+    if !section.functions.is_empty() {
+        let mut sub = vec![];
+        write_name_map(&mut sub, &section.functions)?;
+        subsections.push((FUNCTIONS, sub));
+    }
+    if !section.locals.is_empty() {
+        let mut sub = vec![];
+        write::vec_with(&mut sub, &section.locals, |w, names| {
+            let len = write::unsigned(w, u64::from(names.function))?;
+            Ok(len + write_name_map(w, &names.locals)?)
+        })?;
+        subsections.push((LOCALS, sub));
+    }
+    subsections.extend(section.other.iter().cloned());
+    subsections.sort_by_key(|&(id, _)| id);
+    if subsections.windows(2).any(|pair| pair[0].0 == pair[1].0) {
+        return Err(write::invalid_input("name subsection ids must be unique"));
+    }
+
+    let mut buf = vec![];
+    for (id, sub) in &subsections {
+        buf.push(*id);
+        write::bytes(&mut buf, sub)?;
+    }
+    write::write_encoded(w, &buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn naming(index: u32, name: &str) -> Naming {
+        Naming {
+            index,
+            name: name.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_read() {
+        #[rustfmt::skip]
+        let payload = [
+            // The module name.
+            0x00, 0x02, 0x01, b'm',
+            // Two function names.
+            0x01, 0x08, 0x02, 0x00, 0x02, b'f', b'0', 0x81, 0x01, 0x00,
+            // Locals of function 1.
+            0x02, 0x06, 0x01, 0x01, 0x01, 0x03, 0x01, b'x',
+            // Label names.
+            0x03, 0x02, 0xaa, 0xbb,
+        ];
+        assert_eq!(
+            read(&payload).unwrap(),
+            NameSection {
+                module: Some("m".to_string()),
+                functions: vec![naming(0, "f0"), naming(0x81, "")],
+                locals: vec![LocalNames {
+                    function: 1,
+                    locals: vec![naming(3, "x")],
+                }],
+                other: vec![(3, vec![0xaa, 0xbb])],
+            }
+        );
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let section = NameSection {
+            module: Some("module".to_string()),
+            functions: vec![naming(0, "_start"), naming(300, "ünïcödé")],
+            locals: vec![
+                LocalNames {
+                    function: 0,
+                    locals: vec![naming(0, "argc"), naming(1, "argv")],
+                },
+                LocalNames {
+                    function: 300,
+                    locals: vec![],
+                },
+            ],
+            other: vec![(7, vec![0x01, 0x00, 0x00]), (4, vec![])],
+        };
+        let mut buf = vec![];
+        let len = write(&mut buf, &section).unwrap();
+        assert_eq!(len, buf.len());
+
+        // The other subsections come back in id order.
+        let mut expected = section.clone();
+        expected.other.reverse();
+        assert_eq!(read(&buf).unwrap(), expected);
+
+        let mut buf = vec![];
+        write(&mut buf, &NameSection::default()).unwrap();
+        assert!(buf.is_empty());
+        assert_eq!(read(&buf).unwrap(), NameSection::default());
+    }
+
+    #[test]
+    fn test_read_errors() {
+        match read(&[0x01, 0x01, 0x00, 0x00, 0x01, 0x00]) {
+            Err(Error::MisplacedSubsection(0)) => {}
+            otherwise => panic!("Unexpected: {:?}", otherwise),
+        }
+        match read(&[0x01, 0x01, 0x00, 0x01, 0x01, 0x00]) {
+            Err(Error::MisplacedSubsection(1)) => {}
+            otherwise => panic!("Unexpected: {:?}", otherwise),
+        }
+        // A subsection larger than the rest of the section.
+        match read(&[0x00, 0x05, 0x01, b'm']) {
+            Err(Error::Read(read::Error::TooLong)) => {}
+            otherwise => panic!("Unexpected: {:?}", otherwise),
+        }
+        // A name map with more entries than bytes.
+        match read(&[0x01, 0x02, 0x05, 0x00]) {
+            Err(Error::Read(read::Error::IoError(e))) => {
+                assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof)
+            }
+            otherwise => panic!("Unexpected: {:?}", otherwise),
+        }
+        // An index that does not fit in 32 bits.
+        match read(&[0x01, 0x08, 0x01, 0x80, 0x80, 0x80, 0x80, 0x10, 0x01, b'f']) {
+            Err(Error::Read(read::Error::Overflow)) => {}
+            otherwise => panic!("Unexpected: {:?}", otherwise),
+        }
+        match read(&[0x00, 0x03, 0x01, b'm', 0x00]) {
+            Err(Error::TrailingBytes(MODULE)) => {}
+            otherwise => panic!("Unexpected: {:?}", otherwise),
+        }
+        match read(&[0x00, 0x02, 0x01, 0xff]) {
+            Err(Error::Read(read::Error::InvalidUtf8(_))) => {}
+            otherwise => panic!("Unexpected: {:?}", otherwise),
+        }
+    }
+
+    #[test]
+    fn test_write_rejects_duplicate_ids() {
+        for other in [
+            vec![(MODULE, vec![0x00])],
+            vec![(7, vec![0x00]), (7, vec![])],
+        ] {
+            let section = NameSection {
+                module: Some("m".to_string()),
+                other,
+                ..Default::default()
+            };
+            let mut buf = vec![];
+            match write(&mut buf, &section) {
+                Err(e) => assert_eq!(e.io_error().kind(), io::ErrorKind::InvalidInput),
+                otherwise => panic!("Unexpected: {:?}", otherwise),
+            }
+            assert!(buf.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_write_rejects_known_ids_in_other() {
+        // Even when the matching field is empty, the payload would be read
+        // back as module, function or local names.
+        for &id in &[MODULE, FUNCTIONS, LOCALS] {
+            let section = NameSection {
+                other: vec![(id, vec![0x00])],
+                ..Default::default()
+            };
+            let mut buf = vec![];
+            match write(&mut buf, &section) {
+                Err(e) => assert_eq!(e.io_error().kind(), io::ErrorKind::InvalidInput),
+                otherwise => panic!("Unexpected: {:?}", otherwise),
+            }
+            assert!(buf.is_empty());
+        }
+    }
+}
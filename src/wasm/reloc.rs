@@ -0,0 +1,480 @@
+//! Parse the `reloc.*` custom sections of WebAssembly object files and apply
+//! their relocations.
+//!
+//! Object files produced by `clang --target=wasm32` leave room for the values
+//! a linker fills in, usually as LEB128 numbers padded to five bytes (ten for
+//! 64-bit memories). Each entry of a `reloc.CODE` or `reloc.DATA` section
+//! names a field by its offset within the target section's payload. `apply`
+//! rewrites such a field in place, keeping its existing width.
+//!
+//! ```
+//! use leb128::reader::Leb128Reader;
+//! use leb128::wasm::reloc::{self, R_WASM_FUNCTION_INDEX_LEB};
+//!
+//! // One function index relocation against offset 4 of section 4.
+//! let payload = [0x04, 0x01, R_WASM_FUNCTION_INDEX_LEB, 0x04, 0x00];
+//! let section = reloc::parse(Leb128Reader::new(&payload)).unwrap();
+//! assert_eq!(section.section_index, 4);
+//!
+//! // `call 0`, with the index padded to five bytes.
+//! let mut code = [0x01, 0x08, 0x00, 0x10, 0x80, 0x80, 0x80, 0x80, 0x00, 0x0b];
+//! reloc::apply(&mut code, &section.entries[0], 300).unwrap();
+//! assert_eq!(code[3..9], [0x10, 0xac, 0x82, 0x80, 0x80, 0x00]);
+//! ```
+
+use super::super::reader::{self, ErrorKind, Leb128Reader};
+use super::super::write;
+use super::{peek_varint32, peek_varint64, peek_varuint32};
+use std::fmt;
+
+/// A function index, as a padded `varuint32`.
+pub const R_WASM_FUNCTION_INDEX_LEB: u8 = 0;
+/// A table index, as a padded `varint32`.
+pub const R_WASM_TABLE_INDEX_SLEB: u8 = 1;
+/// A table index, as a little-endian `u32`.
+pub const R_WASM_TABLE_INDEX_I32: u8 = 2;
+/// A memory address, as a padded `varuint32`.
+pub const R_WASM_MEMORY_ADDR_LEB: u8 = 3;
+/// A memory address, as a padded `varint32`.
+pub const R_WASM_MEMORY_ADDR_SLEB: u8 = 4;
+/// A memory address, as a little-endian `u32`.
+pub const R_WASM_MEMORY_ADDR_I32: u8 = 5;
+/// A type index, as a padded `varuint32`.
+pub const R_WASM_TYPE_INDEX_LEB: u8 = 6;
+/// A global index, as a padded `varuint32`.
+pub const R_WASM_GLOBAL_INDEX_LEB: u8 = 7;
+/// An offset within a function's body, as a little-endian `u32`.
+pub const R_WASM_FUNCTION_OFFSET_I32: u8 = 8;
+/// An offset within a section, as a little-endian `u32`.
+pub const R_WASM_SECTION_OFFSET_I32: u8 = 9;
+/// A tag index, as a padded `varuint32`.
+pub const R_WASM_TAG_INDEX_LEB: u8 = 10;
+/// A memory address relative to `__memory_base`, as a padded `varint32`.
+pub const R_WASM_MEMORY_ADDR_REL_SLEB: u8 = 11;
+/// A table index relative to `__table_base`, as a padded `varint32`.
+pub const R_WASM_TABLE_INDEX_REL_SLEB: u8 = 12;
+/// A global index, as a little-endian `u32`.
+pub const R_WASM_GLOBAL_INDEX_I32: u8 = 13;
+/// A 64-bit memory address, as a padded `varuint64`.
+pub const R_WASM_MEMORY_ADDR_LEB64: u8 = 14;
+/// A 64-bit memory address, as a padded `varint64`.
+pub const R_WASM_MEMORY_ADDR_SLEB64: u8 = 15;
+/// A 64-bit memory address, as a little-endian `u64`.
+pub const R_WASM_MEMORY_ADDR_I64: u8 = 16;
+/// A 64-bit memory address relative to `__memory_base`, as a padded
+/// `varint64`.
+pub const R_WASM_MEMORY_ADDR_REL_SLEB64: u8 = 17;
+/// A table index, as a padded `varint64`.
+pub const R_WASM_TABLE_INDEX_SLEB64: u8 = 18;
+/// A table index, as a little-endian `u64`.
+pub const R_WASM_TABLE_INDEX_I64: u8 = 19;
+/// A table number, as a padded `varuint32`.
+pub const R_WASM_TABLE_NUMBER_LEB: u8 = 20;
+/// A memory address relative to `__tls_base`, as a padded `varint32`.
+pub const R_WASM_MEMORY_ADDR_TLS_SLEB: u8 = 21;
+/// An offset within a function's body, as a little-endian `u64`.
+pub const R_WASM_FUNCTION_OFFSET_I64: u8 = 22;
+/// A memory address relative to the relocated field, as a little-endian
+/// `u32`.
+pub const R_WASM_MEMORY_ADDR_LOCREL_I32: u8 = 23;
+/// A table index relative to `__table_base`, as a padded `varint64`.
+pub const R_WASM_TABLE_INDEX_REL_SLEB64: u8 = 24;
+/// A 64-bit memory address relative to `__tls_base`, as a padded `varint64`.
+pub const R_WASM_MEMORY_ADDR_TLS_SLEB64: u8 = 25;
+/// A function index, as a little-endian `u32`.
+pub const R_WASM_FUNCTION_INDEX_I32: u8 = 26;
+
+/// An error that occurred while parsing or applying relocations.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// An entry, or the field it relocates, could not be read.
+    Read(reader::Error),
+    /// A relocation type was not recognized. Carries the offset and type.
+    UnknownType(usize, u8),
+    /// A value does not fit in the field it relocates. Carries the offset of
+    /// the field and the value.
+    ValueOutOfRange(usize, i64),
+}
+
+impl From<reader::Error> for Error {
+    fn from(e: reader::Error) -> Self {
+        Error::Read(e)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match *self {
+            Error::Read(ref e) => write!(f, "leb128::wasm::reloc::Error: {}", e),
+            Error::UnknownType(offset, ty) => write!(
+                f,
+                "leb128::wasm::reloc::Error: Unknown relocation type {} at offset {:#x}",
+                ty, offset
+            ),
+            Error::ValueOutOfRange(offset, val) => write!(
+                f,
+                "leb128::wasm::reloc::Error: Value {} does not fit the field at offset {:#x}",
+                val, offset
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match *self {
+            Error::Read(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// How a relocated field is encoded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Field {
+    /// An unsigned LEB128 number holding 32 bits.
+    Uleb32,
+    /// A signed LEB128 number holding 32 bits.
+    Sleb32,
+    /// A little-endian 32-bit integer.
+    I32,
+    /// An unsigned LEB128 number holding 64 bits.
+    Uleb64,
+    /// A signed LEB128 number holding 64 bits.
+    Sleb64,
+    /// A little-endian 64-bit integer.
+    I64,
+}
+
+/// Look up how the field relocated by a relocation of type `ty` is encoded,
+/// and whether the relocation entry carries an addend. Returns `None` for
+/// unknown types.
+fn describe(ty: u8) -> Option<(Field, bool)> {
+    Some(match ty {
+        R_WASM_FUNCTION_INDEX_LEB
+        | R_WASM_TYPE_INDEX_LEB
+        | R_WASM_GLOBAL_INDEX_LEB
+        | R_WASM_TAG_INDEX_LEB
+        | R_WASM_TABLE_NUMBER_LEB => (Field::Uleb32, false),
+        R_WASM_MEMORY_ADDR_LEB => (Field::Uleb32, true),
+        R_WASM_TABLE_INDEX_SLEB | R_WASM_TABLE_INDEX_REL_SLEB => (Field::Sleb32, false),
+        R_WASM_MEMORY_ADDR_SLEB | R_WASM_MEMORY_ADDR_REL_SLEB | R_WASM_MEMORY_ADDR_TLS_SLEB => {
+            (Field::Sleb32, true)
+        }
+        R_WASM_TABLE_INDEX_I32 | R_WASM_GLOBAL_INDEX_I32 | R_WASM_FUNCTION_INDEX_I32 => {
+            (Field::I32, false)
+        }
+        R_WASM_MEMORY_ADDR_I32
+        | R_WASM_FUNCTION_OFFSET_I32
+        | R_WASM_SECTION_OFFSET_I32
+        | R_WASM_MEMORY_ADDR_LOCREL_I32 => (Field::I32, true),
+        R_WASM_MEMORY_ADDR_LEB64 => (Field::Uleb64, true),
+        R_WASM_TABLE_INDEX_SLEB64 | R_WASM_TABLE_INDEX_REL_SLEB64 => (Field::Sleb64, false),
+        R_WASM_MEMORY_ADDR_SLEB64
+        | R_WASM_MEMORY_ADDR_REL_SLEB64
+        | R_WASM_MEMORY_ADDR_TLS_SLEB64 => (Field::Sleb64, true),
+        R_WASM_TABLE_INDEX_I64 => (Field::I64, false),
+        R_WASM_MEMORY_ADDR_I64 | R_WASM_FUNCTION_OFFSET_I64 => (Field::I64, true),
+        _ => return None,
+    })
+}
+
+/// A relocation entry.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Relocation {
+    /// The relocation type, one of the `R_WASM_*` constants.
+    pub ty: u8,
+    /// The offset of the relocated field within the target section's
+    /// payload.
+    pub offset: u32,
+    /// The index of the symbol, or of the type for `R_WASM_TYPE_INDEX_LEB`.
+    pub index: u32,
+    /// The addend for memory address and offset relocations, or zero.
+    pub addend: i64,
+}
+
+impl Relocation {
+    /// How the relocated field is encoded, or `None` for unknown types.
+    pub fn field(&self) -> Option<Field> {
+        describe(self.ty).map(|(field, _)| field)
+    }
+}
+
+/// The parsed payload of a `reloc.*` custom section.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RelocSection {
+    /// The index of the section the relocations apply to.
+    pub section_index: u32,
+    /// The relocation entries.
+    pub entries: Vec<Relocation>,
+}
+
+/// Parse the payload of a `reloc.*` custom section read by `reader`, such as
+/// the one returned by `sections::Section::reader`.
+pub fn parse(mut reader: Leb128Reader) -> Result<RelocSection, Error> {
+    let section_index = reader.read_with(peek_varuint32)?;
+    let count_offset = reader.offset();
+    let count = reader.read_with(peek_varuint32)?;
+    // Every entry takes at least three bytes.
+    if count as usize > reader.remaining() / 3 {
+        return Err(reader::Error::new(count_offset, ErrorKind::UnexpectedEof).into());
+    }
+
+    let mut entries = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        let ty_offset = reader.offset();
+        let ty = reader.read_u8()?;
+        let (field, has_addend) = describe(ty).ok_or(Error::UnknownType(ty_offset, ty))?;
+        let offset = reader.read_with(peek_varuint32)?;
+        let index = reader.read_with(peek_varuint32)?;
+        let addend = match (has_addend, field) {
+            (false, _) => 0,
+            (true, Field::Uleb64) | (true, Field::Sleb64) | (true, Field::I64) => {
+                reader.read_with(peek_varint64)?
+            }
+            (true, _) => i64::from(reader.read_with(peek_varint32)?),
+        };
+        entries.push(Relocation {
+            ty,
+            offset,
+            index,
+            addend,
+        });
+    }
+
+    Ok(RelocSection {
+        section_index,
+        entries,
+    })
+}
+
+/// Store `val` in the field relocated by `reloc`, within `payload`, the
+/// payload of the relocation's target section.
+///
+/// LEB128 fields keep the width they already have, which is usually five
+/// bytes for 32-bit fields and ten for 64-bit fields. Values that do not fit
+/// in the field's width or type are rejected without modifying `payload`.
+/// Computing `val` from the symbol and addend is up to the caller.
+pub fn apply(payload: &mut [u8], reloc: &Relocation, val: i64) -> Result<(), Error> {
+    let offset = reloc.offset as usize;
+    let field = reloc.field().ok_or(Error::UnknownType(offset, reloc.ty))?;
+    let in_range = match field {
+        Field::Uleb32 => (0..=i64::from(u32::MAX)).contains(&val),
+        Field::Sleb32 => (i64::from(i32::MIN)..=i64::from(i32::MAX)).contains(&val),
+        Field::I32 => (i64::from(i32::MIN)..=i64::from(u32::MAX)).contains(&val),
+        Field::Uleb64 => val >= 0,
+        Field::Sleb64 | Field::I64 => true,
+    };
+    if !in_range {
+        return Err(Error::ValueOutOfRange(offset, val));
+    }
+
+    // Find the width of the existing field.
+    let mut reader = Leb128Reader::new(payload);
+    reader.seek(offset)?;
+    match field {
+        Field::Uleb32 | Field::Uleb64 => {
+            reader.read_uleb128()?;
+        }
+        Field::Sleb32 | Field::Sleb64 => {
+            reader.read_sleb128()?;
+        }
+        Field::I32 => reader.skip(4)?,
+        Field::I64 => reader.skip(8)?,
+    }
+    let width = reader.offset() - offset;
+
+    let mut dest = &mut payload[offset..offset + width];
+    let result = match field {
+        Field::Uleb32 | Field::Uleb64 => write::unsigned_padded(&mut dest, val as u64, width),
+        Field::Sleb32 | Field::Sleb64 => write::signed_padded(&mut dest, val, width),
+        Field::I32 => {
+            dest.copy_from_slice(&(val as u32).to_le_bytes());
+            Ok(width)
+        }
+        Field::I64 => {
+            dest.copy_from_slice(&val.to_le_bytes());
+            Ok(width)
+        }
+    };
+    result
+        .map(|_| ())
+        .map_err(|_| Error::ValueOutOfRange(offset, val))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        #[rustfmt::skip]
+        let payload = [
+            0x03, 0x04,
+            R_WASM_FUNCTION_INDEX_LEB, 0x01, 0x02,
+            R_WASM_MEMORY_ADDR_SLEB, 0x81, 0x01, 0x00, 0x7c,
+            R_WASM_TABLE_INDEX_I32, 0x20, 0x05,
+            R_WASM_MEMORY_ADDR_I64, 0x30, 0x06, 0x80, 0x80, 0x80, 0x80, 0x80, 0x01,
+        ];
+        let section = parse(Leb128Reader::with_offset(&payload, 0x40)).unwrap();
+        assert_eq!(section.section_index, 3);
+        assert_eq!(
+            section.entries,
+            vec![
+                Relocation {
+                    ty: R_WASM_FUNCTION_INDEX_LEB,
+                    offset: 1,
+                    index: 2,
+                    addend: 0,
+                },
+                Relocation {
+                    ty: R_WASM_MEMORY_ADDR_SLEB,
+                    offset: 0x81,
+                    index: 0,
+                    addend: -4,
+                },
+                Relocation {
+                    ty: R_WASM_TABLE_INDEX_I32,
+                    offset: 0x20,
+                    index: 5,
+                    addend: 0,
+                },
+                Relocation {
+                    ty: R_WASM_MEMORY_ADDR_I64,
+                    offset: 0x30,
+                    index: 6,
+                    addend: 1 << 35,
+                },
+            ]
+        );
+        assert_eq!(section.entries[1].field(), Some(Field::Sleb32));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(
+            parse(Leb128Reader::with_offset(
+                &[0x00, 0x01, 0x7f, 0x00, 0x00],
+                0x10
+            )),
+            Err(Error::UnknownType(0x12, 0x7f))
+        );
+        assert_eq!(
+            parse(Leb128Reader::with_offset(
+                &[0x00, 0x02, 0x00, 0x00, 0x00],
+                0x10
+            )),
+            Err(Error::Read(reader::Error::new(
+                0x11,
+                ErrorKind::UnexpectedEof
+            )))
+        );
+        // An addend that takes six bytes.
+        let payload = [
+            0x00,
+            0x01,
+            R_WASM_MEMORY_ADDR_LEB,
+            0x00,
+            0x00,
+            0x80,
+            0x80,
+            0x80,
+            0x80,
+            0x80,
+            0x00,
+        ];
+        assert_eq!(
+            parse(Leb128Reader::new(&payload)),
+            Err(Error::Read(reader::Error::new(5, ErrorKind::Overflow)))
+        );
+    }
+
+    fn reloc(ty: u8, offset: u32) -> Relocation {
+        Relocation {
+            ty,
+            offset,
+            index: 0,
+            addend: 0,
+        }
+    }
+
+    #[test]
+    fn test_apply_keeps_width() {
+        let mut payload = [0xaa, 0x80, 0x80, 0x80, 0x80, 0x00, 0xbb];
+        apply(&mut payload, &reloc(R_WASM_FUNCTION_INDEX_LEB, 1), 3).unwrap();
+        assert_eq!(payload, [0xaa, 0x83, 0x80, 0x80, 0x80, 0x00, 0xbb]);
+
+        apply(&mut payload, &reloc(R_WASM_MEMORY_ADDR_SLEB, 1), -2).unwrap();
+        assert_eq!(payload, [0xaa, 0xfe, 0xff, 0xff, 0xff, 0x7f, 0xbb]);
+
+        // A field that was not padded still only fits small values.
+        let mut payload = [0x00, 0x0b];
+        apply(&mut payload, &reloc(R_WASM_GLOBAL_INDEX_LEB, 0), 0x7f).unwrap();
+        assert_eq!(payload, [0x7f, 0x0b]);
+        assert_eq!(
+            apply(&mut payload, &reloc(R_WASM_GLOBAL_INDEX_LEB, 0), 0x80),
+            Err(Error::ValueOutOfRange(0, 0x80))
+        );
+        assert_eq!(payload, [0x7f, 0x0b]);
+
+        let mut payload = [0; 10];
+        apply(&mut payload, &reloc(R_WASM_MEMORY_ADDR_I32, 1), 0x1234_5678).unwrap();
+        apply(&mut payload, &reloc(R_WASM_FUNCTION_INDEX_I32, 6), -1).unwrap();
+        assert_eq!(
+            payload,
+            [0x00, 0x78, 0x56, 0x34, 0x12, 0x00, 0xff, 0xff, 0xff, 0xff]
+        );
+    }
+
+    #[test]
+    fn test_apply_64_bit() {
+        let mut payload = [0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x00];
+        apply(&mut payload, &reloc(R_WASM_MEMORY_ADDR_LEB64, 0), 1 << 40).unwrap();
+        let mut expected = vec![];
+        write::unsigned_padded(&mut expected, 1 << 40, 10).unwrap();
+        assert_eq!(payload[..], expected[..]);
+
+        apply(&mut payload, &reloc(R_WASM_MEMORY_ADDR_SLEB64, 0), i64::MIN).unwrap();
+        let mut expected = vec![];
+        write::signed_padded(&mut expected, i64::MIN, 10).unwrap();
+        assert_eq!(payload[..], expected[..]);
+
+        let mut payload = [0; 8];
+        apply(&mut payload, &reloc(R_WASM_TABLE_INDEX_I64, 0), 0x0102).unwrap();
+        assert_eq!(payload, [0x02, 0x01, 0, 0, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_apply_errors() {
+        let mut payload = [0x80, 0x80, 0x80, 0x80, 0x00];
+        for &(ty, val) in &[
+            (R_WASM_FUNCTION_INDEX_LEB, -1),
+            (R_WASM_FUNCTION_INDEX_LEB, 1 << 32),
+            (R_WASM_MEMORY_ADDR_SLEB, 1 << 31),
+            (R_WASM_MEMORY_ADDR_I32, 1 << 32),
+        ] {
+            assert_eq!(
+                apply(&mut payload, &reloc(ty, 0), val),
+                Err(Error::ValueOutOfRange(0, val))
+            );
+        }
+        assert_eq!(payload, [0x80, 0x80, 0x80, 0x80, 0x00]);
+
+        assert_eq!(
+            apply(&mut [0x80, 0x80], &reloc(R_WASM_FUNCTION_INDEX_LEB, 0), 0),
+            Err(Error::Read(reader::Error::new(0, ErrorKind::UnexpectedEof)))
+        );
+        assert_eq!(
+            apply(&mut payload, &reloc(R_WASM_TABLE_INDEX_I64, 0), 0),
+            Err(Error::Read(reader::Error::new(0, ErrorKind::UnexpectedEof)))
+        );
+        assert_eq!(
+            apply(&mut payload, &reloc(0x7f, 0), 0),
+            Err(Error::UnknownType(0, 0x7f))
+        );
+        assert_eq!(
+            apply(&mut payload, &reloc(R_WASM_FUNCTION_INDEX_LEB, 9), 0),
+            Err(Error::Read(reader::Error::new(9, ErrorKind::UnexpectedEof)))
+        );
+    }
+}
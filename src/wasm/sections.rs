@@ -0,0 +1,303 @@
+//! Split a WebAssembly module into its sections.
+//!
+//! A module is the `\0asm` magic number and a version, followed by sections,
+//! each a one-byte id, a `varuint32` payload size and the payload. Custom
+//! sections start their payload with a name.
+//!
+//! ```
+//! use leb128::wasm::sections::{self, Sections};
+//!
+//! let module = b"\0asm\x01\0\0\0\x01\x04\x01\x60\0\0\0\x07\x04name\x02\0";
+//! let mut iter = Sections::new(module).unwrap();
+//!
+//! let types = iter.next().unwrap().unwrap();
+//! assert_eq!(types.id, sections::TYPE);
+//! assert_eq!(types.payload, [0x01, 0x60, 0x00, 0x00]);
+//! assert_eq!(types.offset, 10);
+//!
+//! let names = iter.next().unwrap().unwrap();
+//! assert_eq!(names.name, Some("name"));
+//! assert_eq!(names.payload, [0x02, 0x00]);
+//!
+//! assert!(iter.next().is_none());
+//! ```
+
+use super::super::reader::{self, ErrorKind, Leb128Reader};
+use super::{peek_name, peek_varuint32};
+use std::fmt;
+
+/// The magic number at the start of every module.
+pub const MAGIC: &[u8; 4] = b"\0asm";
+/// The binary format version this reader understands.
+pub const VERSION: u32 = 1;
+
+/// The id of custom sections.
+pub const CUSTOM: u8 = 0;
+/// The id of the type section.
+pub const TYPE: u8 = 1;
+/// The id of the import section.
+pub const IMPORT: u8 = 2;
+/// The id of the function section.
+pub const FUNCTION: u8 = 3;
+/// The id of the table section.
+pub const TABLE: u8 = 4;
+/// The id of the memory section.
+pub const MEMORY: u8 = 5;
+/// The id of the global section.
+pub const GLOBAL: u8 = 6;
+/// The id of the export section.
+pub const EXPORT: u8 = 7;
+/// The id of the start section.
+pub const START: u8 = 8;
+/// The id of the element section.
+pub const ELEMENT: u8 = 9;
+/// The id of the code section.
+pub const CODE: u8 = 10;
+/// The id of the data section.
+pub const DATA: u8 = 11;
+/// The id of the data count section.
+pub const DATA_COUNT: u8 = 12;
+
+/// An error that occurred while splitting a module into sections.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// A section header or custom section name could not be read.
+    Read(reader::Error),
+    /// The module did not start with `MAGIC`.
+    BadMagic,
+    /// The module's version was not `VERSION`. Carries the version.
+    UnsupportedVersion(u32),
+}
+
+impl From<reader::Error> for Error {
+    fn from(e: reader::Error) -> Self {
+        Error::Read(e)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match *self {
+            Error::Read(ref e) => write!(f, "leb128::wasm::sections::Error: {}", e),
+            Error::BadMagic => write!(
+                f,
+                "leb128::wasm::sections::Error: Missing WebAssembly magic number"
+            ),
+            Error::UnsupportedVersion(version) => write!(
+                f,
+                "leb128::wasm::sections::Error: Unsupported version {}",
+                version
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match *self {
+            Error::Read(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// A section of a module, borrowed from the module's bytes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Section<'a> {
+    /// The section's id.
+    pub id: u8,
+    /// The name of a custom section, or `None` for other sections.
+    pub name: Option<&'a str>,
+    /// The offset of the payload within the module.
+    pub offset: usize,
+    /// The section's payload, after the name for custom sections.
+    pub payload: &'a [u8],
+}
+
+impl<'a> Section<'a> {
+    /// A reader over the payload that reports offsets within the module.
+    pub fn reader(&self) -> Leb128Reader<'a> {
+        Leb128Reader::with_offset(self.payload, self.offset)
+    }
+}
+
+/// An iterator over the sections of a module.
+///
+/// Iteration stops after the first error.
+#[derive(Clone, Debug)]
+pub struct Sections<'a> {
+    reader: Leb128Reader<'a>,
+    failed: bool,
+}
+
+impl<'a> Sections<'a> {
+    /// Check the header of the module in `module` and iterate over its
+    /// sections.
+    pub fn new(module: &'a [u8]) -> Result<Sections<'a>, Error> {
+        let mut reader = Leb128Reader::new(module);
+        if reader.read_bytes(MAGIC.len())? != MAGIC {
+            return Err(Error::BadMagic);
+        }
+        let mut version = [0; 4];
+        version.copy_from_slice(reader.read_bytes(4)?);
+        let version = u32::from_le_bytes(version);
+        if version != VERSION {
+            return Err(Error::UnsupportedVersion(version));
+        }
+
+        Ok(Sections {
+            reader,
+            failed: false,
+        })
+    }
+
+    /// The offset of the next section within the module.
+    pub fn offset(&self) -> usize {
+        self.reader.offset()
+    }
+
+    fn read_section(&mut self) -> Result<Section<'a>, Error> {
+        let id = self.reader.read_u8()?;
+        let size_offset = self.reader.offset();
+        let size = self.reader.read_with(peek_varuint32)?;
+        if size as usize > self.reader.remaining() {
+            return Err(reader::Error::new(size_offset, ErrorKind::UnexpectedEof).into());
+        }
+
+        let mut payload = self.reader.sub_reader(size as usize)?;
+        let name = if id == CUSTOM {
+            Some(payload.read_with(peek_name)?)
+        } else {
+            None
+        };
+
+        Ok(Section {
+            id,
+            name,
+            offset: payload.offset(),
+            payload: payload.rest(),
+        })
+    }
+}
+
+impl<'a> Iterator for Sections<'a> {
+    type Item = Result<Section<'a>, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed || self.reader.is_empty() {
+            return None;
+        }
+        let result = self.read_section();
+        self.failed = result.is_err();
+        Some(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEADER: &[u8] = b"\0asm\x01\0\0\0";
+
+    fn module(sections: &[u8]) -> Vec<u8> {
+        let mut module = HEADER.to_vec();
+        module.extend_from_slice(sections);
+        module
+    }
+
+    #[test]
+    fn test_sections() {
+        #[rustfmt::skip]
+        let module = module(&[
+            // A type section with one `[] -> []` function type.
+            0x01, 0x04, 0x01, 0x60, 0x00, 0x00,
+            // A function section, with a padded size.
+            0x03, 0x82, 0x80, 0x00, 0x01, 0x00,
+            // An empty custom section.
+            0x00, 0x05, 0x04, b'e', b'm', b'p', b't',
+            // A code section with one empty body.
+            0x0a, 0x04, 0x01, 0x02, 0x00, 0x0b,
+        ]);
+        let sections: Vec<Section> = Sections::new(&module)
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(
+            sections,
+            vec![
+                Section {
+                    id: TYPE,
+                    name: None,
+                    offset: 10,
+                    payload: &[0x01, 0x60, 0x00, 0x00],
+                },
+                Section {
+                    id: FUNCTION,
+                    name: None,
+                    offset: 18,
+                    payload: &[0x01, 0x00],
+                },
+                Section {
+                    id: CUSTOM,
+                    name: Some("empt"),
+                    offset: 27,
+                    payload: &[],
+                },
+                Section {
+                    id: CODE,
+                    name: None,
+                    offset: 29,
+                    payload: &[0x01, 0x02, 0x00, 0x0b],
+                },
+            ]
+        );
+        assert_eq!(sections[3].reader().offset(), 29);
+        assert!(Sections::new(HEADER).unwrap().next().is_none());
+    }
+
+    #[test]
+    fn test_header_errors() {
+        assert_eq!(
+            Sections::new(b"\0asn\x01\0\0\0").unwrap_err(),
+            Error::BadMagic
+        );
+        assert_eq!(
+            Sections::new(b"\0asm\x02\0\0\0").unwrap_err(),
+            Error::UnsupportedVersion(2)
+        );
+        assert_eq!(
+            Sections::new(b"\0asm\x01").unwrap_err(),
+            Error::Read(reader::Error::new(4, ErrorKind::UnexpectedEof))
+        );
+    }
+
+    #[test]
+    fn test_section_errors() {
+        for &(sections, offset, kind) in &[
+            // A size that takes more than five bytes.
+            (
+                &[0x01, 0x80, 0x80, 0x80, 0x80, 0x80, 0x00][..],
+                9,
+                ErrorKind::Overflow,
+            ),
+            // A size with bits set above 32.
+            (
+                &[0x01, 0x80, 0x80, 0x80, 0x80, 0x10],
+                9,
+                ErrorKind::Overflow,
+            ),
+            // A size larger than the rest of the module.
+            (&[0x01, 0x02, 0x00], 9, ErrorKind::UnexpectedEof),
+            // A custom section whose name is not UTF-8.
+            (&[0x00, 0x02, 0x01, 0xff], 10, ErrorKind::InvalidUtf8),
+        ] {
+            let module = module(sections);
+            let mut iter = Sections::new(&module).unwrap();
+            assert_eq!(
+                iter.next(),
+                Some(Err(Error::Read(reader::Error::new(offset, kind))))
+            );
+            assert_eq!(iter.next(), None);
+        }
+    }
+}
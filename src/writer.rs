@@ -0,0 +1,310 @@
+//! A growable buffer for writing LEB128 encoded numbers, with support for
+//! length-prefixed regions whose size is filled in once their contents have
+//! been written.
+
+use super::write;
+
+/// How the size of a region opened with `Leb128Writer::begin_sized` is
+/// encoded.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SizeEncoding {
+    /// Encode the size in as few bytes as possible. The region's contents are
+    /// shifted along to make room for the size once it is known.
+    #[default]
+    Minimal,
+    /// Reserve a placeholder of the given number of bytes, from 1 to 10, when
+    /// the region is opened, and patch the size into it with a padded encoding
+    /// once it is known. Contents are never moved, but regions whose size does
+    /// not fit in the placeholder cannot be closed.
+    Padded(usize),
+}
+
+/// A region opened by `begin_sized` that has not yet been closed.
+#[derive(Clone, Copy, Debug)]
+struct Region {
+    /// Where the size goes.
+    size_offset: usize,
+    /// Where the contents begin.
+    contents_offset: usize,
+}
+
+/// Writes LEB128 encoded numbers and raw bytes into a `Vec<u8>`, and supports
+/// nested regions prefixed by their unsigned LEB128 encoded size.
+///
+/// ```
+/// use leb128::writer::Leb128Writer;
+///
+/// let mut writer = Leb128Writer::new();
+/// writer.write_u8(0x01);
+/// writer.begin_sized();
+/// writer.write_uleb128(624485);
+/// writer.write_sleb128(-1);
+/// writer.end_sized().unwrap();
+/// assert_eq!(writer.into_inner(), [0x01, 0x04, 0xe5, 0x8e, 0x26, 0x7f]);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct Leb128Writer {
+    buf: Vec<u8>,
+    size_encoding: SizeEncoding,
+    regions: Vec<Region>,
+}
+
+impl Leb128Writer {
+    /// Create a new, empty writer that encodes region sizes minimally.
+    pub fn new() -> Leb128Writer {
+        Leb128Writer::default()
+    }
+
+    /// Create a new, empty writer that encodes region sizes as described by
+    /// `size_encoding`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `size_encoding` is `SizeEncoding::Padded` with a width
+    /// outside 1 to 10, which no size could ever be patched into.
+    pub fn with_size_encoding(size_encoding: SizeEncoding) -> Leb128Writer {
+        if let SizeEncoding::Padded(width) = size_encoding {
+            assert!(
+                (1..=10).contains(&width),
+                "Leb128Writer::with_size_encoding called with a padded width of {}",
+                width
+            );
+        }
+        Leb128Writer {
+            size_encoding,
+            ..Leb128Writer::default()
+        }
+    }
+
+    /// The number of bytes written so far, including any placeholders.
+    pub fn len(&self) -> usize {
+        self.buf.len()
+    }
+
+    /// Returns `true` if nothing has been written yet.
+    pub fn is_empty(&self) -> bool {
+        self.buf.is_empty()
+    }
+
+    /// The bytes written so far.
+    pub fn as_slice(&self) -> &[u8] {
+        &self.buf
+    }
+
+    /// The number of regions that have been opened but not yet closed.
+    pub fn open_regions(&self) -> usize {
+        self.regions.len()
+    }
+
+    /// Consume the writer and return the bytes written.
+    ///
+    /// # Panics
+    ///
+    /// Panics if any region opened with `begin_sized` is still open.
+    pub fn into_inner(self) -> Vec<u8> {
+        assert!(
+            self.regions.is_empty(),
+            "Leb128Writer::into_inner called with {} unclosed regions",
+            self.regions.len()
+        );
+        self.buf
+    }
+
+    /// Write a single byte.
+    pub fn write_u8(&mut self, byte: u8) {
+        self.buf.push(byte);
+    }
+
+    /// Write raw bytes.
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        self.buf.extend_from_slice(bytes);
+    }
+
+    /// Write an unsigned LEB128 number, returning the number of bytes used.
+    pub fn write_uleb128(&mut self, val: u64) -> usize {
+        write::unsigned(&mut self.buf, val).expect("writing to a Vec cannot fail")
+    }
+
+    /// Write a signed LEB128 number, returning the number of bytes used.
+    pub fn write_sleb128(&mut self, val: i64) -> usize {
+        write::signed(&mut self.buf, val).expect("writing to a Vec cannot fail")
+    }
+
+    /// Write a byte string prefixed by its unsigned LEB128 encoded length,
+    /// returning the total number of bytes used.
+    pub fn write_prefixed_bytes(&mut self, bytes: &[u8]) -> usize {
+        write::bytes(&mut self.buf, bytes).expect("writing to a Vec cannot fail")
+    }
+
+    /// Write a string prefixed by its unsigned LEB128 encoded length in bytes,
+    /// returning the total number of bytes used.
+    pub fn write_prefixed_str(&mut self, s: &str) -> usize {
+        write::str(&mut self.buf, s).expect("writing to a Vec cannot fail")
+    }
+
+    /// Open a region whose unsigned LEB128 encoded size will precede its
+    /// contents. Everything written until the matching `end_sized` call is
+    /// part of the region. Regions may be nested.
+    pub fn begin_sized(&mut self) {
+        let size_offset = self.buf.len();
+        if let SizeEncoding::Padded(width) = self.size_encoding {
+            self.buf.resize(size_offset + width, 0);
+        }
+        self.regions.push(Region {
+            size_offset,
+            contents_offset: self.buf.len(),
+        });
+    }
+
+    /// Close the most recently opened region, writing its size in front of
+    /// its contents. Returns the size of the region's contents.
+    ///
+    /// With `SizeEncoding::Padded`, this fails if the size does not fit in the
+    /// reserved placeholder. The region is left open in that case.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there is no open region.
+    pub fn end_sized(&mut self) -> Result<usize, write::Error> {
+        let region = *self
+            .regions
+            .last()
+            .expect("Leb128Writer::end_sized called without a matching begin_sized");
+        let size = self.buf.len() - region.contents_offset;
+
+        match self.size_encoding {
+            SizeEncoding::Minimal => {
+                let mut encoded = [0; 10];
+                let len = write::unsigned(&mut &mut encoded[..], size as u64)?;
+                self.buf.splice(
+                    region.size_offset..region.size_offset,
+                    encoded[..len].iter().cloned(),
+                );
+            }
+            SizeEncoding::Padded(width) => {
+                let mut placeholder = &mut self.buf[region.size_offset..region.contents_offset];
+                write::unsigned_padded(&mut placeholder, size as u64, width)?;
+            }
+        }
+
+        self.regions.pop();
+        Ok(size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use read;
+    use std::io;
+
+    #[test]
+    fn test_minimal() {
+        let mut writer = Leb128Writer::new();
+        writer.begin_sized();
+        writer.write_bytes(&[0xaa; 200]);
+        assert_eq!(writer.end_sized().unwrap(), 200);
+
+        let bytes = writer.into_inner();
+        assert_eq!(bytes.len(), 202);
+        assert_eq!(&bytes[..2], &[0xc8, 0x01]);
+        assert_eq!(&bytes[2..], &[0xaa; 200][..]);
+    }
+
+    #[test]
+    fn test_prefixed() {
+        let mut writer = Leb128Writer::new();
+        assert_eq!(writer.write_prefixed_bytes(&[0xaa]), 2);
+        assert_eq!(writer.write_prefixed_str("hi"), 3);
+        assert_eq!(writer.into_inner(), [0x01, 0xaa, 0x02, b'h', b'i']);
+    }
+
+    #[test]
+    fn test_padded() {
+        let mut writer = Leb128Writer::with_size_encoding(SizeEncoding::Padded(5));
+        writer.write_u8(0xff);
+        writer.begin_sized();
+        writer.write_uleb128(128);
+        writer.begin_sized();
+        writer.end_sized().unwrap();
+        assert_eq!(writer.end_sized().unwrap(), 7);
+
+        assert_eq!(
+            writer.into_inner(),
+            [0xff, 0x87, 0x80, 0x80, 0x80, 0x00, 0x80, 0x01, 0x80, 0x80, 0x80, 0x80, 0x00]
+        );
+    }
+
+    #[test]
+    fn test_padded_too_small() {
+        let mut writer = Leb128Writer::with_size_encoding(SizeEncoding::Padded(1));
+        writer.begin_sized();
+        writer.write_bytes(&[0; 128]);
+        let err = writer.end_sized().unwrap_err();
+        assert_eq!(err.io_error().kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(writer.open_regions(), 1);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_padded_zero_width() {
+        Leb128Writer::with_size_encoding(SizeEncoding::Padded(0));
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_padded_too_wide() {
+        Leb128Writer::with_size_encoding(SizeEncoding::Padded(11));
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_unbalanced_end() {
+        Leb128Writer::new().end_sized().unwrap();
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_unclosed_region() {
+        let mut writer = Leb128Writer::new();
+        writer.begin_sized();
+        writer.into_inner();
+    }
+
+    // Nest `depth` regions, each holding a marker byte, a filler large enough
+    // to make sizes cross the one-byte boundary, and the next region. Then
+    // walk back down through the regions checking every size.
+    fn check_deeply_nested(size_encoding: SizeEncoding) {
+        let depth = 100;
+        let mut writer = Leb128Writer::with_size_encoding(size_encoding);
+        for i in 0..depth {
+            writer.begin_sized();
+            writer.write_u8(i as u8);
+            writer.write_bytes(&[0xee; 3]);
+        }
+        for _ in 0..depth {
+            writer.end_sized().unwrap();
+        }
+        let bytes = writer.into_inner();
+
+        let mut rest = &bytes[..];
+        for i in 0..depth {
+            let size = read::unsigned(&mut rest).unwrap() as usize;
+            assert_eq!(size, rest.len());
+            assert_eq!(rest[0], i as u8);
+            assert_eq!(&rest[1..4], &[0xee; 3]);
+            rest = &rest[4..];
+        }
+        assert!(rest.is_empty());
+    }
+
+    #[test]
+    fn test_deeply_nested_minimal() {
+        check_deeply_nested(SizeEncoding::Minimal);
+    }
+
+    #[test]
+    fn test_deeply_nested_padded() {
+        check_deeply_nested(SizeEncoding::Padded(3));
+    }
+}
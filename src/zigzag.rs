@@ -0,0 +1,132 @@
+//! ZigZag mapping of signed integers onto unsigned integers, as used by
+//! Protocol Buffers' `sint32`/`sint64`, Avro, Thrift's compact protocol and
+//! Go's `binary.Varint`.
+//!
+//! ZigZag interleaves negative and non-negative numbers (0, -1, 1, -2, 2, ...
+//! map to 0, 1, 2, 3, 4, ...) so that numbers with a small magnitude have a
+//! short unsigned LEB128 encoding regardless of their sign. Use
+//! `read::zigzag` and `write::zigzag` to read and write them.
+//!
+//! ```
+//! let mut buf = vec![];
+//! leb128::write::zigzag(&mut buf, -3i32).unwrap();
+//! assert_eq!(buf, [0x05]);
+//!
+//! let val: i32 = leb128::read::zigzag(&mut &buf[..]).unwrap();
+//! assert_eq!(val, -3);
+//! ```
+
+use std::convert::TryFrom;
+
+/// Map a signed number onto an unsigned number with ZigZag encoding.
+///
+/// ```
+/// assert_eq!(leb128::zigzag::zigzag_encode(-1), 1);
+/// assert_eq!(leb128::zigzag::zigzag_encode(1), 2);
+/// ```
+#[inline]
+pub fn zigzag_encode(val: i64) -> u64 {
+    ((val << 1) ^ (val >> 63)) as u64
+}
+
+/// Map an unsigned number produced by `zigzag_encode` back onto the signed
+/// number it came from.
+///
+/// ```
+/// assert_eq!(leb128::zigzag::zigzag_decode(3), -2);
+/// assert_eq!(leb128::zigzag::zigzag_decode(4), 2);
+/// ```
+#[inline]
+pub fn zigzag_decode(val: u64) -> i64 {
+    ((val >> 1) as i64) ^ -((val & 1) as i64)
+}
+
+/// A signed integer type that can be read and written with ZigZag encoding.
+///
+/// The ZigZag encoding of a narrow integer is the same as the ZigZag encoding
+/// of that integer widened to `i64`, so every width shares the 64-bit
+/// implementation and only differs in which values it accepts when reading.
+pub trait ZigZag: Copy {
+    /// Widen this number to an `i64`.
+    fn to_i64(self) -> i64;
+
+    /// Narrow an `i64` to this type, or return `None` if it does not fit.
+    fn from_i64(val: i64) -> Option<Self>;
+}
+
+macro_rules! impl_zigzag {
+    ( $( $ty:ty ),* ) => {
+        $(
+            impl ZigZag for $ty {
+                #[inline]
+                fn to_i64(self) -> i64 {
+                    self as i64
+                }
+
+                #[inline]
+                fn from_i64(val: i64) -> Option<Self> {
+                    <$ty>::try_from(val).ok()
+                }
+            }
+        )*
+    }
+}
+
+impl_zigzag!(i8, i16, i32, i64, isize);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use read;
+    use write;
+
+    #[test]
+    fn test_zigzag_encode() {
+        assert_eq!(zigzag_encode(0), 0);
+        assert_eq!(zigzag_encode(-1), 1);
+        assert_eq!(zigzag_encode(1), 2);
+        assert_eq!(zigzag_encode(-2), 3);
+        assert_eq!(zigzag_encode(2147483647), 4294967294);
+        assert_eq!(zigzag_encode(-2147483648), 4294967295);
+        assert_eq!(zigzag_encode(i64::MAX), u64::MAX - 1);
+        assert_eq!(zigzag_encode(i64::MIN), u64::MAX);
+    }
+
+    #[test]
+    fn test_zigzag_decode() {
+        for i in -1025..1025 {
+            assert_eq!(zigzag_decode(zigzag_encode(i)), i);
+        }
+        assert_eq!(zigzag_decode(u64::MAX - 1), i64::MAX);
+        assert_eq!(zigzag_decode(u64::MAX), i64::MIN);
+    }
+
+    #[test]
+    fn test_read_write_widths() {
+        let mut buf = vec![];
+        write::zigzag(&mut buf, i8::MIN).unwrap();
+        write::zigzag(&mut buf, i16::MAX).unwrap();
+        write::zigzag(&mut buf, -1i32).unwrap();
+        write::zigzag(&mut buf, i64::MIN).unwrap();
+        write::zigzag(&mut buf, 64isize).unwrap();
+
+        let mut readable = &buf[..];
+        assert_eq!(read::zigzag::<_, i8>(&mut readable).unwrap(), i8::MIN);
+        assert_eq!(read::zigzag::<_, i16>(&mut readable).unwrap(), i16::MAX);
+        assert_eq!(read::zigzag::<_, i32>(&mut readable).unwrap(), -1);
+        assert_eq!(read::zigzag::<_, i64>(&mut readable).unwrap(), i64::MIN);
+        assert_eq!(read::zigzag::<_, isize>(&mut readable).unwrap(), 64);
+        assert!(readable.is_empty());
+    }
+
+    #[test]
+    fn test_read_narrow_overflow() {
+        let mut buf = vec![];
+        write::zigzag(&mut buf, 128i64).unwrap();
+        match read::zigzag::<_, i8>(&mut &buf[..]) {
+            Err(read::Error::Overflow) => {}
+            otherwise => panic!("Unexpected: {:?}", otherwise),
+        }
+        assert_eq!(read::zigzag::<_, i16>(&mut &buf[..]).unwrap(), 128);
+    }
+}
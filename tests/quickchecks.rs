@@ -19,7 +19,7 @@ fn can_round_trip_any_unsigned_int() {
         let mut v = vec![];
         leb128::write::unsigned(&mut v, x)?;
         let y = leb128::read::unsigned(&mut &v[..])
-            .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+            .map_err(io::Error::other)?;
         Ok(x == y)
     }
     quickcheck::quickcheck(f as fn(u64) -> io::Result<bool>);
@@ -41,8 +41,166 @@ fn can_round_trip_any_signed_int() {
         let mut v = vec![];
         leb128::write::signed(&mut v, x)?;
         let y = leb128::read::signed(&mut &v[..])
-            .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+            .map_err(io::Error::other)?;
         Ok(x == y)
     }
     quickcheck::quickcheck(f as fn(i64) -> io::Result<bool>);
 }
+
+#[test]
+fn peek_matches_write() {
+    fn f(x: i64) -> io::Result<bool> {
+        let mut v = vec![];
+        let n = leb128::write::signed(&mut v, x)?;
+        let peeked = leb128::read::peek_signed(&v).map_err(io::Error::other)?;
+        let mut u = vec![];
+        let m = leb128::write::unsigned(&mut u, x as u64)?;
+        let upeeked = leb128::read::peek_unsigned(&u).map_err(io::Error::other)?;
+        Ok(peeked == (x, n) && upeeked == (x as u64, m))
+    }
+    quickcheck::quickcheck(f as fn(i64) -> io::Result<bool>);
+}
+
+#[test]
+fn can_round_trip_any_string() {
+    fn f(x: String) -> io::Result<bool> {
+        let mut v = vec![];
+        leb128::write::str(&mut v, &x)?;
+        let y = leb128::read::string(&mut &v[..], x.len()).map_err(io::Error::other)?;
+        let (z, len) = leb128::read::peek_string(&v, x.len()).map_err(io::Error::other)?;
+        Ok(x == y && x == z && len == v.len())
+    }
+    quickcheck::quickcheck(f as fn(String) -> io::Result<bool>);
+}
+
+#[test]
+fn can_round_trip_any_zigzag_int() {
+    fn f(x: i64) -> io::Result<bool> {
+        let mut v = vec![];
+        leb128::write::zigzag(&mut v, x)?;
+        let y: i64 = leb128::read::zigzag(&mut &v[..]).map_err(io::Error::other)?;
+        let z = leb128::zigzag::zigzag_decode(leb128::zigzag::zigzag_encode(x));
+        Ok(x == y && x == z)
+    }
+    quickcheck::quickcheck(f as fn(i64) -> io::Result<bool>);
+    for &x in &[i64::MIN, i64::MIN + 1, -1, 0, 1, i64::MAX] {
+        assert!(f(x).unwrap());
+    }
+}
+
+#[test]
+fn can_round_trip_any_zigzag_i32() {
+    fn f(x: i32) -> io::Result<bool> {
+        let mut v = vec![];
+        leb128::write::zigzag(&mut v, x)?;
+        let y: i32 = leb128::read::zigzag(&mut &v[..]).map_err(io::Error::other)?;
+        Ok(x == y && v.len() <= 5)
+    }
+    quickcheck::quickcheck(f as fn(i32) -> io::Result<bool>);
+    assert!(f(i32::MIN).unwrap());
+}
+
+#[test]
+fn zigzag_is_a_bijection() {
+    fn f(x: u64) -> bool {
+        leb128::zigzag::zigzag_encode(leb128::zigzag::zigzag_decode(x)) == x
+    }
+    quickcheck::quickcheck(f as fn(u64) -> bool);
+}
+
+#[test]
+fn can_round_trip_any_vlq() {
+    fn f(x: u64) -> io::Result<bool> {
+        let mut v = vec![];
+        let n = leb128::vlq::write(&mut v, x)?;
+        let y = leb128::vlq::read_strict(&mut &v[..]).map_err(io::Error::other)?;
+        Ok(x == y && n == v.len() && n == leb128::vlq::encoded_len(x))
+    }
+    quickcheck::quickcheck(f as fn(u64) -> io::Result<bool>);
+}
+
+#[test]
+fn can_round_trip_any_git_offset() {
+    fn f(x: u64) -> io::Result<bool> {
+        let mut v = vec![];
+        let n = leb128::git::write_offset(&mut v, x)?;
+        let y = leb128::git::read_offset(&mut &v[..]).map_err(io::Error::other)?;
+        Ok(x == y && n == v.len() && n == leb128::git::offset_len(x))
+    }
+    quickcheck::quickcheck(f as fn(u64) -> io::Result<bool>);
+}
+
+#[test]
+fn can_round_trip_any_sqlite_varint() {
+    fn f(x: u64) -> io::Result<bool> {
+        let mut v = vec![];
+        let n = leb128::sqlite::write_varint(&mut v, x)?;
+        let (y, m) = leb128::sqlite::read_varint(&v).map_err(io::Error::other)?;
+        Ok(x == y && n == m && n == v.len() && n == leb128::sqlite::varint_len(x))
+    }
+    quickcheck::quickcheck(f as fn(u64) -> io::Result<bool>);
+}
+
+#[test]
+fn codec_presets_match_dedicated_writers() {
+    use leb128::codec::Codec;
+
+    fn f(x: u64) -> io::Result<bool> {
+        let (mut a, mut b, mut c) = (vec![], vec![], vec![]);
+        leb128::write::unsigned(&mut a, x)?;
+        leb128::vlq::write(&mut b, x)?;
+        leb128::git::write_offset(&mut c, x)?;
+        let (mut a2, mut b2, mut c2) = (vec![], vec![], vec![]);
+        Codec::LEB128.write(&mut a2, x)?;
+        Codec::VLQ.write(&mut b2, x)?;
+        Codec::GIT_OFFSET.write(&mut c2, x)?;
+        Ok(a == a2 && b == b2 && c == c2)
+    }
+    quickcheck::quickcheck(f as fn(u64) -> io::Result<bool>);
+}
+
+#[test]
+fn codec_presets_match_dedicated_readers() {
+    use leb128::codec::Codec;
+
+    fn same<E, F>(x: Result<(u64, usize), E>, y: Result<(u64, usize), F>) -> bool {
+        match (x, y) {
+            (Ok(x), Ok(y)) => x == y,
+            (Err(_), Err(_)) => true,
+            _ => false,
+        }
+    }
+
+    fn f(buf: Vec<u8>) -> bool {
+        same(
+            leb128::read::peek_unsigned(&buf),
+            Codec::LEB128.decode(&buf),
+        ) && same(leb128::vlq::peek(&buf), Codec::VLQ.decode(&buf))
+            && same(
+                leb128::git::peek_offset(&buf),
+                Codec::GIT_OFFSET.decode(&buf),
+            )
+    }
+    quickcheck::quickcheck(f as fn(Vec<u8>) -> bool);
+}
+
+#[test]
+fn can_round_trip_any_packed_relocations() {
+    use leb128::aps2::{self, Relocation};
+
+    fn f(fields: Vec<(u64, u64, i64)>) -> io::Result<bool> {
+        let relocs: Vec<Relocation> = fields
+            .into_iter()
+            .map(|(offset, info, addend)| Relocation {
+                offset,
+                info,
+                addend,
+            })
+            .collect();
+        let mut v = vec![];
+        let n = aps2::write(&mut v, &relocs)?;
+        let decoded = aps2::read(&mut &v[..], relocs.len()).map_err(io::Error::other)?;
+        Ok(decoded == relocs && n == v.len())
+    }
+    quickcheck::quickcheck(f as fn(Vec<(u64, u64, i64)>) -> io::Result<bool>);
+}